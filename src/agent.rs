@@ -0,0 +1,325 @@
+use crate::util::secure::{SecretBytes, SecretString};
+use std::env;
+use std::fs::{self, DirBuilder, File, Permissions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type AgentResult<T> = Result<T, AgentError>;
+
+/// Interval at which the agent checks for new connections and its timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time after which an unresponsive client is disconnected.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Request for the key held by the agent.
+const GET_REQUEST: &[u8] = b"get";
+
+/// Request for the agent to wipe the key and stop.
+const LOCK_REQUEST: &[u8] = b"lock";
+
+/// Line written by the agent process once it is ready to serve.
+const READY_LINE: &str = "ready\n";
+
+/// Listener of the agent socket, along with the lock file which is held
+/// for as long as the agent listens, so that only one agent runs at a time.
+struct AgentListener {
+    listener: UnixListener,
+    lock_file: File,
+}
+
+/// Makes the directory of the socket, which only the current user can
+/// enter, so that others can never connect to the socket inside it.
+fn create_private_dir(socket_path: &Path) -> io::Result<()> {
+    let socket_dir = socket_path.parent().unwrap();
+    DirBuilder::new().recursive(true).mode(0o700).create(socket_dir)?;
+    fs::set_permissions(socket_dir, Permissions::from_mode(0o700))
+}
+
+/// Binds the agent socket at the given path, inside its private directory.
+/// Any stale socket left behind by an agent which is no longer running is
+/// replaced, which is safe only while holding the lock file.
+/// - If an agent is already running, returns `AlreadyRunning`.
+/// - If the socket cannot be bound, returns `StartFailed`.
+fn bind(socket_path: &Path) -> AgentResult<AgentListener> {
+    create_private_dir(socket_path).map_err(|_| AgentError::StartFailed)?;
+    let lock_file = File::create(socket_path.with_extension("lock"))
+        .map_err(|_| AgentError::StartFailed)?;
+    let fd = lock_file.as_raw_fd();
+    if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        return Err(AgentError::AlreadyRunning);
+    }
+    fs::remove_file(socket_path).unwrap_or_default();
+    let listener = UnixListener::bind(socket_path)
+        .map_err(|_| AgentError::StartFailed)?;
+    fs::set_permissions(socket_path, Permissions::from_mode(0o600))
+        .map_err(|_| AgentError::StartFailed)?;
+    Ok(AgentListener { listener, lock_file })
+}
+
+/// Reads the request of a client, which closes its writing end after it.
+fn read_request(stream: &mut UnixStream) -> Vec<u8> {
+    let mut request = Vec::new();
+    stream.set_nonblocking(false).unwrap_or_default();
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap_or_default();
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT)).unwrap_or_default();
+    let max_len = LOCK_REQUEST.len() as u64;
+    stream.take(max_len).read_to_end(&mut request).unwrap_or_default();
+    request
+}
+
+/// Serves the key to the clients connecting to the listener, until either
+/// the vault is locked or no client connects within the idle timeout.
+/// The socket is removed and the key is wiped on return.
+fn serve(
+    listener: AgentListener,
+    socket_path: &Path,
+    key: SecretString,
+    timeout: Duration,
+) {
+    let AgentListener { listener, lock_file } = listener;
+    listener.set_nonblocking(true).unwrap();
+    let mut last_used = Instant::now();
+    let lock_stream = loop {
+        if last_used.elapsed() >= timeout {
+            break None;
+        }
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(_) => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        last_used = Instant::now();
+        match read_request(&mut stream).as_slice() {
+            GET_REQUEST => stream
+                .write_all(key.as_bytes())
+                .unwrap_or_default(),
+            LOCK_REQUEST => break Some(stream),
+            _ => {}
+        }
+    };
+    drop(listener);
+    fs::remove_file(socket_path).unwrap_or_default();
+    drop(lock_file);
+    drop(key);
+    // the client is only answered once the agent is fully stopped, so that
+    // the vault is locked by the time it returns
+    if let Some(mut stream) = lock_stream {
+        stream.write_all(b"ok").unwrap_or_default();
+    }
+}
+
+/// Sends the request to the agent listening on the given socket.
+/// Returns the stream to read the response from.
+/// - If no agent is running, returns `None`.
+fn send_request(socket_path: &Path, request: &[u8]) -> Option<UnixStream> {
+    let mut stream = UnixStream::connect(socket_path).ok()?;
+    stream.write_all(request).ok()?;
+    stream.shutdown(Shutdown::Write).ok()?;
+    Some(stream)
+}
+
+/// Requests the key from the agent listening on the given socket.
+/// - If no agent is running, returns `None`.
+pub fn request_key(socket_path: &Path) -> Option<SecretString> {
+    let mut stream = send_request(socket_path, GET_REQUEST)?;
+    let mut key = SecretBytes::new();
+    let mut chunk = SecretBytes::zeroed(256);
+    loop {
+        match stream.read(&mut chunk).ok()? {
+            0 => break,
+            len => key.extend_from_slice(&chunk[..len]),
+        }
+    }
+    match key.is_empty() {
+        true => None,
+        false => SecretString::from_utf8(key),
+    }
+}
+
+/// Asks the agent listening on the given socket to wipe the key and stop.
+/// Waits until the agent has stopped.
+/// - If no agent is running, returns `NotRunning`.
+pub fn lock(socket_path: &Path) -> AgentResult<()> {
+    let mut stream = send_request(socket_path, LOCK_REQUEST)
+        .ok_or(AgentError::NotRunning)?;
+    stream.read_to_end(&mut Vec::new()).unwrap_or_default();
+    Ok(())
+}
+
+/// Starts the agent in a background process, which holds the given key
+/// on the given socket until the idle timeout.
+/// The process runs the given command of the current executable, which is
+/// expected to call `run` with the same socket and timeout.
+/// - If an agent is already running, returns `AlreadyRunning`.
+/// - If the process fails to start serving, returns `StartFailed`.
+// the agent is meant to outlive the current process, so it is not waited on
+#[allow(clippy::zombie_processes)]
+pub fn start(socket_path: &Path, key: &SecretString, command: &[&str])
+    -> AgentResult<()> {
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(AgentError::AlreadyRunning);
+    }
+    let exec_path = env::current_exe().map_err(|_| AgentError::StartFailed)?;
+    let mut child = Command::new(exec_path)
+        .args(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|_| AgentError::StartFailed)?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(key.as_bytes()).unwrap_or_default();
+    drop(stdin);
+    let mut line = String::new();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    stdout.read_line(&mut line).unwrap_or_default();
+    match line == READY_LINE {
+        true => Ok(()),
+        false => Err(AgentError::StartFailed),
+    }
+}
+
+/// Runs the agent in the current process, reading the key from stdin.
+/// The process is detached from the terminal, and signals through stdout
+/// once it is ready to serve. Returns once the agent has stopped.
+/// - If an agent is already running, returns `AlreadyRunning`.
+/// - If the key is malformed or the socket cannot be bound,
+///   returns `StartFailed`.
+pub fn run(socket_path: &Path, timeout: Duration) -> AgentResult<()> {
+    let mut key = SecretBytes::new();
+    let mut chunk = SecretBytes::zeroed(256);
+    let mut stdin = io::stdin();
+    loop {
+        match stdin.read(&mut chunk).unwrap() {
+            0 => break,
+            len => key.extend_from_slice(&chunk[..len]),
+        }
+    }
+    let key = SecretString::from_utf8(key).ok_or(AgentError::StartFailed)?;
+    let listener = bind(socket_path)?;
+    unsafe { libc::setsid() };
+    let mut stdout = io::stdout();
+    stdout.write_all(READY_LINE.as_bytes()).unwrap();
+    stdout.flush().unwrap();
+    serve(listener, socket_path, key, timeout);
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AgentError {
+    AlreadyRunning,
+    NotRunning,
+    StartFailed,
+}
+
+#[cfg(test)]
+mod test {
+    use super::AgentError;
+    use crate::util::secure::SecretString;
+    use once_cell::sync::Lazy;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::panic;
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    const AGENT_DIR: &str = "agent-test-dir";
+    static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
+        let lock = DIR_LOCK.lock().unwrap();
+        fs::create_dir_all(AGENT_DIR).unwrap();
+        let result = panic::catch_unwind(test);
+        fs::remove_dir_all(AGENT_DIR).unwrap();
+        drop(lock);
+        assert!(result.is_ok())
+    }
+
+    fn spawn_agent(socket_path: &'static Path, timeout: Duration)
+        -> thread::JoinHandle<()> {
+        let listener = super::bind(socket_path).unwrap();
+        let key = SecretString::from("0000key");
+        thread::spawn(move || {
+            super::serve(listener, socket_path, key, timeout)
+        })
+    }
+
+    #[test]
+    fn should_serve_key_until_locked() {
+        run_test(|| {
+            let socket_path = Path::new("agent-test-dir/agent.sock");
+            let timeout = Duration::from_secs(10);
+            let handle = spawn_agent(socket_path, timeout);
+            let mode = fs::metadata(socket_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let mode = fs::metadata(AGENT_DIR).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+            let key = super::request_key(socket_path).unwrap();
+            assert_eq!(key.as_str(), "0000key");
+            let error = Err(AgentError::AlreadyRunning);
+            assert_eq!(super::bind(socket_path).map(|_| ()), error);
+            assert_eq!(super::lock(socket_path), Ok(()));
+            handle.join().unwrap();
+            assert!(!socket_path.exists());
+            assert!(super::request_key(socket_path).is_none());
+            let error = Err(AgentError::NotRunning);
+            assert_eq!(super::lock(socket_path), error);
+        })
+    }
+
+    #[test]
+    fn should_not_replace_socket_of_running_agent() {
+        run_test(|| {
+            let socket_path = Path::new("agent-test-dir/agent.sock");
+            let handle = spawn_agent(socket_path, Duration::from_secs(1));
+            // the lock is held by the agent, even once its socket is gone
+            fs::remove_file(socket_path).unwrap();
+            let error = Err(AgentError::AlreadyRunning);
+            assert_eq!(super::bind(socket_path).map(|_| ()), error);
+            handle.join().unwrap();
+            assert!(super::bind(socket_path).is_ok());
+        })
+    }
+
+    #[test]
+    fn should_stop_after_idle_timeout() {
+        run_test(|| {
+            let socket_path = Path::new("agent-test-dir/agent.sock");
+            let timeout = Duration::from_millis(2000);
+            let handle = spawn_agent(socket_path, timeout);
+            thread::sleep(Duration::from_millis(1000));
+            assert!(super::request_key(socket_path).is_some());
+            thread::sleep(Duration::from_millis(1000));
+            assert!(super::request_key(socket_path).is_some());
+            handle.join().unwrap();
+            assert!(super::request_key(socket_path).is_none());
+        })
+    }
+
+    #[test]
+    fn should_replace_stale_socket() {
+        run_test(|| {
+            let socket_path = Path::new("agent-test-dir/agent.sock");
+            drop(super::bind(socket_path).unwrap());
+            assert!(socket_path.exists());
+            assert!(super::request_key(socket_path).is_none());
+            let handle = spawn_agent(socket_path, Duration::from_secs(10));
+            assert!(super::request_key(socket_path).is_some());
+            super::lock(socket_path).unwrap();
+            handle.join().unwrap();
+        })
+    }
+}
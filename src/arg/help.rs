@@ -52,7 +52,7 @@ impl HelpGenerator {
                 if i > 0 {
                     result.push('\n');
                 }
-                if name == &SECTION_DEF {
+                if name == SECTION_DEF {
                     result.push_str(value);
                     result.push(':');
                 } else if name.is_empty() {
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
 type ParserResult<T> = Result<T, ParserError>;
 
@@ -65,6 +66,18 @@ impl ParsedArgs {
             .map(|x| x.as_str())
     }
 
+    /// Parses the argument value for the given key, if it exists.
+    /// Returns `ParserError` if the value cannot be parsed.
+    pub fn parse_value<T>(&self, key: &str) -> ParserResult<Option<T>>
+    where T: FromStr {
+        self.get_value(key)
+            .map(|value| value
+                .parse::<T>()
+                .map_err(|_| ParserError::invalid_value(key))
+            )
+            .transpose()
+    }
+
     /// Gets the argument value for the given key, if it exists.
     /// Returns `ParserError` otherwise.
     pub fn expect_index(&self, index: u16, key: &str) -> ParserResult<&str> {
@@ -72,6 +85,14 @@ impl ParsedArgs {
             .ok_or_else(|| ParserError::missing_value(key))
     }
 
+    /// Gets the argument value for the given key, if it is non-empty.
+    /// Returns `ParserError` otherwise.
+    pub fn expect_value(&self, key: &str) -> ParserResult<&str> {
+        self.get_value(key)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| ParserError::missing_value(key))
+    }
+
     /// Ensures there are no unexpected indices.
     /// In case unexpected keys are found, returns `ParserError`.
     pub fn expect_no_index_over(&self, index: u16) -> ParserResult<()> {
@@ -168,6 +189,25 @@ mod test {
         assert_eq!(args.get_value("forc"), None);
     }
 
+    #[test]
+    fn should_parse_arg_values() {
+        let command = "vlt zip --keep 3 --days x";
+        let args = super::ParsedArgs::from_iter(command.split(' '));
+        assert_eq!(args.parse_value::<u32>("keep"), Ok(Some(3)));
+        assert_eq!(args.parse_value::<u32>("none"), Ok(None));
+        let error = Err(super::ParserError::invalid_value("days"));
+        assert_eq!(args.parse_value::<u32>("days"), error);
+    }
+
+    #[test]
+    fn should_expect_non_empty_values() {
+        let command = "vlt repair --attach 003.vlt --path";
+        let args = super::ParsedArgs::from_iter(command.split(' '));
+        assert_eq!(args.expect_value("attach"), Ok("003.vlt"));
+        let error = Err(super::ParserError::missing_value("path"));
+        assert_eq!(args.expect_value("path"), error);
+    }
+
     #[test]
     fn should_throw_error_for_unrecognized_args() {
         let command = "vlt get --force --key val";
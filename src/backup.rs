@@ -0,0 +1,889 @@
+use crate::config;
+use crate::constant::EXEC_PATH;
+use crate::crc::{self, CrcMap, CrcMismatchError};
+use crate::crypto::{self, CryptoError};
+use crate::manifest::{self, ManifestError};
+use crate::secret::{self, IndexMap};
+use crate::util::ext::{PathExt, VecExt};
+use crate::util::secure::SecretString;
+use crate::util::serde::Deserialize;
+use crate::util::zip::{self, Unzipper, Zipper, ZipperOptions};
+use chrono::offset::Local;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+type BackupResult<T> = Result<T, BackupError>;
+
+/// Path to the manifest within incremental zip backups.
+/// The manifest contains the file name of the parent backup.
+const MANIFEST_PATH: &str = "manifest";
+
+/// Retention policy applied to the zip backups after creating a new one.
+/// The latest backup is always kept, regardless of the policy.
+#[derive(Default)]
+pub struct RetentionPolicy {
+    /// Maximum number of backups to keep.
+    pub keep_count: Option<usize>,
+    /// Maximum age in days of the backups to keep.
+    pub keep_days: Option<u64>,
+}
+
+/// Details of a single zip backup in the backup directory.
+pub struct BackupInfo {
+    pub name: String,
+    pub size: u64,
+    pub encrypted: bool,
+    pub verified: bool,
+}
+
+/// Returns the path to a new zip backup in the given backup directory.
+/// The name is time-stamped and does not clash with existing backups.
+fn get_new_backup_path(backup_dir: &Path) -> PathBuf {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+    (1..)
+        .map(|i| match i {
+            1 => format!("vault-{}.zip", timestamp),
+            _ => format!("vault-{}_{}.zip", timestamp, i),
+        })
+        .map(|name| backup_dir.join(name))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Returns the path to the directory where the backup is unpacked
+/// before replacing the given lock directory.
+#[inline]
+fn get_stage_dir_path(lock_dir: &Path) -> PathBuf {
+    lock_dir.with_extension("stage")
+}
+
+/// Returns the path to the directory where the current contents of
+/// the given lock directory are kept after a restore.
+#[inline]
+fn get_prev_dir_path(lock_dir: &Path) -> PathBuf {
+    let timestamp = Local::now().format("%Y%m%d%H%M%S");
+    lock_dir.with_extension(format!("{}.bak", timestamp))
+}
+
+/// Lists all the zip backups in the given backup directory.
+/// The backups are sorted from the oldest to the latest.
+/// - If the directory does not exist, returns empty list.
+fn list_backup_paths(backup_dir: &Path) -> Vec<PathBuf> {
+    if let Ok(dir_entries) = fs::read_dir(backup_dir) {
+        dir_entries
+            .filter_map(|entry| {
+                let entry_path = entry.unwrap().path();
+                let file_name = entry_path.to_filename_str();
+                let is_match = file_name.starts_with("vault-")
+                    && file_name.ends_with(".zip");
+                match is_match && entry_path.is_file() {
+                    true => {
+                        let meta = fs::metadata(&entry_path).unwrap();
+                        Some((meta.modified().unwrap(), entry_path))
+                    }
+                    false => None,
+                }
+            })
+            .collect::<Vec<_>>()
+            .into_sorted()
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Opens the given zip backup for reading.
+/// Encrypted backups are decrypted using the given backup password.
+/// - If the archive is unreadable, returns `InvalidArchive`.
+/// - If the backup password is incorrect, returns `IncorrectBackupPassword`.
+fn open_backup(
+    zip_path: &Path,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<Unzipper> {
+    match (zip::is_encrypted(zip_path), backup_pass) {
+        (true, Some(pass)) => Unzipper::with_password(zip_path, pass)
+            .ok_or(BackupError::IncorrectBackupPassword),
+        (true, None) => Err(BackupError::IncorrectBackupPassword),
+        (false, _) => Unzipper::new(zip_path)
+            .ok_or(BackupError::InvalidArchive),
+    }
+}
+
+/// Reads the crc map archived within the given zip backup.
+/// - If the archive is unreadable or has no crc map, returns `None`.
+fn read_backup_crc(
+    zip_path: &Path,
+    lock_dir: &Path,
+    backup_pass: Option<&SecretString>,
+) -> Option<CrcMap> {
+    let mut unzipper = open_backup(zip_path, backup_pass).ok()?;
+    let contents = unzipper.read_file(lock_dir.join("index.crc"))?;
+    CrcMap::deserialize(&String::from_utf8(contents).ok()?)
+}
+
+/// Resolves the chain of zip backups needed to rebuild the given backup.
+/// The chain starts at the full backup and ends at the given backup.
+/// - If any backup in the chain is unreadable, returns `BrokenChain`.
+/// - If a parent is not named as a file next to the given backup, returns
+///   `BrokenChain`.
+fn resolve_backup_chain(
+    zip_path: &Path,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<Vec<PathBuf>> {
+    let mut chain = vec![zip_path.to_path_buf()];
+    loop {
+        let current_path = chain.last().unwrap();
+        let broken_chain = || BackupError::BrokenChain {
+            file_path: current_path.to_path_str().to_owned(),
+        };
+        let mut unzipper = open_backup(current_path, backup_pass)
+            .map_err(|_| broken_chain())?;
+        let parent_name = match unzipper.read_file(MANIFEST_PATH) {
+            Some(contents) => String::from_utf8(contents)
+                .map_err(|_| broken_chain())?,
+            None => break,
+        };
+        let parent_name = parent_name.trim();
+        let is_file_name = !parent_name.is_empty()
+            && !parent_name.contains(&['/', '\\'][..])
+            && !parent_name.contains("..");
+        if !is_file_name {
+            return Err(broken_chain());
+        }
+        let parent_path = zip_path.with_file_name(parent_name);
+        if chain.contains(&parent_path) {
+            return Err(broken_chain());
+        }
+        chain.push(parent_path);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Removes all the files in the given directory which are neither tracked
+/// by its crc map nor in the given list of file names.
+/// These are left behind by the older backups in a chain.
+fn remove_untracked_files(root_dir: &Path, file_names: &[String]) {
+    let crc_map = crc::read_crc_file(root_dir);
+    for entry in fs::read_dir(root_dir).unwrap() {
+        let entry_path = entry.unwrap().path();
+        let file_name = entry_path.to_filename_str().to_owned();
+        let is_tracked = file_name == "index.crc"
+            || crc_map.contains_key(&file_name)
+            || file_names.contains(&file_name);
+        if !is_tracked {
+            fs::remove_file(&entry_path).unwrap();
+        }
+    }
+}
+
+/// Unpacks the lock directory from the given zip backup into the stage
+/// directory, and verifies the checksums of the unpacked files.
+/// Incremental backups are rebuilt by unpacking the whole chain in order.
+/// - If the archive is unreadable or empty, returns `InvalidArchive`.
+/// - If the backup password is incorrect, returns `IncorrectBackupPassword`.
+/// - If any backup in the chain is unreadable, returns `BrokenChain`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+fn unpack_backup(
+    zip_path: &Path,
+    lock_dir: &Path,
+    stage_dir: &Path,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<()> {
+    open_backup(zip_path, backup_pass)?;
+    let chain = resolve_backup_chain(zip_path, backup_pass)?;
+    fs::remove_dir_all(stage_dir).unwrap_or_default();
+    let mut matches = Vec::new();
+    for chain_path in chain.iter() {
+        let mut unzipper = open_backup(chain_path, backup_pass).unwrap();
+        matches = unzipper.unzip_dir(lock_dir, stage_dir);
+    }
+    if !stage_dir.exists() {
+        return Err(BackupError::InvalidArchive);
+    }
+    remove_untracked_files(stage_dir, &matches);
+    if let Err(err) = crc::check_crc_all(stage_dir).into_result() {
+        fs::remove_dir_all(stage_dir).unwrap_or_default();
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Verifies the unpacked backup in the given stage directory.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If there is no index file, returns `InvalidArchive`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+fn verify_stage_dir(
+    stage_dir: &Path,
+    pass: &SecretString,
+) -> BackupResult<()> {
+    let index_file_path = stage_dir.join("index.vlt");
+    let index_ad = secret::get_index_file_ad(&config::read_config(stage_dir));
+    let index = crypto::read_file_versioned::<_, IndexMap>(
+        index_file_path,
+        pass,
+        &index_ad,
+    )?;
+    match index {
+        Some((index_format, _)) => {
+            manifest::check_restored_manifest(stage_dir, pass, index_format)?;
+            Ok(())
+        }
+        None => Err(BackupError::InvalidArchive),
+    }
+}
+
+/// Packs the lock directory into a new zip backup in the backup directory.
+/// Returns the path to the created zip backup.
+/// - If the backup directory does not exist, new one is created.
+/// - If the backup password is empty, returns `IncorrectBackupPassword`.
+pub fn create_backup<P, Q>(
+    lock_dir: P,
+    backup_dir: Q,
+    options: ZipperOptions,
+) -> BackupResult<PathBuf>
+where P: AsRef<Path>, Q: AsRef<Path> {
+    fs::create_dir_all(&backup_dir).unwrap();
+    let zip_path = get_new_backup_path(backup_dir.as_ref());
+    let mut zipper = Zipper::new(&zip_path, options);
+    zipper.zip_dir(lock_dir);
+    zipper.zip_file(EXEC_PATH);
+    zipper.finish()
+        .map_err(|_| BackupError::IncorrectBackupPassword)?;
+    Ok(zip_path)
+}
+
+/// Packs the files in the lock directory changed since the latest zip
+/// backup in the backup directory into a new incremental zip backup.
+/// Returns the path to the created zip backup.
+/// - If there is no readable backup to compare with, creates a full backup.
+/// - If the backup password is empty, returns `IncorrectBackupPassword`.
+pub fn create_incremental_backup<P, Q>(
+    lock_dir: P,
+    backup_dir: Q,
+    options: ZipperOptions,
+) -> BackupResult<PathBuf>
+where P: AsRef<Path>, Q: AsRef<Path> {
+    let lock_dir = lock_dir.as_ref();
+    let backup_pass = options.pass.as_ref();
+    let parent_path = list_backup_paths(backup_dir.as_ref()).pop();
+    let parent_crc = parent_path
+        .as_ref()
+        .and_then(|path| read_backup_crc(path, lock_dir, backup_pass));
+    let (parent_path, parent_crc) = match (parent_path, parent_crc) {
+        (Some(path), Some(crc)) => (path, crc),
+        _ => return create_backup(lock_dir, backup_dir, options),
+    };
+    let zip_path = get_new_backup_path(backup_dir.as_ref());
+    let mut zipper = Zipper::new(&zip_path, options);
+    crc::read_crc_file(lock_dir)
+        .into_iter()
+        .filter(|(name, crc)| parent_crc.get(name) != Some(crc))
+        .for_each(|(name, _)| {
+            zipper.zip_file(lock_dir.join(name));
+        });
+    zipper.zip_file(lock_dir.join("index.crc"));
+    let parent_name = parent_path.to_filename_str();
+    zipper.zip_contents(MANIFEST_PATH, parent_name.as_bytes());
+    zipper.finish()
+        .map_err(|_| BackupError::IncorrectBackupPassword)?;
+    Ok(zip_path)
+}
+
+/// Removes the zip backups in the backup directory not allowed by the
+/// given retention policy. Returns the paths of the removed backups.
+/// - Backups needed to rebuild the kept incremental backups are kept.
+/// - If any kept backup cannot be resolved, no backup is removed.
+/// - If a backup cannot be removed, returns `RemoveFailed`.
+pub fn prune_backups<P>(
+    backup_dir: P,
+    policy: &RetentionPolicy,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<Vec<String>>
+where P: AsRef<Path> {
+    let backup_paths = list_backup_paths(backup_dir.as_ref());
+    let total = backup_paths.len();
+    let keep_count = policy.keep_count.unwrap_or(total).max(1);
+    let min_time = policy.keep_days.map(|days| {
+        let age = Duration::from_secs(days * 24 * 60 * 60);
+        SystemTime::now() - age
+    });
+    let (removed, kept): (Vec<_>, Vec<_>) = backup_paths
+        .into_iter()
+        .enumerate()
+        .partition(|(i, path)| {
+            let is_latest = i + 1 == total;
+            let is_extra = i + keep_count < total;
+            let is_expired = min_time.map_or(false, |min_time| {
+                let meta = fs::metadata(path).unwrap();
+                meta.modified().unwrap() < min_time
+            });
+            !is_latest && (is_extra || is_expired)
+        });
+    let needed = kept
+        .into_iter()
+        .map(|(_, path)| resolve_backup_chain(&path, backup_pass))
+        .collect::<BackupResult<Vec<_>>>();
+    let needed = match needed {
+        Ok(chains) => chains.into_iter().flatten().collect::<HashSet<_>>(),
+        Err(_) => return Ok(Vec::new()),
+    };
+    removed
+        .into_iter()
+        .filter(|(_, path)| !needed.contains(path))
+        .map(|(_, path)| {
+            let file_path = path.to_path_str().to_owned();
+            match fs::remove_file(&path) {
+                Ok(()) => Ok(file_path),
+                Err(_) => Err(BackupError::RemoveFailed { file_path }),
+            }
+        })
+        .collect()
+}
+
+/// Lists all the zip backups in the given backup directory, along with
+/// their sizes and whether their checksums are intact.
+/// The backups are sorted from the oldest to the latest.
+/// - Encrypted backups are verified only if the backup password is given.
+pub fn list_backups<P, Q>(
+    lock_dir: P,
+    backup_dir: Q,
+    backup_pass: Option<&SecretString>,
+) -> Vec<BackupInfo>
+where P: AsRef<Path>, Q: AsRef<Path> {
+    let lock_dir = lock_dir.as_ref();
+    list_backup_paths(backup_dir.as_ref())
+        .into_iter()
+        .map(|zip_path| {
+            let stage_dir = zip_path.with_extension("stage");
+            let result =
+                unpack_backup(&zip_path, lock_dir, &stage_dir, backup_pass);
+            fs::remove_dir_all(&stage_dir).unwrap_or_default();
+            BackupInfo {
+                name: zip_path.to_filename_str().to_owned(),
+                size: fs::metadata(&zip_path).unwrap().len(),
+                encrypted: zip::is_encrypted(&zip_path),
+                verified: result.is_ok(),
+            }
+        })
+        .collect()
+}
+
+/// Restores the lock directory from the given zip backup.
+/// The backup is unpacked into a stage directory and verified first,
+/// and only then swapped with the lock directory.
+/// Returns the path where the previous lock directory is kept, if any.
+/// - If the archive is unreadable or empty, returns `InvalidArchive`.
+/// - If the backup password is incorrect, returns `IncorrectBackupPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the directories cannot be swapped, returns `RestoreFailed`, after
+///   putting back the previous lock directory.
+pub fn restore_backup<P, Q>(
+    zip_path: P,
+    lock_dir: Q,
+    pass: &SecretString,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<Option<PathBuf>>
+where P: AsRef<Path>, Q: AsRef<Path> {
+    let lock_dir = lock_dir.as_ref();
+    let stage_dir = get_stage_dir_path(lock_dir);
+    unpack_backup(zip_path.as_ref(), lock_dir, &stage_dir, backup_pass)?;
+    if let Err(err) = verify_stage_dir(&stage_dir, pass) {
+        fs::remove_dir_all(&stage_dir).unwrap_or_default();
+        return Err(err);
+    }
+    let restore_failed = |file_path: &Path| {
+        fs::remove_dir_all(&stage_dir).unwrap_or_default();
+        BackupError::RestoreFailed {
+            file_path: file_path.to_path_str().to_owned(),
+        }
+    };
+    let prev_dir = match lock_dir.exists() {
+        true => {
+            let prev_dir = get_prev_dir_path(lock_dir);
+            fs::rename(lock_dir, &prev_dir)
+                .map_err(|_| restore_failed(lock_dir))?;
+            Some(prev_dir)
+        }
+        false => None,
+    };
+    if fs::rename(&stage_dir, lock_dir).is_err() {
+        if let Some(prev_dir) = &prev_dir {
+            fs::rename(prev_dir, lock_dir).unwrap_or_default();
+        }
+        return Err(restore_failed(lock_dir));
+    }
+    manifest::update_manifest(lock_dir, pass);
+    Ok(prev_dir)
+}
+
+/// Reads the contents of the given file in the lock directory out of the
+/// given zip backup, following its chain back to the backup which last
+/// packed the file. The contents are checked against the backup crc map.
+/// - If the file is not in the backup, returns `NonExistentFile`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+pub fn read_backup_file<P, Q>(
+    zip_path: P,
+    lock_dir: Q,
+    file_name: &str,
+    backup_pass: Option<&SecretString>,
+) -> BackupResult<Vec<u8>>
+where P: AsRef<Path>, Q: AsRef<Path> {
+    let (zip_path, lock_dir) = (zip_path.as_ref(), lock_dir.as_ref());
+    let chain = resolve_backup_chain(zip_path, backup_pass)?;
+    let backup_crc = read_backup_crc(zip_path, lock_dir, backup_pass)
+        .ok_or(BackupError::InvalidArchive)?;
+    let non_existent_file = || BackupError::NonExistentFile {
+        file_path: file_name.to_owned(),
+    };
+    let stored_crc = backup_crc.get(file_name).ok_or_else(non_existent_file)?;
+    let contents = chain
+        .iter()
+        .rev()
+        .find_map(|chain_path| {
+            let mut unzipper = open_backup(chain_path, backup_pass).ok()?;
+            unzipper.read_file(lock_dir.join(file_name))
+        })
+        .ok_or_else(non_existent_file)?;
+    match crc::compute_crc_contents(&contents) == *stored_crc {
+        true => Ok(contents),
+        false => Err(BackupError::CrcMismatch {
+            file_path: file_name.to_owned(),
+        }),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BackupError {
+    BrokenChain { file_path: String },
+    CorruptContents,
+    CrcMismatch { file_path: String },
+    IncorrectBackupPassword,
+    IncorrectPassword,
+    InvalidArchive,
+    ManifestMismatch { error: ManifestError },
+    NonExistentFile { file_path: String },
+    RemoveFailed { file_path: String },
+    RestoreFailed { file_path: String },
+    UnsupportedFormat { format: u8, kdf: u8, cipher: u8 },
+}
+
+impl From<CrcMismatchError> for BackupError {
+    fn from(error: CrcMismatchError) -> Self {
+        Self::CrcMismatch { file_path: error.file_path }
+    }
+}
+
+impl From<ManifestError> for BackupError {
+    fn from(error: ManifestError) -> Self {
+        Self::ManifestMismatch { error }
+    }
+}
+
+impl From<CryptoError> for BackupError {
+    fn from(error: CryptoError) -> Self {
+        match error {
+            CryptoError::CorruptContents => Self::CorruptContents,
+            CryptoError::IncorrectPassword => Self::IncorrectPassword,
+            CryptoError::UnsupportedFormat { format, kdf, cipher } =>
+                Self::UnsupportedFormat { format, kdf, cipher },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crc;
+    use crate::crypto;
+    use crate::manifest::{self, ManifestError};
+    use crate::secret;
+    use crate::util::ext::{PathExt, VecExt};
+    use crate::util::secure::SecretString;
+    use crate::util::zip::{Unzipper, Zipper, ZipperOptions};
+    use once_cell::sync::Lazy;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::panic;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    const BACKUP_DIR: &str = "backup-test-dir";
+    static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
+        let lock = DIR_LOCK.lock().unwrap();
+        fs::create_dir_all(BACKUP_DIR).unwrap();
+        let result = panic::catch_unwind(test);
+        fs::remove_dir_all(BACKUP_DIR).unwrap();
+        drop(lock);
+        assert!(result.is_ok())
+    }
+
+    fn full_backup(lock_dir: &Path, backup_dir: &Path) -> PathBuf {
+        super::create_backup(lock_dir, backup_dir, Default::default())
+            .unwrap()
+    }
+
+    fn incremental_backup(lock_dir: &Path, backup_dir: &Path) -> PathBuf {
+        let options = Default::default();
+        super::create_incremental_backup(lock_dir, backup_dir, options)
+            .unwrap()
+    }
+
+    fn restore<P>(zip_path: P, lock_dir: &Path, pass: &SecretString)
+    -> super::BackupResult<Option<PathBuf>>
+    where P: AsRef<Path> {
+        super::restore_backup(zip_path, lock_dir, pass, None)
+    }
+
+    fn write_lock_dir(lock_dir: &Path, pass: &SecretString) {
+        let index_map = HashMap::from([("key".to_owned(), 1_u32)]);
+        let index_ad = secret::get_index_file_ad(&Default::default());
+        let index_path = lock_dir.join("index.vlt");
+        crypto::write_file(index_path, &index_map, pass, &index_ad).unwrap();
+        crypto::write_file(lock_dir.join("001.vlt"), "contents", pass, b"")
+            .unwrap();
+        crc::update_crc_all(lock_dir);
+        manifest::update_manifest(lock_dir, pass);
+    }
+
+    fn create_backup(lock_dir: &Path, zip_path: &Path, pass: &SecretString) {
+        write_lock_dir(lock_dir, pass);
+        let mut zipper = Zipper::new(zip_path, Default::default());
+        zipper.zip_dir(lock_dir);
+        zipper.finish().unwrap();
+    }
+
+    #[test]
+    fn should_restore_intact_backup() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let zip_path = Path::new(BACKUP_DIR).join("backup.zip");
+        run_test(|| {
+            create_backup(&lock_dir, &zip_path, pass);
+            fs::write(lock_dir.join("001.vlt"), "modified").unwrap();
+            let result = restore(&zip_path, &lock_dir, pass);
+            let prev_dir = result.unwrap().unwrap();
+            let prev_contents = fs::read(prev_dir.join("001.vlt")).unwrap();
+            assert_eq!(prev_contents, b"modified");
+            assert!(crc::check_crc_all(&lock_dir).is_intact());
+        })
+    }
+
+    #[test]
+    fn should_restore_older_signed_backup() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            manifest::update_manifest(&lock_dir, pass);
+            let zip_path = full_backup(&lock_dir, &backup_dir);
+            manifest::update_manifest(&lock_dir, pass);
+            assert!(restore(&zip_path, &lock_dir, pass).is_ok());
+            let format = crypto::FORMAT_VERSION;
+            let result = manifest::check_manifest(&lock_dir, pass, format);
+            assert_eq!(result, Ok(()));
+        })
+    }
+
+    #[test]
+    fn should_not_restore_substituted_signed_backup() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let zip_path = Path::new(BACKUP_DIR).join("backup.zip");
+        let error = Err(super::BackupError::ManifestMismatch {
+            error: ManifestError::HashMismatch {
+                file_name: "001.vlt".to_owned(),
+            },
+        });
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            manifest::update_manifest(&lock_dir, pass);
+            let file_path = lock_dir.join("001.vlt");
+            crypto::write_file(file_path, "other", pass, b"").unwrap();
+            crc::update_crc_all(&lock_dir);
+            let mut zipper = Zipper::new(&zip_path, Default::default());
+            zipper.zip_dir(&lock_dir);
+            zipper.finish().unwrap();
+            assert_eq!(restore(&zip_path, &lock_dir, pass), error);
+        })
+    }
+
+    #[test]
+    fn should_read_file_from_backup_chain() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let error = Err(super::BackupError::NonExistentFile {
+            file_path: "002.vlt".to_owned(),
+        });
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            let expected = fs::read(lock_dir.join("001.vlt")).unwrap();
+            full_backup(&lock_dir, &backup_dir);
+            fs::write(lock_dir.join("003.vlt"), "other").unwrap();
+            crc::update_crc_all(&lock_dir);
+            let zip_path = incremental_backup(&lock_dir, &backup_dir);
+            let read_file = |file_name| {
+                super::read_backup_file(&zip_path, &lock_dir, file_name, None)
+            };
+            assert_eq!(read_file("001.vlt"), Ok(expected));
+            assert_eq!(read_file("002.vlt"), error);
+        })
+    }
+
+    #[test]
+    fn should_not_restore_backup_with_incorrect_pass() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let zip_path = Path::new(BACKUP_DIR).join("backup.zip");
+        let error = Err(super::BackupError::IncorrectPassword);
+        run_test(|| {
+            create_backup(&lock_dir, &zip_path, pass);
+            let wrong_pass = &SecretString::from("4321");
+            let result = restore(&zip_path, &lock_dir, wrong_pass);
+            assert_eq!(result, error);
+            assert!(!lock_dir.with_extension("stage").exists());
+        })
+    }
+
+    #[test]
+    fn should_not_restore_corrupt_backup() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let zip_path = Path::new(BACKUP_DIR).join("backup.zip");
+        let error = Err(super::BackupError::CrcMismatch {
+            file_path: "001.vlt".to_owned(),
+        });
+        run_test(|| {
+            fs::create_dir_all(&lock_dir).unwrap();
+            crc::update_crc_all(&lock_dir);
+            fs::write(lock_dir.join("001.vlt"), "corrupt").unwrap();
+            let mut zipper = Zipper::new(&zip_path, Default::default());
+            zipper.zip_dir(&lock_dir);
+            zipper.finish().unwrap();
+            let result = restore(&zip_path, &lock_dir, pass);
+            assert_eq!(result, error);
+        })
+    }
+
+    #[test]
+    fn should_not_restore_invalid_archive() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let zip_path = Path::new(BACKUP_DIR).join("backup.zip");
+        let error = Err(super::BackupError::InvalidArchive);
+        run_test(|| {
+            fs::write(&zip_path, "invalid").unwrap();
+            let result = restore(&zip_path, &lock_dir, pass);
+            assert_eq!(result, error);
+        })
+    }
+
+    #[test]
+    fn should_create_backups_without_clash() {
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        run_test(|| {
+            fs::create_dir_all(&lock_dir).unwrap();
+            crc::update_crc_all(&lock_dir);
+            let first_path = full_backup(&lock_dir, &backup_dir);
+            let second_path = full_backup(&lock_dir, &backup_dir);
+            assert_ne!(first_path, second_path);
+            assert!(first_path.is_file() && second_path.is_file());
+        })
+    }
+
+    #[test]
+    fn should_prune_backups_over_keep_count() {
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let policy = super::RetentionPolicy {
+            keep_count: Some(1),
+            keep_days: None,
+        };
+        run_test(|| {
+            fs::create_dir_all(&lock_dir).unwrap();
+            crc::update_crc_all(&lock_dir);
+            full_backup(&lock_dir, &backup_dir);
+            full_backup(&lock_dir, &backup_dir);
+            let latest_path = full_backup(&lock_dir, &backup_dir);
+            let removed =
+                super::prune_backups(&backup_dir, &policy, None).unwrap();
+            assert_eq!(removed.len(), 2);
+            assert!(latest_path.is_file());
+        })
+    }
+
+    #[test]
+    fn should_keep_latest_backup_when_expired() {
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let policy = super::RetentionPolicy {
+            keep_count: Some(0),
+            keep_days: Some(0),
+        };
+        run_test(|| {
+            fs::create_dir_all(&lock_dir).unwrap();
+            crc::update_crc_all(&lock_dir);
+            let latest_path = full_backup(&lock_dir, &backup_dir);
+            let removed =
+                super::prune_backups(&backup_dir, &policy, None).unwrap();
+            assert!(removed.is_empty());
+            assert!(latest_path.is_file());
+        })
+    }
+
+    #[test]
+    fn should_list_backups_with_verification() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        run_test(|| {
+            fs::create_dir_all(&backup_dir).unwrap();
+            create_backup(&lock_dir, &backup_dir.join("vault-1.zip"), pass);
+            fs::write(backup_dir.join("vault-2.zip"), "invalid").unwrap();
+            let backups = super::list_backups(&lock_dir, &backup_dir, None);
+            let result = backups
+                .into_iter()
+                .map(|info| (info.name, info.verified))
+                .collect::<Vec<_>>();
+            assert_eq!(result, [
+                ("vault-1.zip".to_owned(), true),
+                ("vault-2.zip".to_owned(), false),
+            ]);
+        })
+    }
+
+    #[test]
+    fn should_create_incremental_backup_with_changed_files() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let stage_dir = Path::new(BACKUP_DIR).join("stage");
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            full_backup(&lock_dir, &backup_dir);
+            fs::write(lock_dir.join("002.vlt"), "added").unwrap();
+            crc::update_crc_all(&lock_dir);
+            let zip_path = incremental_backup(&lock_dir, &backup_dir);
+            let mut unzipper = Unzipper::new(zip_path).unwrap();
+            let matches = unzipper.unzip_dir(&lock_dir, stage_dir);
+            assert_eq!(matches.into_sorted(), ["002.vlt", "index.crc"]);
+        })
+    }
+
+    #[test]
+    fn should_restore_incremental_backup_chain() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            fs::write(lock_dir.join("002.vlt"), "removed").unwrap();
+            crc::update_crc_all(&lock_dir);
+            manifest::update_manifest(&lock_dir, pass);
+            full_backup(&lock_dir, &backup_dir);
+            fs::remove_file(lock_dir.join("002.vlt")).unwrap();
+            fs::write(lock_dir.join("003.vlt"), "added").unwrap();
+            crc::update_crc_all(&lock_dir);
+            manifest::update_manifest(&lock_dir, pass);
+            let zip_path = incremental_backup(&lock_dir, &backup_dir);
+            fs::remove_dir_all(&lock_dir).unwrap();
+            let result = restore(zip_path, &lock_dir, pass);
+            assert_eq!(result, Ok(None));
+            assert!(!lock_dir.join("002.vlt").exists());
+            assert!(lock_dir.join("003.vlt").exists());
+            assert!(crc::check_crc_all(&lock_dir).is_intact());
+        })
+    }
+
+    #[test]
+    fn should_not_restore_incremental_backup_without_parent() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            let full_path = full_backup(&lock_dir, &backup_dir);
+            let zip_path = incremental_backup(&lock_dir, &backup_dir);
+            fs::remove_file(&full_path).unwrap();
+            let error = Err(super::BackupError::BrokenChain {
+                file_path: full_path.to_path_str().to_owned(),
+            });
+            let result = restore(zip_path, &lock_dir, pass);
+            assert_eq!(result, error);
+        })
+    }
+
+    #[test]
+    fn should_not_resolve_parent_outside_backup_dir() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let zip_path = backup_dir.join("vault-1.zip");
+        let error = Err(super::BackupError::BrokenChain {
+            file_path: zip_path.to_path_str().to_owned(),
+        });
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            let full_path = full_backup(&lock_dir, Path::new(BACKUP_DIR));
+            let parent_name = format!("../{}", full_path.to_filename_str());
+            fs::create_dir_all(&backup_dir).unwrap();
+            let mut zipper = Zipper::new(&zip_path, Default::default());
+            zipper.zip_contents(super::MANIFEST_PATH, parent_name.as_bytes());
+            zipper.finish().unwrap();
+            assert_eq!(super::resolve_backup_chain(&zip_path, None), error);
+        })
+    }
+
+    #[test]
+    fn should_not_prune_backups_needed_by_chain() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let policy = super::RetentionPolicy {
+            keep_count: Some(1),
+            keep_days: None,
+        };
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            full_backup(&lock_dir, &backup_dir);
+            incremental_backup(&lock_dir, &backup_dir);
+            incremental_backup(&lock_dir, &backup_dir);
+            let removed =
+                super::prune_backups(&backup_dir, &policy, None).unwrap();
+            assert!(removed.is_empty());
+        })
+    }
+
+    #[test]
+    fn should_restore_encrypted_backup_with_backup_pass() {
+        let pass = &SecretString::from("1234");
+        let lock_dir = Path::new(BACKUP_DIR).join("lock");
+        let backup_dir = Path::new(BACKUP_DIR).join("backup");
+        let options = ZipperOptions {
+            compress: true,
+            pass: Some(SecretString::from("4321")),
+        };
+        let error = Err(super::BackupError::IncorrectBackupPassword);
+        run_test(|| {
+            write_lock_dir(&lock_dir, pass);
+            let zip_path =
+                super::create_backup(&lock_dir, &backup_dir, options)
+                    .unwrap();
+            assert_eq!(restore(&zip_path, &lock_dir, pass), error);
+            let backup_pass = Some(&SecretString::from("4321"));
+            let result =
+                super::restore_backup(zip_path, &lock_dir, pass, backup_pass);
+            assert!(result.is_ok());
+        })
+    }
+}
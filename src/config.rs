@@ -0,0 +1,133 @@
+use crate::crc;
+use crate::crypto::Padding;
+use crate::util::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Name of the config file, stored alongside the index.
+const CONFIG_FILE: &str = "index.cfg";
+
+/// Vault wide options, which decide how the secrets are stored.
+#[derive(Debug, Default, PartialEq)]
+pub struct VaultConfig {
+    /// Whether the secrets are stored under random opaque ids.
+    pub opaque: bool,
+    /// Padding applied to the secret contents, to hide their length.
+    pub padding: Padding,
+    /// Random id of the vault, which the encrypted files are bound to.
+    /// Vaults created before the id was introduced have zero.
+    pub vault_id: u32,
+    /// Whether all the encrypted files were upgraded to the latest format,
+    /// after which files of the first format are no longer accepted.
+    pub upgraded: bool,
+}
+
+/// Reads the vault config from the config file in the given directory.
+/// - If the file does not exist or is malformed, the default is returned.
+pub fn read_config<P: AsRef<Path>>(root_dir: P) -> VaultConfig {
+    let config_file_path = root_dir.as_ref().join(CONFIG_FILE);
+    let config_map = fs::read_to_string(config_file_path)
+        .ok()
+        .and_then(|contents| HashMap::<String, u32>::deserialize(&contents))
+        .unwrap_or_default();
+    let padding = match config_map.get("pad_block") {
+        Some(block_size) if *block_size > 0 =>
+            Padding::Block(*block_size as usize),
+        _ => Padding::Bucket,
+    };
+    VaultConfig {
+        opaque: config_map.get("opaque") == Some(&1),
+        padding,
+        vault_id: config_map.get("vault_id").copied().unwrap_or_default(),
+        upgraded: config_map.get("upgraded") == Some(&1),
+    }
+}
+
+/// Reads the vault config from the config file in the given directory.
+/// If the vault has no id yet, a random one is generated and written.
+pub fn read_or_init_config<P: AsRef<Path>>(root_dir: P) -> VaultConfig {
+    let mut config = read_config(&root_dir);
+    if config.vault_id == 0 {
+        while config.vault_id == 0 {
+            let mut bytes = [0; 4];
+            orion::util::secure_rand_bytes(&mut bytes).unwrap();
+            config.vault_id = u32::from_le_bytes(bytes);
+        }
+        write_config(&config, root_dir);
+    }
+    config
+}
+
+/// Writes the vault config into the config file in the given directory.
+/// Also updates the stored checksum of the config file.
+pub fn write_config<P: AsRef<Path>>(config: &VaultConfig, root_dir: P) {
+    let config_file_path = root_dir.as_ref().join(CONFIG_FILE);
+    let block_size = match config.padding {
+        Padding::Bucket => 0,
+        Padding::Block(block_size) => block_size as u32,
+    };
+    let config_map = HashMap::from([
+        ("opaque".to_owned(), config.opaque as u32),
+        ("pad_block".to_owned(), block_size),
+        ("vault_id".to_owned(), config.vault_id),
+        ("upgraded".to_owned(), config.upgraded as u32),
+    ]);
+    fs::create_dir_all(&root_dir).unwrap();
+    fs::write(&config_file_path, config_map.serialize()).unwrap();
+    crc::update_crc(config_file_path, root_dir);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::crypto::Padding;
+    use once_cell::sync::Lazy;
+    use std::fs;
+    use std::panic;
+    use std::sync::Mutex;
+
+    const CONFIG_DIR: &str = "config-test-dir";
+    static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
+        let lock = DIR_LOCK.lock().unwrap();
+        fs::create_dir_all(CONFIG_DIR).unwrap();
+        let result = panic::catch_unwind(test);
+        fs::remove_dir_all(CONFIG_DIR).unwrap();
+        drop(lock);
+        assert!(result.is_ok())
+    }
+
+    #[test]
+    fn should_read_default_config_when_missing() {
+        run_test(|| {
+            let config = super::read_config(CONFIG_DIR);
+            assert_eq!(config, super::VaultConfig::default());
+        })
+    }
+
+    #[test]
+    fn should_write_and_read_config() {
+        let config = super::VaultConfig {
+            opaque: true,
+            padding: Padding::Block(4096),
+            vault_id: 1234,
+            upgraded: true,
+        };
+        run_test(|| {
+            super::write_config(&config, CONFIG_DIR);
+            assert_eq!(super::read_config(CONFIG_DIR), config);
+        })
+    }
+
+    #[test]
+    fn should_init_vault_id_once() {
+        run_test(|| {
+            let config = super::read_or_init_config(CONFIG_DIR);
+            assert_ne!(config.vault_id, 0);
+            assert_eq!(super::read_config(CONFIG_DIR), config);
+            assert_eq!(super::read_or_init_config(CONFIG_DIR), config);
+        })
+    }
+}
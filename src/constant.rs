@@ -3,3 +3,22 @@ pub const LOCK_DIR: &str = "vault-lock";
 
 /// Path to the directory where decrypted files are stored.
 pub const UNLOCK_DIR: &str = "vault-unlock";
+
+/// Path to the directory where zip backups are stored by default.
+pub const BACKUP_DIR: &str = "vault-backup";
+
+/// Path to the vault executable, which is packed along with backups.
+pub const EXEC_PATH: &str = "vault";
+
+/// Path to the socket the unlock agent listens on.
+/// It is kept in its own directory, which only the current user can enter.
+pub const AGENT_SOCKET: &str = "vault-agent/agent.sock";
+
+/// Seconds of inactivity after which the unlock agent stops by default.
+pub const AGENT_TIMEOUT_SECS: u64 = 300;
+
+/// Seconds of inactivity after which the explorer locks by default.
+pub const TUI_LOCK_SECS: u64 = 300;
+
+/// Number of password attempts the explorer allows by default.
+pub const TUI_PASS_ATTEMPTS: u32 = 3;
@@ -1,21 +1,39 @@
-use crate::util::ext::PathExt;
+use crate::util::ext::{PathExt, VecExt};
 use crate::util::serde::Deserialize;
 use crate::util::serde::Serialize;
 use crc::{Crc, CRC_32_ISCSI};
 use std::collections::HashMap;
-use std::fs;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 
-type CrcMap = HashMap<String, u32>;
+/// Hashmap that maps file names to their checksum values.
+pub type CrcMap = HashMap<String, u32>;
+
 type CrcResult<T> = Result<T, CrcMismatchError>;
 const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
+/// Computes crc checksum for the given contents.
+#[inline]
+pub fn compute_crc_contents(contents: &[u8]) -> u32 {
+    CRC32.checksum(contents)
+}
+
 /// Computes crc checksum for the given file path.
+/// The file is read in chunks, so it is never held whole in memory.
 /// Returns any error while accessing the file.
 fn compute_crc<P: AsRef<Path>>(path: P) -> io::Result<u32> {
-    let file_content = fs::read(path)?;
-    Ok(CRC32.checksum(&file_content))
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut digest = CRC32.digest();
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(digest.finalize());
+        }
+        digest.update(chunk);
+        let chunk_len = chunk.len();
+        reader.consume(chunk_len);
+    }
 }
 
 /// Computes crc checksum for all files in given directory (non-recursive).
@@ -27,8 +45,7 @@ fn compute_crc_all<P: AsRef<Path>>(root_dir: P) -> CrcMap {
             let file_entry = entry.unwrap();
             let file_name = file_entry.file_name();
             if file_name != "index.crc" {
-                let file_content = fs::read(file_entry.path()).unwrap();
-                let checksum = CRC32.checksum(&file_content);
+                let checksum = compute_crc(file_entry.path()).unwrap();
                 accum.insert(file_name.into_string().unwrap(), checksum);
             }
             accum
@@ -41,12 +58,23 @@ fn compute_crc_all<P: AsRef<Path>>(root_dir: P) -> CrcMap {
 /// Reads crc map from an index file in the given directory.
 /// Returns a hashmap mapping file name to its checksum value.
 /// - If the given directory does not exist, an empty map is returned.
-fn read_crc_file<P: AsRef<Path>>(root_dir: P) -> CrcMap {
+/// - If a checksum is stored under the file path, as done by earlier
+///   versions, it is read under the file name instead.
+pub fn read_crc_file<P: AsRef<Path>>(root_dir: P) -> CrcMap {
     let crc_file_path = root_dir.as_ref().join("index.crc");
-    match fs::read_to_string(crc_file_path) {
+    let stored_crc = match fs::read_to_string(crc_file_path) {
         Ok(contents) => CrcMap::deserialize(&contents).unwrap(),
-        Err(_) => HashMap::new(),
-    }
+        Err(_) => return HashMap::new(),
+    };
+    // checksums stored under the path were updated one file at a time,
+    // so they are more recent than the ones stored under the name
+    let (by_path, by_name): (Vec<_>, Vec<_>) = stored_crc
+        .into_iter()
+        .partition(|(key, _)| key.to_filename_str() != key);
+    let by_path = by_path
+        .into_iter()
+        .map(|(key, crc)| (key.to_filename_str().to_owned(), crc));
+    by_name.into_iter().chain(by_path).collect()
 }
 
 /// Writes crc map into an index file in the given directory.
@@ -63,7 +91,7 @@ pub fn check_crc<P, Q>(path: P, root_dir: Q) -> CrcResult<()>
 where P: AsRef<Path>, Q: AsRef<Path> {
     let stored_crc_all = read_crc_file(root_dir);
     match compute_crc(&path) {
-        Ok(computed_crc) => match stored_crc_all.get(path.to_path_str()) {
+        Ok(computed_crc) => match stored_crc_all.get(path.to_filename_str()) {
             Some(stored_crc) => match stored_crc == &computed_crc {
                 true => Ok(()),
                 false => Err(CrcMismatchError::new(path.to_filename_str())),
@@ -79,38 +107,42 @@ pub fn update_crc<P, Q>(path: P, root_dir: Q)
 where P: AsRef<Path>, Q: AsRef<Path> {
     let mut stored_crc = read_crc_file(&root_dir);
     match compute_crc(&path) {
-        Ok(crc) => stored_crc.insert(path.to_path_str().to_owned(), crc),
-        Err(_) => stored_crc.remove(path.to_path_str()),
+        Ok(crc) => stored_crc.insert(path.to_filename_str().to_owned(), crc),
+        Err(_) => stored_crc.remove(path.to_filename_str()),
     };
     write_crc_file(&stored_crc, root_dir);
 }
 
 /// Compares computed crc checksum of all files in the the given directory
 /// with the corresponding stored values.
-/// - If the comparison fails, returns `CrcMismatchError`.
-pub fn check_crc_all<P: AsRef<Path>>(root_dir: P) -> CrcResult<()> {
+/// Returns a report of all the mismatches, sorted by their category.
+pub fn check_crc_all<P: AsRef<Path>>(root_dir: P) -> CrcReport {
     let stored_crc = read_crc_file(&root_dir);
     let computed_crc = compute_crc_all(root_dir);
-    let added_errors = computed_crc
+    let untracked = computed_crc
         .keys()
-        .filter_map(|computed_key| {
-            match stored_crc.contains_key(computed_key) {
-                true => None,
-                false => Some(CrcMismatchError::new(computed_key)),
-            }
-        });
-    let diff_errors = stored_crc
+        .filter(|computed_key| !stored_crc.contains_key(*computed_key))
+        .cloned()
+        .collect::<Vec<_>>();
+    let missing = stored_crc
         .keys()
-        .filter_map(|stored_key| {
-            let stored_value = stored_crc.get(stored_key).unwrap();
-            match computed_crc.get(stored_key) {
-                Some(computed_value) if computed_value == stored_value => None,
-                _ => Some(CrcMismatchError::new(stored_key)),
+        .filter(|stored_key| !computed_crc.contains_key(*stored_key))
+        .cloned()
+        .collect::<Vec<_>>();
+    let modified = stored_crc
+        .iter()
+        .filter(|(stored_key, stored_value)| {
+            match computed_crc.get(*stored_key) {
+                Some(computed_value) => computed_value != *stored_value,
+                None => false,
             }
-        });
-    match added_errors.chain(diff_errors).next() {
-        Some(err) => Err(err),
-        None => Ok(()),
+        })
+        .map(|(stored_key, _)| stored_key.to_owned())
+        .collect::<Vec<_>>();
+    CrcReport {
+        untracked: untracked.into_sorted(),
+        missing: missing.into_sorted(),
+        modified: modified.into_sorted(),
     }
 }
 
@@ -121,6 +153,47 @@ pub fn update_crc_all<P: AsRef<Path>>(root_dir: P) {
     write_crc_file(&computed_crc, root_dir)
 }
 
+/// Report of all the crc mismatches in a directory.
+#[derive(Debug, Default, PartialEq)]
+pub struct CrcReport {
+    /// Files which exist, but have no stored checksum.
+    pub untracked: Vec<String>,
+    /// Files which have a stored checksum, but do not exist.
+    pub missing: Vec<String>,
+    /// Files whose computed checksum differs from the stored one.
+    pub modified: Vec<String>,
+}
+
+impl CrcReport {
+    /// Checks whether there are no mismatches at all.
+    pub fn is_intact(&self) -> bool {
+        self.untracked.is_empty()
+            && self.missing.is_empty()
+            && self.modified.is_empty()
+    }
+
+    /// Converts the report into a result with the first mismatch, if any.
+    pub fn into_result(self) -> CrcResult<()> {
+        self.untracked
+            .into_iter()
+            .chain(self.missing)
+            .chain(self.modified)
+            .next()
+            .map_or(Ok(()), |file_path| Err(CrcMismatchError::new(file_path)))
+    }
+}
+
+impl Serialize for &CrcReport {
+    fn serialize(self) -> String {
+        format!(
+            "{{\"untracked\":{},\"missing\":{},\"modified\":{}}}",
+            self.untracked.serialize(),
+            self.missing.serialize(),
+            self.modified.serialize(),
+        )
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct CrcMismatchError {
     pub file_path: String,
@@ -134,20 +207,21 @@ impl CrcMismatchError {
 
 #[cfg(test)]
 mod test {
+    use crate::util::serde::Serialize;
     use once_cell::sync::Lazy;
     use std::fs;
     use std::panic;
     use std::path::Path;
     use std::sync::Mutex;
 
-    const CRC_DIR: &'static str = "crc-test-dir";
+    const CRC_DIR: &str = "crc-test-dir";
     static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-    fn run_test<T>(test: T) -> ()
-    where T: FnOnce() -> () + panic::UnwindSafe {
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
         let lock = DIR_LOCK.lock().unwrap();
         fs::create_dir_all(CRC_DIR).unwrap();
-        let result = panic::catch_unwind(|| test());
+        let result = panic::catch_unwind(test);
         fs::remove_dir_all(CRC_DIR).unwrap();
         drop(lock);
         assert!(result.is_ok())
@@ -182,7 +256,7 @@ mod test {
             let file_path = Path::new(CRC_DIR).join("path");
             fs::write(file_path, "first_val").unwrap();
             super::update_crc_all(CRC_DIR);
-            assert_eq!(super::check_crc_all(CRC_DIR), Ok(()));
+            assert!(super::check_crc_all(CRC_DIR).is_intact());
         })
     }
 
@@ -194,8 +268,58 @@ mod test {
             fs::write(&file_path, "first_val").unwrap();
             super::update_crc_all(CRC_DIR);
             fs::write(file_path, "second_val").unwrap();
-            let error = Err(super::CrcMismatchError::new(file_name));
-            assert_eq!(super::check_crc_all(CRC_DIR), error);
+            let report = super::CrcReport {
+                modified: [file_name.to_owned()].to_vec(),
+                ..Default::default()
+            };
+            assert_eq!(super::check_crc_all(CRC_DIR), report);
+        })
+    }
+
+    #[test]
+    fn should_report_all_crc_mismatches_by_category() {
+        run_test(|| {
+            let root_dir = Path::new(CRC_DIR);
+            fs::write(root_dir.join("f1"), "first_val").unwrap();
+            fs::write(root_dir.join("f2"), "first_val").unwrap();
+            fs::write(root_dir.join("f3"), "first_val").unwrap();
+            super::update_crc_all(CRC_DIR);
+            fs::write(root_dir.join("f1"), "second_val").unwrap();
+            fs::remove_file(root_dir.join("f2")).unwrap();
+            fs::write(root_dir.join("f4"), "first_val").unwrap();
+            fs::write(root_dir.join("f5"), "first_val").unwrap();
+            let report = super::CrcReport {
+                untracked: ["f4".to_owned(), "f5".to_owned()].to_vec(),
+                missing: ["f2".to_owned()].to_vec(),
+                modified: ["f1".to_owned()].to_vec(),
+            };
+            assert_eq!(super::check_crc_all(CRC_DIR), report);
         })
     }
+
+    #[test]
+    fn should_read_crc_stored_under_path() {
+        run_test(|| {
+            let file_path = Path::new(CRC_DIR).join("path");
+            fs::write(&file_path, "first_val").unwrap();
+            super::update_crc(&file_path, CRC_DIR);
+            let crc = super::read_crc_file(CRC_DIR)["path"];
+            let stored_crc = format!("{{\"{}/path\":{}}}", CRC_DIR, crc);
+            fs::write(Path::new(CRC_DIR).join("index.crc"), stored_crc)
+                .unwrap();
+            assert_eq!(super::check_crc(&file_path, CRC_DIR), Ok(()));
+            assert!(super::check_crc_all(CRC_DIR).is_intact());
+        })
+    }
+
+    #[test]
+    fn should_serialize_crc_report() {
+        let report = super::CrcReport {
+            untracked: ["f1".to_owned()].to_vec(),
+            ..Default::default()
+        };
+        let expected =
+            "{\"untracked\":[\"f1\"],\"missing\":[],\"modified\":[]}";
+        assert_eq!(report.serialize(), expected);
+    }
 }
@@ -1,47 +1,442 @@
+use crate::util::secure::{SecretBytes, SecretString};
 use crate::util::serde::{Serialize, Deserialize};
 use orion::aead;
+use orion::kdf;
 use orion::errors::UnknownCryptoError;
-use std::fs;
-use std::path::Path;
+use orion::hazardous::aead::streaming::{
+    StreamTag,
+    StreamXChaCha20Poly1305,
+    ABYTES,
+};
+use orion::hazardous::stream::chacha20;
+use orion::hazardous::stream::xchacha20::{Nonce, XCHACHA_NONCESIZE};
+use std::cmp;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
 
-type CryptoResult<T> = Result<T, UnknownCryptoError>;
+pub type CryptoResult<T> = Result<T, CryptoError>;
 
-/// Encrypts a stream of bytes using the given password.
-fn encrypt(data: &[u8], pass: &str) -> CryptoResult<Vec<u8>> {
-    let password = format!("{:0>32}", pass);
+/// Minimum size of the padded contents, in bytes.
+const MIN_PAD_SIZE: usize = 256;
+
+/// Largest power-of-two size bucket, in bytes. Larger contents are padded
+/// to multiples of it instead, so that they are not nearly doubled.
+const MAX_BUCKET_SIZE: usize = 1 << 20;
+
+/// Size of the chunks the contents are sealed in, in bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Magic bytes at the start of every encrypted file with a header.
+const MAGIC: &[u8; 4] = b"VLT\0";
+
+/// Length of the header: magic bytes, format, KDF and cipher identifiers.
+const HEADER_LEN: usize = MAGIC.len() + 3;
+
+/// Latest format, which seals the padded contents as a stream of chunks
+/// bound to their associated data, so that files of any size are encrypted
+/// and decrypted in constant memory.
+/// Contents sealed whole before the header was introduced have format 0.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// KDF which pads the password with leading zeroes into a 32-byte key.
+const KDF_ZERO_PADDED: u8 = 1;
+
+/// Cipher used by `orion::aead::streaming`, which is XChaCha20-Poly1305
+/// in the secretstream construction.
+const CIPHER_XCHACHA20_POLY1305_STREAM: u8 = 1;
+
+/// Argon2i iterations used to derive a key from a password.
+const KDF_ITERATIONS: u32 = 3;
+
+/// Argon2i memory used to derive a key from a password, in KiB.
+const KDF_MEMORY: u32 = 1 << 16;
+
+/// Size of the random salt stored before the contents sealed with a
+/// derived key, in bytes.
+const SALT_SIZE: usize = 16;
+
+/// Padding applied to the contents before encrypting, to hide their length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    /// Pads to the next power-of-two size bucket, up to `MAX_BUCKET_SIZE`.
+    Bucket,
+    /// Pads to the next multiple of the given block size.
+    Block(usize),
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self::Bucket
+    }
+}
+
+/// Returns the size of the data of the given size after padding.
+fn get_padded_size(size: usize, padding: Padding) -> usize {
+    let marked_size = size + 1;
+    match padding {
+        Padding::Bucket if marked_size > MAX_BUCKET_SIZE =>
+            get_padded_size(size, Padding::Block(MAX_BUCKET_SIZE)),
+        Padding::Bucket =>
+            cmp::max(marked_size.next_power_of_two(), MIN_PAD_SIZE),
+        Padding::Block(block_size) => {
+            let block_size = cmp::max(block_size, 1);
+            (marked_size + block_size - 1) / block_size * block_size
+        }
+    }
+}
+
+/// Pads the contents of the reader of the given size using the given
+/// padding, so that their length is hidden, without reading them into memory.
+/// A `0x80` marker is appended, followed by zeroes.
+fn pad_reader<R: Read>(reader: R, size: usize, padding: Padding)
+    -> impl Read {
+    let zero_count = get_padded_size(size, padding) - size - 1;
+    reader
+        .take(size as u64)
+        .chain(Cursor::new([0x80]))
+        .chain(io::repeat(0).take(zero_count as u64))
+}
+
+/// Writer which removes the padding added by `pad_reader` from the end of the
+/// written contents. The trailing run of zeroes and the marker before it
+/// are held back, until either more contents or the end is written.
+struct Unpadder<W: Write> {
+    inner: W,
+    has_marker: bool,
+    zero_count: u64,
+}
+
+impl<W: Write> Unpadder<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, has_marker: false, zero_count: 0 }
+    }
+
+    /// Writes out the held back bytes, as they turned out not to be padding.
+    fn write_held(&mut self) {
+        if self.has_marker {
+            self.inner.write_all(&[0x80]).unwrap();
+            self.has_marker = false;
+        }
+        let mut zeroes = io::repeat(0).take(self.zero_count);
+        io::copy(&mut zeroes, &mut self.inner).unwrap();
+        self.zero_count = 0;
+    }
+
+    fn write_all(&mut self, data: &[u8]) {
+        match data.iter().rposition(|byte| *byte != 0) {
+            Some(last_pos) => {
+                self.write_held();
+                let is_marker = data[last_pos] == 0x80;
+                let kept_len = last_pos + !is_marker as usize;
+                self.inner.write_all(&data[..kept_len]).unwrap();
+                self.has_marker = is_marker;
+                self.zero_count = (data.len() - last_pos - 1) as u64;
+            }
+            None => self.zero_count += data.len() as u64,
+        }
+    }
+
+    /// Returns the inner writer, dropping the held back padding.
+    /// - If the padding is malformed, returns `None`.
+    fn finish(self) -> Option<W> {
+        match self.has_marker {
+            true => Some(self.inner),
+            false => None,
+        }
+    }
+}
+
+/// Reads into the buffer until it is full or the reader is exhausted.
+/// Returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]).unwrap() {
+            0 => break,
+            read_len => len += read_len,
+        }
+    }
+    len
+}
+
+/// Returns the path of the partial file, which is written before being
+/// renamed to the given path, so that the given path is never left half
+/// written.
+fn get_part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map_or_else(OsString::new, |name| {
+        name.to_owned()
+    });
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+/// Pads the password with leading zeroes into at least 32 characters,
+/// which is what the secret key is created from.
+pub fn pad_password(pass: &SecretString) -> SecretString {
+    let zero_count = 32usize.saturating_sub(pass.chars().count());
+    let mut password = SecretString::with_capacity(zero_count + pass.len());
+    (0..zero_count).for_each(|_| password.push('0'));
+    password.push_str(pass);
+    password
+}
+
+/// Encrypts a stream of bytes using the given password, in the first
+/// format, so that tests can set up vaults written before the upgrade.
+#[cfg(test)]
+pub fn encrypt(data: &[u8], pass: &SecretString) -> CryptoResult<Vec<u8>> {
+    let password = pad_password(pass);
     let secret_key = aead::SecretKey::from_slice(password.as_bytes())?;
-    aead::seal(&secret_key, data)
+    Ok(aead::seal(&secret_key, data)?)
+}
+
+/// Derives the secret key from the password and salt using Argon2i.
+/// - If the password is empty, returns `IncorrectPassword`.
+fn derive_key(pass: &SecretString, salt: &kdf::Salt)
+    -> CryptoResult<aead::SecretKey> {
+    let password = kdf::Password::from_slice(pass.as_bytes())?;
+    let key =
+        kdf::derive_key(&password, salt, KDF_ITERATIONS, KDF_MEMORY, 32)?;
+    Ok(key)
+}
+
+/// Encrypts a stream of bytes using a key derived from the given password,
+/// which is not limited in length. The random salt is prepended.
+/// - If the password is empty, returns `IncorrectPassword`.
+pub fn encrypt_derived(data: &[u8], pass: &SecretString)
+    -> CryptoResult<Vec<u8>> {
+    let salt = kdf::Salt::default();
+    let secret_key = derive_key(pass, &salt)?;
+    Ok([salt.as_ref(), &aead::seal(&secret_key, data)?].concat())
+}
+
+/// Decrypts a stream of bytes sealed by `encrypt_derived`.
+/// - If the password does not match, returns `IncorrectPassword`.
+pub fn decrypt_derived(data: &[u8], pass: &SecretString)
+    -> CryptoResult<SecretBytes> {
+    if data.len() < SALT_SIZE {
+        return Err(CryptoError::IncorrectPassword);
+    }
+    let (salt, data_enc) = data.split_at(SALT_SIZE);
+    let secret_key = derive_key(pass, &kdf::Salt::from_slice(salt)?)?;
+    Ok(SecretBytes::from(aead::open(&secret_key, data_enc)?))
 }
 
 /// Decrypts a stream of bytes using the given password.
-/// - If the password does not match, returns `UnknownCryptoError`.
-fn decrypt(data: &[u8], pass: &str) -> CryptoResult<Vec<u8>> {
-    let password = format!("{:0>32}", pass);
+/// - If the password does not match, returns `IncorrectPassword`.
+pub fn decrypt(data: &[u8], pass: &SecretString) -> CryptoResult<SecretBytes> {
+    let password = pad_password(pass);
     let secret_key = aead::SecretKey::from_slice(password.as_bytes())?;
-    aead::open(&secret_key, data)
+    Ok(SecretBytes::from(aead::open(&secret_key, data)?))
+}
+
+/// Encrypts the contents of the reader into the writer using the given
+/// password, as a header and a nonce followed by a stream of chunks.
+/// Every chunk is bound to the associated data, and the last one is tagged,
+/// so that truncated or reordered chunks cannot be decrypted.
+fn seal_stream<R, W>(
+    mut reader: R,
+    mut writer: W,
+    pass: &SecretString,
+    ad: &[u8],
+) -> CryptoResult<W>
+where R: Read, W: Write {
+    let password = pad_password(pass);
+    let secret_key = chacha20::SecretKey::from_slice(password.as_bytes())?;
+    let nonce = Nonce::generate();
+    let header = [
+        FORMAT_VERSION,
+        KDF_ZERO_PADDED,
+        CIPHER_XCHACHA20_POLY1305_STREAM,
+    ];
+    writer.write_all(MAGIC).unwrap();
+    writer.write_all(&header).unwrap();
+    writer.write_all(nonce.as_ref()).unwrap();
+    let mut sealer = StreamXChaCha20Poly1305::new(&secret_key, &nonce);
+    let mut chunk = SecretBytes::zeroed(CHUNK_SIZE);
+    let mut next_chunk = SecretBytes::zeroed(CHUNK_SIZE);
+    let mut chunk_enc = vec![0; CHUNK_SIZE + ABYTES];
+    let mut len = read_full(&mut reader, &mut chunk);
+    loop {
+        // full chunks may be followed by more contents, so the next chunk
+        // is read ahead to tell whether the current one is the last
+        let next_len = match len {
+            CHUNK_SIZE => read_full(&mut reader, &mut next_chunk),
+            _ => 0,
+        };
+        let tag = match next_len {
+            0 => StreamTag::Finish,
+            _ => StreamTag::Message,
+        };
+        let chunk_enc = &mut chunk_enc[..len + ABYTES];
+        sealer.seal_chunk(&chunk[..len], Some(ad), chunk_enc, tag)?;
+        writer.write_all(chunk_enc).unwrap();
+        if next_len == 0 {
+            return Ok(writer);
+        }
+        mem::swap(&mut chunk, &mut next_chunk);
+        len = next_len;
+    }
+}
+
+/// Decrypts the stream of chunks sealed by `seal_stream` from the reader
+/// into the writer, removing the padding. The header must already be read.
+/// - If the password or the associated data does not match,
+///   returns `IncorrectPassword`.
+/// - If the chunks were truncated, reordered or appended to,
+///   returns `CorruptContents`.
+fn open_stream<R, W>(mut reader: R, writer: W, pass: &SecretString, ad: &[u8])
+    -> CryptoResult<W>
+where R: Read, W: Write {
+    let password = pad_password(pass);
+    let secret_key = chacha20::SecretKey::from_slice(password.as_bytes())?;
+    let mut nonce = [0; XCHACHA_NONCESIZE];
+    if read_full(&mut reader, &mut nonce) < nonce.len() {
+        return Err(CryptoError::CorruptContents);
+    }
+    let nonce = Nonce::from_slice(&nonce)?;
+    let mut opener = StreamXChaCha20Poly1305::new(&secret_key, &nonce);
+    let mut unpadder = Unpadder::new(writer);
+    let mut chunk_enc = vec![0; CHUNK_SIZE + ABYTES];
+    let mut chunk = SecretBytes::zeroed(CHUNK_SIZE);
+    let mut is_first = true;
+    loop {
+        let len = read_full(&mut reader, &mut chunk_enc);
+        if len < ABYTES {
+            return Err(CryptoError::CorruptContents);
+        }
+        let chunk = &mut chunk[..len - ABYTES];
+        let tag = match opener.open_chunk(&chunk_enc[..len], Some(ad), chunk) {
+            Ok(tag) => tag,
+            // only the first chunk tells apart an incorrect password, as
+            // any later chunk can fail because of the chunks before it
+            Err(_) if is_first => return Err(CryptoError::IncorrectPassword),
+            Err(_) => return Err(CryptoError::CorruptContents),
+        };
+        unpadder.write_all(chunk);
+        if tag == StreamTag::Finish {
+            break;
+        }
+        is_first = false;
+    }
+    if read_full(&mut reader, &mut [0]) > 0 {
+        return Err(CryptoError::CorruptContents);
+    }
+    unpadder.finish().ok_or(CryptoError::CorruptContents)
+}
+
+/// Pads and encrypts a stream of bytes using the given password, binding
+/// it to the associated data.
+/// The result starts with a header holding the format, KDF and cipher,
+/// so that it can be told apart from contents sealed by older versions.
+pub fn encrypt_padded(
+    data: &[u8],
+    pass: &SecretString,
+    padding: Padding,
+    ad: &[u8],
+) -> CryptoResult<Vec<u8>> {
+    let reader = pad_reader(data, data.len(), padding);
+    seal_stream(reader, Vec::new(), pass, ad)
+}
+
+/// Checks whether the format, KDF and cipher in the header are known.
+/// - If the format, KDF or cipher is not known, returns `UnsupportedFormat`.
+fn check_header(header: &[u8]) -> CryptoResult<()> {
+    let format = header[MAGIC.len()];
+    let kdf = header[MAGIC.len() + 1];
+    let cipher = header[MAGIC.len() + 2];
+    let is_supported = format == FORMAT_VERSION
+        && kdf == KDF_ZERO_PADDED
+        && cipher == CIPHER_XCHACHA20_POLY1305_STREAM;
+    match is_supported {
+        true => Ok(()),
+        false => Err(CryptoError::UnsupportedFormat { format, kdf, cipher }),
+    }
 }
 
-/// Writes out the serialized value to encrypted file using the given password.
-pub fn write_file<P, S>(path: P, val: S, pass: &str) -> CryptoResult<()>
+/// Checks whether the encrypted file starts with the magic bytes of the
+/// header, which the first version did not have.
+/// - If the file cannot be read, returns `false`.
+pub fn has_header<P: AsRef<Path>>(path: P) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    match File::open(path) {
+        Ok(mut file) => read_full(&mut file, &mut magic) == MAGIC.len()
+            && &magic == MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// Decrypts a stream of bytes sealed by either `encrypt_padded` or by the
+/// first version, which sealed the contents whole with no header, padding
+/// or associated data.
+/// Returns the format along with the unpadded contents.
+/// - If the format, KDF or cipher is not known, returns `UnsupportedFormat`.
+/// - If the password or the associated data does not match,
+///   returns `IncorrectPassword`.
+/// - If the chunks were truncated or reordered, returns `CorruptContents`.
+pub fn decrypt_versioned(data: &[u8], pass: &SecretString, ad: &[u8])
+    -> CryptoResult<(u8, SecretBytes)> {
+    // the leading bytes of the first version are random, so they are only
+    // taken as a header if the rest of the contents can be opened
+    if data.len() > HEADER_LEN && data.starts_with(MAGIC) {
+        let (header, contents_enc) = data.split_at(HEADER_LEN);
+        let writer = SecretBytes::with_capacity(contents_enc.len());
+        let result = check_header(header)
+            .and_then(|_| open_stream(contents_enc, writer, pass, ad));
+        match result {
+            Ok(contents) => return Ok((FORMAT_VERSION, contents)),
+            Err(error) if decrypt(data, pass).is_err() => return Err(error),
+            Err(_) => {}
+        }
+    }
+    Ok((0, decrypt(data, pass)?))
+}
+
+/// Writes out the serialized value to encrypted file using the given password,
+/// binding it to the associated data.
+pub fn write_file<P, S>(path: P, val: S, pass: &SecretString, ad: &[u8])
+    -> CryptoResult<()>
 where P: AsRef<Path>, S: Serialize {
-    let val_str = val.serialize();
-    let contents_enc = encrypt(val_str.as_bytes(), pass)?;
-    path.as_ref()
-        .parent()
-        .map(|parent| fs::create_dir_all(parent).unwrap());
+    let val_str = SecretString::from(val.serialize());
+    let padding = Padding::default();
+    let contents_enc = encrypt_padded(val_str.as_bytes(), pass, padding, ad)?;
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
     fs::write(path, contents_enc).unwrap();
     Ok(())
 }
 
 /// Reads the deserialized value of an encrypted file using the given password.
 /// - If the file cannot be read or contains non-utf-8 values, returns `None`.
-/// - If the password does not match, returns `UnknownCryptoError`.
-pub fn read_file<P, D>(path: P, pass: &str) -> CryptoResult<Option<D>>
+/// - If the format of the file is not known, returns `UnsupportedFormat`.
+/// - If the password or the associated data does not match,
+///   returns `IncorrectPassword`.
+#[cfg(test)]
+pub fn read_file<P, D>(path: P, pass: &SecretString, ad: &[u8])
+    -> CryptoResult<Option<D>>
+where P: AsRef<Path>, D: Deserialize {
+    let result = read_file_versioned(path, pass, ad)?;
+    Ok(result.map(|(_, val)| val))
+}
+
+/// Reads the format along with the deserialized value of an encrypted file
+/// using the given password.
+/// - If the file cannot be read or contains non-utf-8 values, returns `None`.
+/// - If the format of the file is not known, returns `UnsupportedFormat`.
+/// - If the password or the associated data does not match,
+///   returns `IncorrectPassword`.
+pub fn read_file_versioned<P, D>(path: P, pass: &SecretString, ad: &[u8])
+    -> CryptoResult<Option<(u8, D)>>
 where P: AsRef<Path>, D: Deserialize {
     let result = if let Ok(contents_enc) = fs::read(path) {
-        let contents_raw = decrypt(&contents_enc, pass)?;
-        match String::from_utf8(contents_raw) {
-            Ok(contents_str) => D::deserialize(&contents_str),
+        let (format, contents_raw) =
+            decrypt_versioned(&contents_enc, pass, ad)?;
+        match std::str::from_utf8(&contents_raw) {
+            Ok(contents_str) =>
+                D::deserialize(contents_str).map(|val| (format, val)),
             Err(_) => None
         }
     } else {
@@ -50,139 +445,390 @@ where P: AsRef<Path>, D: Deserialize {
     Ok(result)
 }
 
-/// Encrypts contents of src file into the dest file using the given password.
+/// Encrypts contents of src file into the dest file using the given password,
+/// binding them to the associated data.
 /// Doing so creates or updates the dest file, so the src file is not affected.
-pub fn encrypt_file<P, Q>(src: P, dest: Q, pass: &str) -> CryptoResult<()>
+/// The contents are streamed in chunks, so they are never read whole.
+pub fn encrypt_file<P, Q>(
+    src: P,
+    dest: Q,
+    pass: &SecretString,
+    padding: Padding,
+    ad: &[u8],
+) -> CryptoResult<()>
 where P: AsRef<Path>, Q: AsRef<Path> {
-    let contents_raw = fs::read(src).unwrap();
-    let contents_enc = encrypt(&contents_raw, pass)?;
-    dest.as_ref()
-        .parent()
-        .map(|parent| fs::create_dir_all(parent).unwrap());
-    fs::write(dest, contents_enc).unwrap();
+    let src_file = File::open(src).unwrap();
+    let src_size = src_file.metadata().unwrap().len() as usize;
+    // the contents are read in whole chunks, so they are not buffered twice
+    let reader = pad_reader(src_file, src_size, padding);
+    if let Some(parent) = dest.as_ref().parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let part_path = get_part_path(dest.as_ref());
+    let writer = BufWriter::new(File::create(&part_path).unwrap());
+    match seal_stream(reader, writer, pass, ad) {
+        Ok(writer) => drop(writer.into_inner().unwrap()),
+        Err(error) => {
+            fs::remove_file(part_path).unwrap();
+            return Err(error);
+        }
+    }
+    fs::rename(part_path, dest).unwrap();
     Ok(())
 }
 
 /// Decrypts contents of src file into the dest file using the given password.
 /// Doing so creates or updates the dest file, so the src file is not affected.
-/// - If the password does not match, returns `UnknownCryptoError`.
-pub fn decrypt_file<P, Q>(src: P, dest: Q, pass: &str) -> CryptoResult<()>
+/// The contents of the latest format are streamed in chunks, so they are
+/// never read whole, while the first version sealed them whole.
+/// Returns the format of the src file.
+/// - If the format of the file is not known, returns `UnsupportedFormat`.
+/// - If the password or the associated data does not match,
+///   returns `IncorrectPassword`.
+/// - If the chunks were truncated or reordered, returns `CorruptContents`.
+pub fn decrypt_file<P, Q>(src: P, dest: Q, pass: &SecretString, ad: &[u8])
+    -> CryptoResult<u8>
 where P: AsRef<Path>, Q: AsRef<Path> {
-    let contents_enc = fs::read(src).unwrap();
-    let contents_raw = decrypt(&contents_enc, pass)?;
-    dest.as_ref()
-        .parent()
-        .map(|parent| fs::create_dir_all(parent).unwrap());
-    fs::write(dest, contents_raw).unwrap();
-    Ok(())
+    let mut reader = BufReader::new(File::open(&src).unwrap());
+    let mut header = [0; HEADER_LEN];
+    let header_len = read_full(&mut reader, &mut header);
+    let is_streamed = header_len == HEADER_LEN
+        && header.starts_with(MAGIC)
+        && check_header(&header).is_ok();
+    if let Some(parent) = dest.as_ref().parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let part_path = get_part_path(dest.as_ref());
+    let format = if is_streamed {
+        // the decrypted chunks are written whole, so they are not buffered
+        let writer = File::create(&part_path).unwrap();
+        match open_stream(reader, writer, pass, ad) {
+            Ok(writer) => drop(writer),
+            Err(error) => {
+                fs::remove_file(part_path).unwrap();
+                return Err(error);
+            }
+        }
+        FORMAT_VERSION
+    } else {
+        let contents_enc = fs::read(src).unwrap();
+        let (format, contents_raw) =
+            decrypt_versioned(&contents_enc, pass, ad)?;
+        fs::write(&part_path, &*contents_raw).unwrap();
+        format
+    };
+    fs::rename(part_path, dest).unwrap();
+    Ok(format)
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum CryptoError {
+    CorruptContents,
+    IncorrectPassword,
+    UnsupportedFormat { format: u8, kdf: u8, cipher: u8 },
+}
+
+impl From<UnknownCryptoError> for CryptoError {
+    fn from(_error: UnknownCryptoError) -> Self {
+        Self::IncorrectPassword
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::{CryptoError, CryptoResult, Padding};
+    use crate::util::secure::SecretString;
     use once_cell::sync::Lazy;
-    use orion::errors::UnknownCryptoError;
     use std::collections::HashMap;
     use std::fs;
+    use std::io::Read;
     use std::panic;
     use std::path::Path;
     use std::sync::Mutex;
 
-    const CRYPTO_DIR: &'static str = "crypto-test-dir";
+    const CRYPTO_DIR: &str = "crypto-test-dir";
     static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-    fn run_test<T>(test: T) -> ()
-    where T: FnOnce() -> () + panic::UnwindSafe {
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
         let lock = DIR_LOCK.lock().unwrap();
         fs::create_dir_all(CRYPTO_DIR).unwrap();
-        let result = panic::catch_unwind(|| test());
+        let result = panic::catch_unwind(test);
         fs::remove_dir_all(CRYPTO_DIR).unwrap();
         drop(lock);
         assert!(result.is_ok())
     }
 
+    // the decrypted contents are copied out, so that they can be compared
+
+    fn decrypt(data: &[u8], pass: &SecretString) -> CryptoResult<Vec<u8>> {
+        super::decrypt(data, pass).map(|contents| contents.to_vec())
+    }
+
+    fn decrypt_versioned(data: &[u8], pass: &SecretString, ad: &[u8])
+        -> CryptoResult<(u8, Vec<u8>)> {
+        super::decrypt_versioned(data, pass, ad)
+            .map(|(format, contents)| (format, contents.to_vec()))
+    }
+
+    fn pad(data: &[u8], padding: Padding) -> Vec<u8> {
+        let mut padded = Vec::new();
+        super::pad_reader(data, data.len(), padding)
+            .read_to_end(&mut padded)
+            .unwrap();
+        padded
+    }
+
+    fn unpad(data: &[u8]) -> Option<Vec<u8>> {
+        let mut unpadder = super::Unpadder::new(Vec::new());
+        unpadder.write_all(data);
+        unpadder.finish()
+    }
+
     #[test]
     fn should_encrypt_and_decrypt_data_with_same_pass() {
-        let (data, pass) = ("contents", "1234");
+        let (data, pass) = ("contents", &SecretString::from("1234"));
         let data_enc = super::encrypt(data.as_bytes(), pass).unwrap();
-        let data_dec = super::decrypt(&data_enc, pass).unwrap();
+        let data_dec = decrypt(&data_enc, pass).unwrap();
         assert_eq!(String::from_utf8(data_dec).unwrap(), data);
     }
 
     #[test]
     fn should_not_encrypt_and_decrypt_data_with_different_pass() {
-        let (data, pass) = ("contents", "1234");
+        let (data, pass) = ("contents", &SecretString::from("1234"));
         let data_enc = super::encrypt(data.as_bytes(), pass).unwrap();
-        let error = Err(UnknownCryptoError);
-        assert_eq!(super::decrypt(&data_enc, "12345"), error);
+        let error = Err(CryptoError::IncorrectPassword);
+        assert_eq!(decrypt(&data_enc, &SecretString::from("12345")), error);
+    }
+
+    #[test]
+    fn should_not_decrypt_data_with_different_ad() {
+        let (data, pass) = (b"contents", &SecretString::from("1234"));
+        let padding = Padding::Bucket;
+        let data_enc =
+            super::encrypt_padded(data, pass, padding, b"1:001").unwrap();
+        let data_dec = decrypt_versioned(&data_enc, pass, b"1:001");
+        assert_eq!(data_dec, Ok((1, data.to_vec())));
+        let error = Err(CryptoError::IncorrectPassword);
+        let data_dec = decrypt_versioned(&data_enc, pass, b"1:002");
+        assert_eq!(data_dec, error);
+    }
+
+    #[test]
+    fn should_pad_to_size_buckets() {
+        for (size, padding, expected) in [
+            (0, Padding::Bucket, 256),
+            (255, Padding::Bucket, 256),
+            (256, Padding::Bucket, 512),
+            (700, Padding::Bucket, 1024),
+            (0, Padding::Block(100), 100),
+            (99, Padding::Block(100), 100),
+            (100, Padding::Block(100), 200),
+        ] {
+            let data = vec![1; size];
+            let padded = pad(&data, padding);
+            assert_eq!(padded.len(), expected);
+            assert_eq!(unpad(&padded), Some(data));
+        }
+        assert_eq!(unpad(&[1, 0, 0]), None);
+        assert_eq!(unpad(&[0, 0]), None);
+    }
+
+    #[test]
+    fn should_pad_large_size_to_bucket_multiples() {
+        for (size, expected) in [
+            ((1 << 20) - 1, 1 << 20),
+            (1 << 20, 2 << 20),
+            (5 << 20, 6 << 20),
+            ((1 << 32) - 1, 1 << 32),
+        ] {
+            let padded_size = super::get_padded_size(size, Padding::Bucket);
+            assert_eq!(padded_size, expected);
+        }
+    }
+
+    #[test]
+    fn should_hide_length_of_padded_data() {
+        let pass = &SecretString::from("1234");
+        let padding = Padding::Bucket;
+        let short_enc = super::encrypt_padded(b"1234", pass, padding, b"");
+        let long_enc = super::encrypt_padded(&[1; 200], pass, padding, b"");
+        assert_eq!(short_enc.unwrap().len(), long_enc.unwrap().len());
+    }
+
+    #[test]
+    fn should_decrypt_padded_and_unversioned_data() {
+        let pass = &SecretString::from("1234");
+        let padding = Padding::Bucket;
+        let padded_enc =
+            super::encrypt_padded(b"contents", pass, padding, b"").unwrap();
+        let result = decrypt_versioned(&padded_enc, pass, b"");
+        assert_eq!(result, Ok((1, b"contents".to_vec())));
+        for _ in 0..20 {
+            let data_enc = super::encrypt(b"contents", pass).unwrap();
+            let result = decrypt_versioned(&data_enc, pass, b"");
+            assert_eq!(result, Ok((0, b"contents".to_vec())));
+        }
+    }
+
+    #[test]
+    fn should_start_with_header() {
+        let pass = &SecretString::from("1234");
+        let padding = Padding::Bucket;
+        let data_enc =
+            super::encrypt_padded(b"contents", pass, padding, b"").unwrap();
+        assert_eq!(&data_enc[..7], b"VLT\0\x01\x01\x01");
+        let mut newer_enc = data_enc.clone();
+        newer_enc[4] = 2;
+        let error = Err(CryptoError::UnsupportedFormat {
+            format: 2,
+            kdf: 1,
+            cipher: 1,
+        });
+        assert_eq!(decrypt_versioned(&newer_enc, pass, b""), error);
+        let error = Err(CryptoError::IncorrectPassword);
+        let wrong_pass = &SecretString::from("12345");
+        assert_eq!(decrypt_versioned(&data_enc, wrong_pass, b""), error);
+        assert_eq!(decrypt_versioned(&data_enc, pass, b"1"), error);
+    }
+
+    #[test]
+    fn should_stream_contents_in_chunks() {
+        let pass = &SecretString::from("1234");
+        let data = (0..200_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let padding = Padding::Block(1);
+        let data_enc =
+            super::encrypt_padded(&data, pass, padding, b"").unwrap();
+        let chunk_len = super::CHUNK_SIZE + super::ABYTES;
+        let header_len = super::HEADER_LEN + 24;
+        assert_eq!(data_enc.len(), header_len + 3 * chunk_len + 3393 + 17);
+        let result = decrypt_versioned(&data_enc, pass, b"");
+        assert_eq!(result, Ok((1, data)));
+        let error = Err(CryptoError::CorruptContents);
+        let truncated_enc = &data_enc[..header_len + 3 * chunk_len];
+        assert_eq!(decrypt_versioned(truncated_enc, pass, b""), error);
+        let appended_enc = [data_enc.as_slice(), &[0]].concat();
+        assert_eq!(decrypt_versioned(&appended_enc, pass, b""), error);
+        let mut reordered_enc = data_enc.clone();
+        let (chunk1, chunk2) = reordered_enc[header_len + chunk_len..]
+            .split_at_mut(chunk_len);
+        chunk1.swap_with_slice(&mut chunk2[..chunk_len]);
+        assert_eq!(decrypt_versioned(&reordered_enc, pass, b""), error);
     }
 
     #[test]
     fn should_read_non_existent_file_str_with_any_pass() {
+        let pass = &SecretString::from("1234");
         let file_path = Path::new(CRYPTO_DIR).join("key");
         let result = None as Option<String>;
         run_test(|| {
-            assert_eq!(super::read_file(file_path, "1234"), Ok(result));
+            assert_eq!(super::read_file(file_path, pass, b""), Ok(result));
         })
     }
 
     #[test]
     fn should_write_and_read_file_str_with_same_pass() {
-        let (data, pass) = ("contents".to_owned(), "1234");
+        let data = "contents".to_owned();
+        let pass = &SecretString::from("1234");
         let file_path = Path::new(CRYPTO_DIR).join("key");
         run_test(|| {
-            assert_eq!(super::write_file(&file_path, &data, pass), Ok(()));
-            assert_eq!(super::read_file(file_path, pass), Ok(Some(data)));
+            let result = super::write_file(&file_path, &data, pass, b"");
+            assert_eq!(result, Ok(()));
+            assert_eq!(super::read_file(file_path, pass, b""), Ok(Some(data)));
         })
     }
 
     #[test]
     fn should_not_write_and_read_file_str_with_different_pass() {
         type Result = super::CryptoResult<Option<String>>;
-        let (data, pass) = ("contents".to_owned(), "1234");
+        let data = "contents".to_owned();
+        let pass = &SecretString::from("1234");
         let file_path = Path::new(CRYPTO_DIR).join("key");
-        let error = Err(UnknownCryptoError) as Result;
+        let error = Err(CryptoError::IncorrectPassword) as Result;
         run_test(|| {
-            assert_eq!(super::write_file(&file_path, &data, pass), Ok(()));
-            assert_eq!(super::read_file(file_path, "12345"), error);
+            let result = super::write_file(&file_path, &data, pass, b"");
+            assert_eq!(result, Ok(()));
+            let wrong_pass = &SecretString::from("12345");
+            assert_eq!(super::read_file(file_path, wrong_pass, b""), error);
         })
     }
 
     #[test]
     fn should_write_and_read_file_serde_with_same_pass() {
         let data = HashMap::from([("key".to_owned(), 1234_u32)]);
-        let pass = "1234";
+        let pass = &SecretString::from("1234");
         let file_path = Path::new(CRYPTO_DIR).join("key");
         run_test(|| {
-            assert_eq!(super::write_file(&file_path, &data, pass), Ok(()));
-            assert_eq!(super::read_file(file_path, pass), Ok(Some(data)));
+            let result = super::write_file(&file_path, &data, pass, b"");
+            assert_eq!(result, Ok(()));
+            assert_eq!(super::read_file(file_path, pass, b""), Ok(Some(data)));
         })
     }
 
     #[test]
     fn should_encrypt_and_decrypt_file_with_same_pass() {
-        let (data, pass) = ("contents", "1234");
+        let (data, pass) = ("contents", &SecretString::from("1234"));
         let dec_path = Path::new(CRYPTO_DIR).join("key");
         let enc_path = Path::new(CRYPTO_DIR).join("key-enc");
+        let padding = Padding::default();
         run_test(|| {
             fs::write(&dec_path, data).unwrap();
-            assert!(super::encrypt_file(&dec_path, &enc_path, pass).is_ok());
+            let result =
+                super::encrypt_file(&dec_path, &enc_path, pass, padding, b"");
+            assert!(result.is_ok());
             fs::remove_file(&dec_path).unwrap();
-            assert!(super::decrypt_file(enc_path, &dec_path, pass).is_ok());
+            let result = super::decrypt_file(enc_path, &dec_path, pass, b"");
+            assert!(result.is_ok());
             assert_eq!(fs::read_to_string(dec_path).unwrap(), data);
         })
     }
 
+    #[test]
+    fn should_stream_large_file_in_chunks() {
+        let pass = &SecretString::from("1234");
+        let data = (0..300_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let dec_path = Path::new(CRYPTO_DIR).join("key");
+        let enc_path = Path::new(CRYPTO_DIR).join("key-enc");
+        let padding = Padding::default();
+        run_test(|| {
+            fs::write(&dec_path, &data).unwrap();
+            let result =
+                super::encrypt_file(&dec_path, &enc_path, pass, padding, b"");
+            assert!(result.is_ok());
+            let enc_size = fs::metadata(&enc_path).unwrap().len();
+            assert!(enc_size > 524_288 && enc_size < 524_288 + 512);
+            fs::write(&dec_path, "previous").unwrap();
+            let wrong_pass = &SecretString::from("12");
+            let result =
+                super::decrypt_file(&enc_path, &dec_path, wrong_pass, b"");
+            assert_eq!(result, Err(CryptoError::IncorrectPassword));
+            assert_eq!(fs::read(&dec_path).unwrap(), b"previous");
+            let result = super::decrypt_file(&enc_path, &dec_path, pass, b"");
+            assert_eq!(result, Ok(1));
+            assert_eq!(fs::read(&dec_path).unwrap(), data);
+            let file_count = fs::read_dir(CRYPTO_DIR).unwrap().count();
+            assert_eq!(file_count, 2);
+        })
+    }
+
     #[test]
     fn should_not_encrypt_and_decrypt_file_with_different_pass() {
-        let (data, pass) = ("contents", "1234");
+        let (data, pass) = ("contents", &SecretString::from("1234"));
         let dec_path = Path::new(CRYPTO_DIR).join("key");
         let enc_path = Path::new(CRYPTO_DIR).join("key-enc");
-        let error = Err(UnknownCryptoError);
+        let padding = Padding::default();
+        let error = Err(CryptoError::IncorrectPassword);
         run_test(|| {
             fs::write(&dec_path, data).unwrap();
-            assert!(super::encrypt_file(&dec_path, &enc_path, pass).is_ok());
+            let result =
+                super::encrypt_file(&dec_path, &enc_path, pass, padding, b"");
+            assert!(result.is_ok());
             fs::remove_file(&dec_path).unwrap();
-            assert_eq!(super::decrypt_file(enc_path, dec_path, "123"), error);
+            let wrong_pass = &SecretString::from("123");
+            let result =
+                super::decrypt_file(enc_path, dec_path, wrong_pass, b"");
+            assert_eq!(result, error);
         })
     }
 }
@@ -1,41 +1,105 @@
+mod agent;
 mod arg;
+mod backup;
+mod config;
 mod constant;
 mod crc;
 mod crypto;
+mod manifest;
 mod secret;
 mod tui;
 mod util;
 
-use chrono::offset::Local;
+use crate::agent::AgentError;
 use crate::arg::{ParsedArgs, ParserError, HelpGenerator};
-use crate::constant::LOCK_DIR;
-use crate::crc::CrcMismatchError;
-use crate::secret::SecretError;
-use crate::util::zip::Zipper;
-use std::io::{self, Write};
-use termion::input::TermRead;
+use crate::backup::{BackupError, RetentionPolicy};
+use crate::constant::{
+    AGENT_SOCKET, AGENT_TIMEOUT_SECS, BACKUP_DIR, LOCK_DIR, TUI_LOCK_SECS,
+    TUI_PASS_ATTEMPTS,
+};
+use crate::crc::CrcReport;
+use crate::crypto::Padding;
+use crate::manifest::ManifestError;
+use crate::secret::{SecretError, VerifyIssue};
+use crate::util::ext::PathExt;
+use crate::util::algo;
+use crate::util::secure::{SecretBytes, SecretString};
+use crate::util::serde::Serialize;
+use crate::util::zip::{self, ZipperOptions};
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Duration;
+use termion::raw::IntoRawMode;
+use zeroize::Zeroize;
 
-/// Prompts for password in stdin.
-/// Clears prompt after the password is entered.
+/// Reads a line from the reader straight into a secret, as termion's
+/// `read_passwd` would, only without collecting it into a plain string.
+/// - If the input is cancelled or is not valid utf-8, returns an empty value.
+fn read_secret_line<R: BufRead>(reader: R) -> SecretString {
+    let mut bytes = SecretBytes::with_capacity(32);
+    for byte in reader.bytes() {
+        match byte.unwrap() {
+            0 | 3 | 4 => return SecretString::new(),
+            0x7f => bytes.truncate(bytes.len().saturating_sub(1)),
+            b'\n' | b'\r' => break,
+            byte => bytes.extend_from_slice(&[byte]),
+        }
+    }
+    SecretString::from_utf8(bytes).unwrap_or_default()
+}
+
+/// Prompts for a secret value in stdin using the given prompt.
+/// Clears prompt after the value is entered.
 /// In test context, this just returns a default value.
-fn prompt_password() -> String {
+fn prompt_secret(prompt: &str) -> SecretString {
     if !cfg!(test) {
-        let mut stdin = io::stdin();
         let mut stdout = io::stdout();
-        stdout.write_all(b"password: ").unwrap();
+        stdout.write_all(prompt.as_bytes()).unwrap();
         stdout.flush().unwrap();
-        let pass = stdin
-            .read_passwd(&mut stdout)
-            .unwrap()
-            .unwrap_or_default();
+        let raw_stdout = io::stdout().into_raw_mode().unwrap();
+        let pass = read_secret_line(io::stdin().lock());
+        drop(raw_stdout);
         stdout.write_all(b"\r").unwrap();
-        stdout.write_all(" ".repeat(10).as_bytes()).unwrap();
+        stdout.write_all(" ".repeat(prompt.len()).as_bytes()).unwrap();
         stdout.write_all(b"\r").unwrap();
         stdout.flush().unwrap();
         pass
     } else {
-        "1234".to_owned()
+        SecretString::from("1234")
+    }
+}
+
+/// Prompts for a line of input in stdin using the given prompt.
+/// In test context, this just returns an empty value.
+fn prompt_line(prompt: &str) -> String {
+    if !cfg!(test) {
+        let mut stdout = io::stdout();
+        stdout.write_all(prompt.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+        let mut line = String::new();
+        io::stdin().read_line(&mut line).unwrap();
+        line.trim().to_owned()
+    } else {
+        String::new()
+    }
+}
+
+/// Obtains the key from the unlock agent, if it is running.
+/// Prompts for password in stdin otherwise.
+fn prompt_password() -> SecretString {
+    if !cfg!(test) {
+        if let Some(key) = agent::request_key(Path::new(AGENT_SOCKET)) {
+            return key;
+        }
     }
+    prompt_secret("password: ")
+}
+
+/// Prompts for backup password in stdin.
+#[inline]
+fn prompt_backup_password() -> SecretString {
+    prompt_secret("backup password: ")
 }
 
 /// Gets fully formatted help string.
@@ -47,6 +111,20 @@ fn get_help_string() -> String {
     generator.push_line("tui","
         starts vault in interactive mode
         this is the recommended way of using vault
+        locks after '--timeout' seconds of inactivity, 0 to never lock
+        exits after '--attempts' incorrect passwords, 3 by default
+        usage: tui [--timeout <seconds>] [--attempts <count>]
+        -----
+    ");
+    generator.push_line("unlock", "
+        keeps the vault unlocked in a background agent
+        other commands then use it instead of prompting
+        the agent stops after '--timeout' seconds of inactivity
+        usage: unlock [--timeout <seconds>]
+        -----
+    ");
+    generator.push_line("lock", "
+        stops the unlock agent and wipes the key it holds
         -----
     ");
     generator.push_line("get", "
@@ -91,12 +169,70 @@ fn get_help_string() -> String {
     ");
     generator.push_line("crc", "
         checks crc integrity for all paths and contents
+        lists all the untracked, missing and modified files
         passing '--force-update' updates all checksums
-        usage: crc [--force-update]
+        passing '--json' prints the report as json
+        and still fails if any file does not match
+        usage: crc [--force-update] [--json]
+        -----
+    ");
+    generator.push_line("verify", "
+        checks integrity by decrypting all paths and contents
+        reports all the issues instead of stopping at the first
+        -----
+    ");
+    generator.push_line("upgrade", "
+        rewrites the files of older formats into the latest format
+        run it once after updating, as older versions cannot read them
+        afterwards files of the first format are no longer accepted
+        -----
+    ");
+    generator.push_line("config", "
+        shows or changes the vault options
+        passing '--opaque on' stores secrets under random ids
+        and rewrites all the existing secrets
+        passing '--padding' pads secrets to hide their length
+        either to power-of-two buckets, which grow by 1 MiB
+        above 1 MiB, or to <n> byte blocks
+        usage: config [--opaque on|off] [--padding bucket|<n>]
+        -----
+    ");
+    generator.push_line("repair", "
+        repairs the issues found by verify
+        prompts for each issue, unless any flag is passed
+        passing '--delete-orphans' deletes files not in index
+        passing '--attach' re-attaches an orphan file at '--path'
+        passing '--drop-missing' drops paths with missing files
+        passing '--from' replaces corrupt files from the backup
+        usage: repair [--<flag>] [--from <zip-path>]
+                      [--attach <file-name> --path <path>]
         -----
     ");
     generator.push_line("zip", "
         packs the encrypted contents for backup
+        backups are stored in '--dir' (default: vault-backup)
+        passing '--keep' keeps only the latest <n> backups
+        passing '--keep-days' removes backups older than <n> days
+        passing '--incremental' packs only the changed contents
+        passing '--compress' compresses the packed contents
+        passing '--encrypt' encrypts the whole backup separately
+        usage: zip [--<flag>] [--dir <path>] [--keep[-days] <n>]
+        -----
+    ");
+    generator.push_line("backup", "
+        same as zip
+        -----
+    ");
+    generator.push_line("backups", "
+        lists the backups along with their size and integrity
+        passing '--decrypt' also verifies encrypted backups
+        usage: backups list [--dir <path>] [--decrypt]
+        -----
+    ");
+    generator.push_line("restore", "
+        restores the encrypted contents from backup
+        the current contents are kept aside as backup
+        usage: restore <zip-path>
     ");
     generator.push_section("options");
     generator.push_line("--help", "show this help message and exit");
@@ -104,6 +240,239 @@ fn get_help_string() -> String {
     generator.generate()
 }
 
+/// Formats the crc report into a readable list of groups.
+fn format_crc_report(report: &CrcReport) -> String {
+    let groups = [
+        ("untracked", &report.untracked),
+        ("missing", &report.missing),
+        ("modified", &report.modified),
+    ];
+    groups
+        .iter()
+        .filter(|(_, file_names)| !file_names.is_empty())
+        .map(|(group, file_names)| {
+            let lines = file_names
+                .iter()
+                .map(|file_name| format!("    {}", file_name))
+                .collect::<Vec<_>>();
+            format!("{}:\n{}", group, lines.join("\n"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats the manifest error into a readable message.
+fn format_manifest_error(error: &ManifestError) -> String {
+    match error {
+        ManifestError::HashMismatch { file_name } =>
+            format!("'{}' was added, removed or substituted", file_name),
+        ManifestError::InvalidMac =>
+            "signature is missing or invalid".to_owned(),
+        ManifestError::Rollback { generation, known_generation } =>
+            format!("generation {} is older than last seen {}",
+                generation, known_generation),
+    }
+}
+
+/// Formats the unsupported file format into a readable message.
+fn format_unsupported_format(format: u8, kdf: u8, cipher: u8) -> String {
+    format!(
+        "file format {} (kdf {}, cipher {}) is not supported",
+        format, kdf, cipher,
+    ) + "\nupdate vault to a newer version to read it"
+}
+
+/// Formats the verify issue into a readable line.
+fn format_verify_issue(issue: &VerifyIssue) -> String {
+    match issue {
+        VerifyIssue::UndecryptableIndex =>
+            "index: cannot be decrypted".to_owned(),
+        VerifyIssue::MissingFile { secret_path, file_name } =>
+            format!("{} ({}): file is missing", file_name, secret_path),
+        VerifyIssue::UndecryptableFile { secret_path, file_name } =>
+            format!("{} ({}): cannot be decrypted", file_name, secret_path),
+        VerifyIssue::OrphanFile { file_name } =>
+            format!("{}: not found in index", file_name),
+        VerifyIssue::CrcMismatch { file_name } =>
+            format!("{}: crc mismatch found", file_name),
+        VerifyIssue::ManifestMismatch { error } =>
+            format!("manifest: {}", format_manifest_error(error)),
+    }
+}
+
+/// Formats all the verify issues into a readable report.
+fn format_verify_report(issues: &[VerifyIssue]) -> String {
+    issues
+        .iter()
+        .map(format_verify_issue)
+        .chain([format!("{} issues found", issues.len())])
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Repairs requested for the `repair` command through flags.
+struct RepairFlags<'a> {
+    delete_orphans: bool,
+    attach: Option<(&'a str, &'a str)>,
+    drop_missing: bool,
+    zip_path: Option<&'a str>,
+}
+
+impl RepairFlags<'_> {
+    /// Checks whether no repair was requested, so each issue is prompted.
+    fn is_empty(&self) -> bool {
+        !self.delete_orphans
+            && self.attach.is_none()
+            && !self.drop_missing
+            && self.zip_path.is_none()
+    }
+}
+
+/// Single repair to be applied for a verify issue.
+enum RepairAction {
+    Delete(String),
+    Attach(String, String),
+    Drop(String, String),
+    Replace(String),
+    Skip,
+}
+
+/// Chooses the repair for the given verify issue using the given flags.
+/// If no flag was passed, prompts for the repair in stdin instead.
+fn choose_repair(issue: &VerifyIssue, flags: &RepairFlags) -> RepairAction {
+    let prompt = |choices: &str| match flags.is_empty() {
+        true => prompt_line(&format!(
+            "{}\n{} or [s]kip? ",
+            format_verify_issue(issue),
+            choices,
+        )),
+        false => String::new(),
+    };
+    let can_replace = flags.zip_path.is_some();
+    match issue {
+        VerifyIssue::OrphanFile { file_name } => match flags.attach {
+            Some((attached_name, path)) if attached_name == file_name =>
+                RepairAction::Attach(file_name.clone(), path.to_owned()),
+            _ if flags.delete_orphans =>
+                RepairAction::Delete(file_name.clone()),
+            _ => match prompt("[d]elete, [a]ttach").as_str() {
+                "d" => RepairAction::Delete(file_name.clone()),
+                "a" => RepairAction::Attach(
+                    file_name.clone(),
+                    prompt_line("secret path: "),
+                ),
+                _ => RepairAction::Skip,
+            },
+        },
+        VerifyIssue::MissingFile { secret_path, file_name } => {
+            match (can_replace, flags.drop_missing) {
+                (true, _) => RepairAction::Replace(file_name.clone()),
+                (_, true) => RepairAction::Drop(
+                    secret_path.clone(),
+                    file_name.clone(),
+                ),
+                _ => match prompt("[d]rop, [r]eplace from backup").as_str() {
+                    "d" => RepairAction::Drop(
+                        secret_path.clone(),
+                        file_name.clone(),
+                    ),
+                    "r" => RepairAction::Replace(file_name.clone()),
+                    _ => RepairAction::Skip,
+                },
+            }
+        }
+        VerifyIssue::UndecryptableIndex
+        | VerifyIssue::UndecryptableFile { .. }
+        | VerifyIssue::CrcMismatch { .. }
+        | VerifyIssue::ManifestMismatch {
+            error: ManifestError::HashMismatch { .. },
+        } => {
+            let file_name = match issue {
+                VerifyIssue::UndecryptableFile { file_name, .. }
+                | VerifyIssue::CrcMismatch { file_name }
+                | VerifyIssue::ManifestMismatch {
+                    error: ManifestError::HashMismatch { file_name },
+                } => file_name.clone(),
+                _ => "index.vlt".to_owned(),
+            };
+            match can_replace || prompt("[r]eplace from backup") == "r" {
+                true => RepairAction::Replace(file_name),
+                false => RepairAction::Skip,
+            }
+        }
+        VerifyIssue::ManifestMismatch { .. } => RepairAction::Skip,
+    }
+}
+
+/// Repairs all the given verify issues, each using the chosen repair,
+/// then signs the manifest over the repaired files.
+/// Returns a line for each repaired issue.
+fn repair_issues(
+    issues: &[VerifyIssue],
+    flags: &RepairFlags,
+    pass: &SecretString,
+) -> Result<Vec<String>, VaultCliError> {
+    let mut zip_path = flags.zip_path.map(str::to_owned);
+    let mut backup_pass = None;
+    let mut replaced = HashSet::new();
+    let mut repaired_files = Vec::new();
+    let mut lines = Vec::new();
+    for issue in issues {
+        let line = match choose_repair(issue, flags) {
+            RepairAction::Delete(file_name) => {
+                secret::delete_orphan_file(&file_name, pass)?;
+                let line = format!("deleted {}", file_name);
+                repaired_files.push(file_name);
+                line
+            }
+            RepairAction::Attach(file_name, secret_path) => {
+                secret::attach_orphan_file(&file_name, &secret_path, pass)?;
+                let line =
+                    format!("attached {} as {}", file_name, secret_path);
+                repaired_files.push(file_name);
+                repaired_files.push("index.vlt".to_owned());
+                line
+            }
+            RepairAction::Drop(secret_path, file_name) => {
+                secret::drop_missing_entry(&secret_path, pass)?;
+                repaired_files.push(file_name);
+                repaired_files.push("index.vlt".to_owned());
+                format!("dropped {}", secret_path)
+            }
+            RepairAction::Replace(file_name) => {
+                if !replaced.insert(file_name.clone()) {
+                    continue;
+                }
+                let zip_path = zip_path
+                    .get_or_insert_with(|| prompt_line("zip path: "))
+                    .as_str();
+                if backup_pass.is_none() && zip::is_encrypted(zip_path) {
+                    backup_pass = Some(prompt_backup_password());
+                }
+                let contents = backup::read_backup_file(
+                    zip_path,
+                    LOCK_DIR,
+                    &file_name,
+                    backup_pass.as_ref(),
+                )?;
+                secret::replace_locked_file(&file_name, &contents, pass)?;
+                let line = format!("replaced {} from {}", file_name, zip_path);
+                repaired_files.push(file_name);
+                line
+            }
+            RepairAction::Skip => continue,
+        };
+        lines.push(line);
+    }
+    if repaired_files.is_empty() {
+        return Ok(lines);
+    }
+    if let Err(error) = secret::sign_repaired_files(&repaired_files, pass) {
+        lines.push(format!("not signed, {}", VaultCliError::from(error)));
+    }
+    Ok(lines)
+}
+
 /// Testable entry point. Except for the interactive `login` command,
 /// none of the commands directly modify `stdout` or read from `stdin`.
 fn main_app<I>(args: I) -> Result<String, VaultCliError>
@@ -111,7 +480,46 @@ where I: IntoIterator<Item = String> {
     let args = ParsedArgs::from_iter(args);
     match args.get_index(1) {
         Some("tui") => {
-            tui::start_event_loop_blocking();
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&["timeout", "attempts"])?;
+            let timeout = args
+                .parse_value::<u64>("timeout")?
+                .unwrap_or(TUI_LOCK_SECS);
+            let attempts = args
+                .parse_value::<u32>("attempts")?
+                .unwrap_or(TUI_PASS_ATTEMPTS);
+            let timeout = Duration::from_secs(timeout);
+            tui::start_event_loop_blocking(timeout, attempts);
+            Ok("".to_owned())
+        }
+        Some("unlock") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&["timeout"])?;
+            let timeout = args
+                .parse_value::<u64>("timeout")?
+                .unwrap_or(AGENT_TIMEOUT_SECS);
+            let password = prompt_secret("password: ");
+            secret::check_password(&password)?;
+            let key = crypto::pad_password(&password);
+            let timeout_str = timeout.to_string();
+            let command = ["agent", "--timeout", &timeout_str];
+            agent::start(Path::new(AGENT_SOCKET), &key, &command)?;
+            Ok(format!("unlocked, locks after {}s of inactivity", timeout))
+        }
+        Some("lock") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&[])?;
+            agent::lock(Path::new(AGENT_SOCKET))?;
+            Ok("ok".to_owned())
+        }
+        Some("agent") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&["timeout"])?;
+            let timeout = args
+                .parse_value::<u64>("timeout")?
+                .unwrap_or(AGENT_TIMEOUT_SECS);
+            let timeout = Duration::from_secs(timeout);
+            agent::run(Path::new(AGENT_SOCKET), timeout)?;
             Ok("".to_owned())
         }
         Some("get") => {
@@ -120,7 +528,11 @@ where I: IntoIterator<Item = String> {
             args.expect_no_keys_except(&[])?;
             let password = prompt_password();
             let contents = secret::get_secret(path, &password)?;
-            Ok(contents)
+            // written out directly, so that no plain copy of it is made
+            let mut stdout = io::stdout();
+            stdout.write_all(contents.as_bytes()).unwrap();
+            stdout.write_all(b"\n").unwrap();
+            Ok("".to_owned())
         }
         Some("set") => {
             let path = args.expect_index(2, "path")?;
@@ -173,23 +585,203 @@ where I: IntoIterator<Item = String> {
         }
         Some("crc") => {
             args.expect_no_index_over(1)?;
-            args.expect_no_keys_except(&["force-update"])?;
+            args.expect_no_keys_except(&["force-update", "json"])?;
             if args.get_value("force-update").is_some() {
                 crc::update_crc_all(LOCK_DIR)
-            } else {
-                crc::check_crc_all(LOCK_DIR)?
             }
-            Ok("ok".to_owned())
+            let report = crc::check_crc_all(LOCK_DIR);
+            match (report.is_intact(), args.get_value("json").is_some()) {
+                (true, true) => Ok(report.serialize()),
+                (false, true) => Err(report.serialize()),
+                (true, false) => Ok("ok".to_owned()),
+                (false, false) => Err(format_crc_report(&report)
+                    + "\ncheck backups for last correct version"),
+            }
         }
-        Some("zip") => {
+        Some("verify") => {
             args.expect_no_index_over(1)?;
             args.expect_no_keys_except(&[])?;
-            let datestamp = Local::now().format("%Y%m%d");
-            let mut zipper = Zipper::new(format!("vault-{}.zip", datestamp));
-            zipper.zip_dir(LOCK_DIR);
-            zipper.zip_file("vault");
-            let matches = zipper.finish();
-            Ok(matches.join("\n"))
+            let password = prompt_password();
+            let issues = secret::verify_secrets(&password)?;
+            match issues.is_empty() {
+                true => Ok("ok".to_owned()),
+                false => Err(format_verify_report(&issues)),
+            }
+        }
+        Some("upgrade") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&[])?;
+            let password = prompt_password();
+            let upgraded = secret::upgrade_secrets(&password)?;
+            Ok(upgraded
+                .iter()
+                .map(|file_name| format!("upgraded '{}'", file_name))
+                .chain([format!("{} files upgraded", upgraded.len())])
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        Some("config") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&["opaque", "padding"])?;
+            let opaque = match args.get_value("opaque") {
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                Some(_) => Err(ParserError::invalid_value("opaque"))?,
+                None => None,
+            };
+            let padding = match args.get_value("padding") {
+                Some("bucket") => Some(Padding::Bucket),
+                Some(_) => match args.parse_value("padding")? {
+                    Some(block_size) if block_size > 0 =>
+                        Some(Padding::Block(block_size)),
+                    _ => Err(ParserError::invalid_value("padding"))?,
+                },
+                None => None,
+            };
+            if opaque.is_some() || padding.is_some() {
+                let password = prompt_password();
+                if let Some(opaque) = opaque {
+                    secret::set_opaque(opaque, &password)?;
+                }
+                if let Some(padding) = padding {
+                    secret::set_padding(padding, &password)?;
+                }
+            }
+            let config = config::read_config(LOCK_DIR);
+            let padding_str = match config.padding {
+                Padding::Bucket => "bucket".to_owned(),
+                Padding::Block(block_size) => block_size.to_string(),
+            };
+            Ok(format!(
+                "opaque: {}\npadding: {}",
+                if config.opaque { "on" } else { "off" },
+                padding_str,
+            ))
+        }
+        Some("repair") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&[
+                "delete-orphans", "attach", "path", "drop-missing", "from",
+            ])?;
+            let attach = match args.get_value("attach") {
+                Some(_) => Some((
+                    args.expect_value("attach")?,
+                    args.expect_value("path")?,
+                )),
+                None => None,
+            };
+            let flags = RepairFlags {
+                delete_orphans: args.get_value("delete-orphans").is_some(),
+                attach,
+                drop_missing: args.get_value("drop-missing").is_some(),
+                zip_path: args.get_value("from"),
+            };
+            let password = prompt_password();
+            let issues = secret::verify_secrets(&password)?;
+            let repaired = repair_issues(&issues, &flags, &password)?;
+            let issues = secret::verify_secrets(&password)?;
+            let unresolved = issues.iter().map(|issue| {
+                format!("unresolved {}", format_verify_issue(issue))
+            });
+            let lines = repaired
+                .into_iter()
+                .chain(unresolved)
+                .collect::<Vec<_>>();
+            match (issues.is_empty(), lines.is_empty()) {
+                (true, true) => Ok("ok".to_owned()),
+                (true, false) => Ok(lines.join("\n")),
+                (false, _) => Err(lines.join("\n")),
+            }
+        }
+        Some("zip") | Some("backup") => {
+            args.expect_no_index_over(1)?;
+            args.expect_no_keys_except(&[
+                "dir", "incremental", "compress", "encrypt",
+                "keep", "keep-days",
+            ])?;
+            let backup_dir = args.get_value("dir").unwrap_or(BACKUP_DIR);
+            let policy = RetentionPolicy {
+                keep_count: args.parse_value("keep")?,
+                keep_days: args.parse_value("keep-days")?,
+            };
+            let backup_pass = args
+                .get_value("encrypt")
+                .map(|_| prompt_backup_password());
+            let options = ZipperOptions {
+                compress: args.get_value("compress").is_some(),
+                pass: backup_pass.clone(),
+            };
+            let zip_path = match args.get_value("incremental") {
+                Some(_) => backup::create_incremental_backup(
+                    LOCK_DIR,
+                    backup_dir,
+                    options,
+                ),
+                None => backup::create_backup(LOCK_DIR, backup_dir, options),
+            }?;
+            let removed = backup::prune_backups(
+                backup_dir,
+                &policy,
+                backup_pass.as_ref(),
+            )?;
+            let removed_lines = removed
+                .into_iter()
+                .map(|path| format!("removed {}", path));
+            let lines = [zip_path.to_path_str().to_owned()]
+                .into_iter()
+                .chain(removed_lines);
+            Ok(lines.collect::<Vec<_>>().join("\n"))
+        }
+        Some("backups") => {
+            args.expect_index(2, "subcommand")?;
+            args.expect_no_index_over(2)?;
+            args.expect_no_keys_except(&["dir", "decrypt"])?;
+            let backup_dir = args.get_value("dir").unwrap_or(BACKUP_DIR);
+            match args.get_index(2) {
+                Some("list") => {
+                    let backup_pass = args
+                        .get_value("decrypt")
+                        .map(|_| prompt_backup_password());
+                    let backups = backup::list_backups(
+                        LOCK_DIR,
+                        backup_dir,
+                        backup_pass.as_ref(),
+                    );
+                    let lines = backups.into_iter().map(|info| format!(
+                        "{}  {}  {}",
+                        info.name,
+                        algo::format_byte_size(info.size),
+                        match (info.verified, info.encrypted) {
+                            (true, _) => "ok",
+                            (false, true) if backup_pass.is_none() =>
+                                "encrypted",
+                            (false, _) => "corrupt",
+                        },
+                    ));
+                    Ok(lines.collect::<Vec<_>>().join("\n"))
+                }
+                _ => Err(ParserError::invalid_value("subcommand").into()),
+            }
+        }
+        Some("restore") => {
+            let archive_path = args.expect_index(2, "zip-path")?;
+            args.expect_no_index_over(2)?;
+            args.expect_no_keys_except(&[])?;
+            let backup_pass = match zip::is_encrypted(archive_path) {
+                true => Some(prompt_backup_password()),
+                false => None,
+            };
+            let password = prompt_password();
+            let prev_dir = backup::restore_backup(
+                archive_path,
+                LOCK_DIR,
+                &password,
+                backup_pass.as_ref(),
+            )?;
+            Ok(match prev_dir {
+                Some(dir) => format!("ok\nkept old at {}", dir.to_path_str()),
+                None => "ok".to_owned(),
+            })
         }
         Some(_) => {
             Err(ParserError::invalid_value("command").into())
@@ -209,8 +801,12 @@ where I: IntoIterator<Item = String> {
 /// Actual entry point.
 fn main() {
     match main_app(std::env::args()) {
-        Ok(stdout) => if !stdout.is_empty() {
-            println!("{}", stdout);
+        Ok(mut stdout) => {
+            if !stdout.is_empty() {
+                println!("{}", stdout);
+            }
+            // the output may hold the contents of a secret
+            stdout.zeroize();
         }
         Err(err) => {
             eprintln!("{}", err);
@@ -236,10 +832,52 @@ impl From<ParserError> for VaultCliError {
     }
 }
 
-impl From<CrcMismatchError> for VaultCliError {
-    fn from(error: CrcMismatchError) -> Self {
-        format!("crc mismatch found for file path '{}'", error.file_path)
-            + "\ncheck backups for last correct version"
+impl From<BackupError> for VaultCliError {
+    fn from(error: BackupError) -> Self {
+        match error {
+            BackupError::BrokenChain { file_path } =>
+                format!("backup chain is broken at '{}'", file_path)
+                    + "\nbackup was not restored",
+            BackupError::CorruptContents =>
+                "contents were truncated or reordered in backup".to_owned()
+                    + "\nbackup was not restored",
+            BackupError::CrcMismatch { file_path } =>
+                format!("crc mismatch found in backup for '{}'", file_path)
+                    + "\nbackup was not restored",
+            BackupError::IncorrectBackupPassword =>
+                "backup password provided was incorrect".to_owned(),
+            BackupError::IncorrectPassword =>
+                "password provided was incorrect".to_owned(),
+            BackupError::InvalidArchive =>
+                "given zip path is not a valid backup".to_owned(),
+            BackupError::NonExistentFile { file_path } =>
+                format!("file '{}' was not found in backup", file_path),
+            BackupError::ManifestMismatch { error } =>
+                format!("manifest check failed in backup: {}",
+                    format_manifest_error(&error))
+                    + "\nbackup was not restored",
+            BackupError::RemoveFailed { file_path } =>
+                format!("could not remove backup '{}'", file_path),
+            BackupError::RestoreFailed { file_path } =>
+                format!("could not replace '{}'", file_path)
+                    + "\nbackup was not restored",
+            BackupError::UnsupportedFormat { format, kdf, cipher } =>
+                format_unsupported_format(format, kdf, cipher),
+        }
+    }
+}
+
+impl From<AgentError> for VaultCliError {
+    fn from(error: AgentError) -> Self {
+        match error {
+            AgentError::AlreadyRunning =>
+                "vault is already unlocked".to_owned()
+                    + "\nrun 'lock' to stop the unlock agent first",
+            AgentError::NotRunning =>
+                "vault is not unlocked".to_owned(),
+            AgentError::StartFailed =>
+                "unlock agent could not be started".to_owned(),
+        }
     }
 }
 
@@ -249,10 +887,32 @@ impl From<SecretError> for VaultCliError {
             SecretError::CrcMismatch { file_path } =>
                 format!("crc mismatch found for file path '{}'", file_path)
                     + "\ncheck backups for last correct version",
+            SecretError::CorruptContents =>
+                "contents were truncated or reordered".to_owned()
+                    + "\ncheck backups for last correct version",
+            SecretError::ExistentPath =>
+                "given secret path already exists".to_owned(),
             SecretError::IncorrectPassword =>
                 "password provided was incorrect".to_owned(),
+            SecretError::InvalidRepair { file_name } =>
+                format!("file '{}' cannot be repaired that way", file_name)
+                    + "\nrun 'verify' to list the issues",
+            SecretError::ManifestMismatch { error } =>
+                format!("manifest check failed: {}",
+                    format_manifest_error(&error))
+                    + "\ncheck backups for last correct version",
             SecretError::NonExistentPath =>
                 "given secret path does not exist".to_owned(),
+            SecretError::RemoveFailed { file_name } =>
+                format!("file '{}' could not be removed", file_name)
+                    + "\nthe secret is gone, remove the file manually",
+            SecretError::UnboundFile { file_name } =>
+                format!("file '{}' is of the first format, which is no longer",
+                    file_name)
+                    + " accepted since the upgrade"
+                    + "\ncheck backups for last correct version",
+            SecretError::UnsupportedFormat { format, kdf, cipher } =>
+                format_unsupported_format(format, kdf, cipher),
         }
     }
 }
@@ -0,0 +1,378 @@
+use crate::crc;
+use crate::crypto;
+use crate::util::algo;
+use crate::util::secure::SecretString;
+use orion::hazardous::hash::blake2b::Blake2b;
+use orion::{auth, hash};
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Sorted map that maps file names to their hex encoded hash values.
+type DigestMap = BTreeMap<String, String>;
+
+type ManifestResult<T> = Result<T, ManifestError>;
+
+/// Name of the manifest file, stored alongside the index.
+const MANIFEST_FILE: &str = "index.mac";
+
+/// Names of the files which are not covered by the manifest.
+const EXCLUDED_FILES: [&str; 2] = ["index.crc", MANIFEST_FILE];
+
+/// Returns the path to the manifest file in the given directory.
+#[inline]
+fn get_manifest_file_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(MANIFEST_FILE)
+}
+
+/// Returns the path to the file holding the last seen generation.
+/// It is kept outside the given directory, so that rolling back the whole
+/// directory (e.g. through a sync service) is also detected.
+#[inline]
+fn get_generation_file_path(root_dir: &Path) -> PathBuf {
+    root_dir.with_extension("gen")
+}
+
+/// Derives the key used for the manifest from the given password.
+fn derive_key(pass: &SecretString) -> auth::SecretKey {
+    let mut password = SecretString::from("vault-manifest:");
+    password.push_str(&crypto::pad_password(pass));
+    let digest = hash::digest(password.as_bytes()).unwrap();
+    auth::SecretKey::from_slice(digest.as_ref()).unwrap()
+}
+
+/// Computes hash for the given file, same as `hash::digest` would.
+/// The file is read in chunks, so it is never held whole in memory.
+fn compute_hash(file_path: &Path) -> String {
+    let mut reader = BufReader::new(File::open(file_path).unwrap());
+    let mut hasher = Blake2b::new(None, 32).unwrap();
+    loop {
+        let chunk = reader.fill_buf().unwrap();
+        if chunk.is_empty() {
+            let digest = hasher.finalize().unwrap();
+            return algo::encode_hex(digest.as_ref());
+        }
+        hasher.update(chunk).unwrap();
+        let chunk_len = chunk.len();
+        reader.consume(chunk_len);
+    }
+}
+
+/// Computes hash for all files in the given directory (non-recursive),
+/// except for the checksum and manifest files.
+/// - If the given directory does not exist, an empty map is returned.
+fn compute_hash_all(root_dir: &Path) -> DigestMap {
+    let dir_entries = match fs::read_dir(root_dir) {
+        Ok(dir_entries) => dir_entries,
+        Err(_) => return DigestMap::new(),
+    };
+    dir_entries
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().into_string().unwrap())
+        .filter(|file_name| !EXCLUDED_FILES.contains(&file_name.as_str()))
+        .map(|file_name| {
+            let file_hash = compute_hash(&root_dir.join(&file_name));
+            (file_name, file_hash)
+        })
+        .collect()
+}
+
+/// Reads the last seen generation for the given directory.
+/// - If no generation was recorded yet, returns `0`.
+fn read_known_generation(root_dir: &Path) -> u64 {
+    fs::read_to_string(get_generation_file_path(root_dir))
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Serializes the generation and the hashes into the signed manifest body.
+fn serialize_body(generation: u64, digest_map: &DigestMap) -> String {
+    digest_map
+        .iter()
+        .map(|(file_name, hash)| format!("{} {}\n", file_name, hash))
+        .fold(format!("generation {}\n", generation), |accum, line| {
+            accum + &line
+        })
+}
+
+/// Parses the generation and the hashes out of the manifest body.
+/// - If the body is malformed, returns `None`.
+fn deserialize_body(body: &str) -> Option<(u64, DigestMap)> {
+    let mut lines = body.lines();
+    let generation = lines.next()?
+        .strip_prefix("generation ")?
+        .parse()
+        .ok()?;
+    let digest_map = lines
+        .map(|line| line
+            .rsplit_once(' ')
+            .map(|(name, hash)| (name.to_owned(), hash.to_owned()))
+        )
+        .collect::<Option<DigestMap>>()?;
+    Some((generation, digest_map))
+}
+
+/// Reads the manifest file in the given directory.
+/// Returns the signed body and the hex encoded mac.
+/// - If the file does not exist or is malformed, returns `None`.
+fn read_manifest_file(root_dir: &Path) -> Option<(String, String)> {
+    let contents = fs::read_to_string(get_manifest_file_path(root_dir)).ok()?;
+    let (body, mac_line) = contents.trim_end().rsplit_once('\n')?;
+    let mac = mac_line.strip_prefix("mac ")?;
+    Some((format!("{}\n", body), mac.to_owned()))
+}
+
+/// Checks whether the index of the given format is always signed.
+/// Every index written in the latest format is followed by signing the
+/// manifest, so only the index left by older versions may go without one.
+/// The format cannot be changed without the password, unlike the recorded
+/// generation, which is kept in plain text outside the directory.
+#[inline]
+fn is_signed_format(index_format: u8) -> bool {
+    index_format == crypto::FORMAT_VERSION
+}
+
+/// Checks the manifest in the given directory against all of its files,
+/// where the index was decrypted from the given format.
+/// - If there is no manifest, no generation was ever recorded and the index
+///   was written by older versions, the directory is considered unsigned
+///   and the check passes.
+/// - If the mac does not match, returns `InvalidMac`.
+/// - If the generation is older than the last seen one, returns `Rollback`.
+/// - If any file was added, removed or modified, returns `HashMismatch`.
+pub fn check_manifest<P>(root_dir: P, pass: &SecretString, index_format: u8)
+    -> ManifestResult<()>
+where P: AsRef<Path> {
+    let root_dir = root_dir.as_ref();
+    let known_generation = read_known_generation(root_dir);
+    let mismatches =
+        find_mismatches_since(root_dir, pass, known_generation, index_format)?;
+    expect_no_mismatch(mismatches)
+}
+
+/// Lists the files in the given directory which were added, removed or
+/// modified since the manifest was signed, where the index was decrypted
+/// from the given format.
+/// - If the mac does not match, returns `InvalidMac`.
+/// - If the generation is older than the last seen one, returns `Rollback`.
+pub fn find_mismatched_files<P>(
+    root_dir: P,
+    pass: &SecretString,
+    index_format: u8,
+) -> ManifestResult<Vec<String>>
+where P: AsRef<Path> {
+    let root_dir = root_dir.as_ref();
+    let known_generation = read_known_generation(root_dir);
+    find_mismatches_since(root_dir, pass, known_generation, index_format)
+}
+
+/// Checks the manifest in the given directory unpacked from a backup.
+/// Unlike `check_manifest`, older generations are accepted, since rolling
+/// back is the whole point of restoring a backup.
+pub fn check_restored_manifest<P>(
+    root_dir: P,
+    pass: &SecretString,
+    index_format: u8,
+) -> ManifestResult<()>
+where P: AsRef<Path> {
+    let mismatches =
+        find_mismatches_since(root_dir.as_ref(), pass, 0, index_format)?;
+    expect_no_mismatch(mismatches)
+}
+
+/// Fails the check with the first of the mismatched files, if any.
+/// - If any file was added, removed or modified, returns `HashMismatch`.
+fn expect_no_mismatch(mismatches: Vec<String>) -> ManifestResult<()> {
+    match mismatches.into_iter().next() {
+        Some(file_name) => Err(ManifestError::HashMismatch { file_name }),
+        None => Ok(()),
+    }
+}
+
+/// Lists the files in the given directory which do not match the manifest,
+/// accepting generations since the given one.
+fn find_mismatches_since(
+    root_dir: &Path,
+    pass: &SecretString,
+    known_generation: u64,
+    index_format: u8,
+) -> ManifestResult<Vec<String>> {
+    let is_unsigned = known_generation == 0 && !is_signed_format(index_format);
+    let (body, mac) = match read_manifest_file(root_dir) {
+        Some(manifest) => manifest,
+        None if is_unsigned => return Ok(Vec::new()),
+        None => return Err(ManifestError::InvalidMac),
+    };
+    let tag = algo::decode_hex(&mac)
+        .and_then(|mac_bytes| auth::Tag::from_slice(&mac_bytes).ok())
+        .ok_or(ManifestError::InvalidMac)?;
+    auth::authenticate_verify(&tag, &derive_key(pass), body.as_bytes())
+        .map_err(|_| ManifestError::InvalidMac)?;
+    let (generation, stored_hash) = deserialize_body(&body)
+        .ok_or(ManifestError::InvalidMac)?;
+    if generation < known_generation {
+        return Err(ManifestError::Rollback { generation, known_generation });
+    }
+    let computed_hash = compute_hash_all(root_dir);
+    let mismatches = stored_hash
+        .keys()
+        .chain(computed_hash.keys())
+        .filter(|file_name| {
+            stored_hash.get(*file_name) != computed_hash.get(*file_name)
+        })
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    Ok(mismatches.into_iter().collect())
+}
+
+/// Computes hash for all the files in the given directory and signs them
+/// along with the next generation into the manifest file.
+/// Also updates the stored checksum of the manifest file.
+pub fn update_manifest<P: AsRef<Path>>(root_dir: P, pass: &SecretString) {
+    let root_dir = root_dir.as_ref();
+    let stored_generation = read_manifest_file(root_dir)
+        .and_then(|(body, _)| deserialize_body(&body))
+        .map_or(0, |(generation, _)| generation);
+    let known_generation = read_known_generation(root_dir);
+    let generation = cmp::max(stored_generation, known_generation) + 1;
+    let body = serialize_body(generation, &compute_hash_all(root_dir));
+    let tag = auth::authenticate(&derive_key(pass), body.as_bytes()).unwrap();
+    let mac = algo::encode_hex(tag.unprotected_as_bytes());
+    let manifest_file_path = get_manifest_file_path(root_dir);
+    fs::create_dir_all(root_dir).unwrap();
+    fs::write(&manifest_file_path, format!("{}mac {}\n", body, mac)).unwrap();
+    fs::write(get_generation_file_path(root_dir), generation.to_string())
+        .unwrap();
+    crc::update_crc(manifest_file_path, root_dir);
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ManifestError {
+    HashMismatch { file_name: String },
+    InvalidMac,
+    Rollback { generation: u64, known_generation: u64 },
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::secure::SecretString;
+    use once_cell::sync::Lazy;
+    use std::fs;
+    use std::panic;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    const MANIFEST_DIR: &str = "manifest-test-dir";
+    static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
+        let lock = DIR_LOCK.lock().unwrap();
+        let root_dir = Path::new(MANIFEST_DIR);
+        fs::create_dir_all(root_dir).unwrap();
+        let result = panic::catch_unwind(test);
+        fs::remove_dir_all(root_dir).unwrap();
+        fs::remove_file(root_dir.with_extension("gen")).unwrap_or_default();
+        drop(lock);
+        assert!(result.is_ok())
+    }
+
+    #[test]
+    fn should_hash_large_file_in_chunks() {
+        let file_path = Path::new(MANIFEST_DIR).join("001.vlt");
+        let contents = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+        run_test(|| {
+            fs::write(&file_path, &contents).unwrap();
+            let digest = orion::hash::digest(&contents).unwrap();
+            let expected = crate::util::algo::encode_hex(digest.as_ref());
+            assert_eq!(super::compute_hash(&file_path), expected);
+        })
+    }
+
+    #[test]
+    fn should_pass_check_when_unsigned() {
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            fs::write(Path::new(MANIFEST_DIR).join("f1"), "val").unwrap();
+            let result = super::check_manifest(MANIFEST_DIR, pass, 0);
+            assert_eq!(result, Ok(()));
+        })
+    }
+
+    #[test]
+    fn should_not_pass_check_when_signed_index_unsigned() {
+        let pass = &SecretString::from("1234");
+        let format = crate::crypto::FORMAT_VERSION;
+        let error = Err(super::ManifestError::InvalidMac);
+        run_test(|| {
+            fs::write(Path::new(MANIFEST_DIR).join("f1"), "val").unwrap();
+            let result = super::check_manifest(MANIFEST_DIR, pass, format);
+            assert_eq!(result, error);
+        })
+    }
+
+    #[test]
+    fn should_pass_check_when_intact() {
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            fs::write(Path::new(MANIFEST_DIR).join("f1"), "val").unwrap();
+            super::update_manifest(MANIFEST_DIR, pass);
+            let result = super::check_manifest(MANIFEST_DIR, pass, 0);
+            assert_eq!(result, Ok(()));
+        })
+    }
+
+    #[test]
+    fn should_not_pass_check_with_different_pass() {
+        let pass = &SecretString::from("1234");
+        let error = Err(super::ManifestError::InvalidMac);
+        run_test(|| {
+            fs::write(Path::new(MANIFEST_DIR).join("f1"), "val").unwrap();
+            super::update_manifest(MANIFEST_DIR, pass);
+            let wrong_pass = &SecretString::from("4321");
+            let result = super::check_manifest(MANIFEST_DIR, wrong_pass, 0);
+            assert_eq!(result, error);
+        })
+    }
+
+    #[test]
+    fn should_not_pass_check_when_file_substituted() {
+        let pass = &SecretString::from("1234");
+        let root_dir = Path::new(MANIFEST_DIR);
+        let error = Err(super::ManifestError::HashMismatch {
+            file_name: "f2".to_owned(),
+        });
+        run_test(|| {
+            fs::write(root_dir.join("f1"), "val").unwrap();
+            fs::write(root_dir.join("f2"), "val").unwrap();
+            super::update_manifest(root_dir, pass);
+            fs::write(root_dir.join("f2"), "new_val").unwrap();
+            assert_eq!(super::check_manifest(root_dir, pass, 0), error);
+        })
+    }
+
+    #[test]
+    fn should_not_pass_check_when_rolled_back() {
+        let pass = &SecretString::from("1234");
+        let root_dir = Path::new(MANIFEST_DIR);
+        let manifest_file_path = root_dir.join(super::MANIFEST_FILE);
+        let error = Err(super::ManifestError::Rollback {
+            generation: 1,
+            known_generation: 2,
+        });
+        run_test(|| {
+            fs::write(root_dir.join("f1"), "old_val").unwrap();
+            super::update_manifest(root_dir, pass);
+            let old_manifest = fs::read(&manifest_file_path).unwrap();
+            fs::write(root_dir.join("f1"), "new_val").unwrap();
+            super::update_manifest(root_dir, pass);
+            fs::write(root_dir.join("f1"), "old_val").unwrap();
+            fs::write(&manifest_file_path, old_manifest).unwrap();
+            assert_eq!(super::check_manifest(root_dir, pass, 0), error);
+        })
+    }
+}
@@ -1,18 +1,20 @@
+use crate::config::{self, VaultConfig};
 use crate::constant::{LOCK_DIR, UNLOCK_DIR};
 use crate::crc::{self, CrcMismatchError};
-use crate::crypto;
+use crate::crypto::{self, CryptoError, CryptoResult, Padding};
+use crate::manifest::{self, ManifestError};
 use crate::util::ext::{VecExt, PathExt};
 use crate::util::pattern::{Pattern, PatternFilter};
-use orion::errors::UnknownCryptoError;
-use std::collections::HashMap;
+use crate::util::secure::{SecretBytes, SecretString};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 /// Hashmap that maps secret paths to numbers.
 /// These numbers can be used to identify encrypted files.
 pub type IndexMap = HashMap<String, u32>;
 
-type CryptoResult<T> = Result<T, UnknownCryptoError>;
 type SecretResult<T> = Result<T, SecretError>;
 
 /// Returns the path to the index file.
@@ -33,21 +35,93 @@ pub fn get_unlocked_file_path(rel_path: &str) -> PathBuf {
     Path::new(UNLOCK_DIR).join(rel_path)
 }
 
+/// Returns the associated data which the index file is bound to.
+#[inline]
+pub fn get_index_file_ad(config: &VaultConfig) -> Vec<u8> {
+    format!("{}:index", config.vault_id).into_bytes()
+}
+
+/// Returns the associated data which the encrypted file with the given
+/// index is bound to, so that it cannot be moved to another index or vault.
+/// Secret paths are bound through the index file instead, so that renaming
+/// a secret only rewrites its key there.
+#[inline]
+fn get_locked_file_ad(config: &VaultConfig, index: u32) -> Vec<u8> {
+    format!("{}:{}", config.vault_id, index).into_bytes()
+}
+
 /// Reads the contents of the index file into a hashmap.
 /// - If the file does not exist, returns an empty map.
-/// - If the password is incorrect, returns `UnknownCryptoError`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
 #[inline]
-pub fn read_index_file(pass: &str) -> CryptoResult<IndexMap> {
+pub fn read_index_file(pass: &SecretString) -> CryptoResult<IndexMap> {
+    read_index_file_versioned(pass).map(|(_, index_map)| index_map)
+}
+
+/// Reads the format along with the contents of the index file.
+/// - If the file does not exist, returns format 0 and an empty map.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+fn read_index_file_versioned(pass: &SecretString)
+    -> CryptoResult<(u8, IndexMap)> {
     let index_file_path = get_index_file_path();
-    crypto::read_file(index_file_path, pass)
+    let config = config::read_config(LOCK_DIR);
+    let index_ad = get_index_file_ad(&config);
+    crypto::read_file_versioned(index_file_path, pass, &index_ad)
         .map(|val| val.unwrap_or_default())
 }
 
 /// Writes out the hashmap into the index file.
+/// Also updates the stored checksum of the index file.
+/// If the vault has no id yet, one is generated before writing.
 #[inline]
-pub fn write_index_file(map: &IndexMap, pass: &str) -> CryptoResult<()> {
+pub fn write_index_file(
+    map: &IndexMap,
+    pass: &SecretString,
+) -> CryptoResult<()> {
     let index_file_path = get_index_file_path();
-    crypto::write_file(index_file_path, map, pass)
+    let config = config::read_or_init_config(LOCK_DIR);
+    let index_ad = get_index_file_ad(&config);
+    crypto::write_file(&index_file_path, map, pass, &index_ad)?;
+    crc::update_crc(index_file_path, LOCK_DIR);
+    Ok(())
+}
+
+/// Reads the contents of the index file, after checking the integrity
+/// manifest of all the encrypted files against it.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+/// - If the vault was upgraded but the index file is of the first format,
+///   returns `UnboundFile`.
+pub fn read_checked_index_file(pass: &SecretString) -> SecretResult<IndexMap> {
+    let (index_format, index_map) = read_index_file_versioned(pass)?;
+    manifest::check_manifest(LOCK_DIR, pass, index_format)?;
+    let config = config::read_config(LOCK_DIR);
+    let is_bound = index_format == crypto::FORMAT_VERSION;
+    check_bound_file(&get_index_file_path(), is_bound, &config)?;
+    Ok(index_map)
+}
+
+/// Checks the password against the index file and the manifest.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn check_password(pass: &SecretString) -> SecretResult<()> {
+    read_checked_index_file(pass).map(|_| ())
+}
+
+/// Checks whether the vault was created, which happens once its index file
+/// is first written.
+pub fn vault_exists() -> bool {
+    get_index_file_path().exists()
+}
+
+/// Writes out the index file, so that the vault is created with the given
+/// password if it does not exist yet.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+pub fn init_vault(pass: &SecretString) -> SecretResult<()> {
+    let index_map = read_checked_index_file(pass)?;
+    write_index_file(&index_map, pass)?;
+    manifest::update_manifest(LOCK_DIR, pass);
+    Ok(())
 }
 
 /// Reserves an index for a path in the given hashmap.
@@ -63,63 +137,330 @@ pub fn reserve_index(map: &mut IndexMap, path: &str) -> u32 {
     new_index
 }
 
+/// Finds a random index, which is neither zero nor in the given set.
+fn find_random_free_index(used: &HashSet<u32>) -> u32 {
+    loop {
+        let mut bytes = [0; 4];
+        orion::util::secure_rand_bytes(&mut bytes).unwrap();
+        let index = u32::from_le_bytes(bytes);
+        if index != 0 && !used.contains(&index) {
+            return index;
+        }
+    }
+}
+
+/// Reserves an index for a path in the given hashmap.
+/// A random available index is reserved, so that the encrypted file names
+/// give away neither the count nor the order of the secrets.
+pub fn reserve_opaque_index(map: &mut IndexMap, path: &str) -> u32 {
+    let used = map.values().copied().collect::<HashSet<_>>();
+    let new_index = find_random_free_index(&used);
+    map.insert(path.to_owned(), new_index);
+    new_index
+}
+
 /// Gets the index for the given path, or reserves it otherwise.
-pub fn get_or_reserve_index(map: &mut IndexMap, path: &str) -> u32 {
+/// Opaque vaults reserve random indices instead of the least available.
+pub fn get_or_reserve_index(
+    map: &mut IndexMap,
+    path: &str,
+    config: &VaultConfig,
+) -> u32 {
     match map.get(path) {
         Some(value) => value.to_owned(),
+        None if config.opaque => reserve_opaque_index(map, path),
         None => reserve_index(map, path),
     }
 }
 
-/// Returns the secret contents for the given path.
+/// Encrypts the contents into the given encrypted file, binding them to
+/// the associated data. The contents are padded using the vault config.
+fn write_locked_file(
+    enc_path: &Path,
+    contents: &[u8],
+    config: &VaultConfig,
+    pass: &SecretString,
+    ad: &[u8],
+) -> CryptoResult<()> {
+    let contents_enc =
+        crypto::encrypt_padded(contents, pass, config.padding, ad)?;
+    fs::write(enc_path, contents_enc).unwrap();
+    Ok(())
+}
+
+/// Checks that the encrypted file is bound to its associated data, once the
+/// vault was upgraded. Files of the first format are not bound to anything,
+/// so after the upgrade they can only have been swapped in.
+/// - If the vault was upgraded and the file is not bound,
+///   returns `UnboundFile`.
+fn check_bound_file(enc_path: &Path, is_bound: bool, config: &VaultConfig)
+    -> SecretResult<()> {
+    match config.upgraded && !is_bound {
+        true => Err(SecretError::UnboundFile {
+            file_name: enc_path.to_filename_str().to_owned(),
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Decrypts the contents of the given encrypted file.
+/// Returns the format of the file along with its contents.
+/// - If the password or the associated data is incorrect,
+///   returns `IncorrectPassword`.
+/// - If the vault was upgraded but the file is of the first format,
+///   returns `UnboundFile`.
+fn read_locked_file(
+    enc_path: &Path,
+    config: &VaultConfig,
+    pass: &SecretString,
+    ad: &[u8],
+) -> SecretResult<(u8, SecretBytes)> {
+    let contents_enc = fs::read(enc_path).unwrap();
+    let (format, contents) =
+        crypto::decrypt_versioned(&contents_enc, pass, ad)?;
+    check_bound_file(enc_path, format == crypto::FORMAT_VERSION, config)?;
+    Ok((format, contents))
+}
+
+/// Returns the secret contents for the given path, as raw bytes.
 /// - If the path does not exist, returns `NonExistentPath`.
 /// - If the password is incorrect, returns `IncorrectPassword`.
 /// - If the checksum verification fails, returns `CrcMismatch`.
-pub fn get_secret(path: &str, pass: &str) -> SecretResult<String> {
-    let index_map = read_index_file(pass)?;
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn get_secret_bytes(path: &str, pass: &SecretString)
+    -> SecretResult<SecretBytes> {
+    let index_map = read_checked_index_file(pass)?;
     if let Some(enc_index) = index_map.get(path) {
         let enc_path = get_locked_file_path(*enc_index);
         crc::check_crc(&enc_path, LOCK_DIR)?;
-        let contents = crypto::read_file(enc_path, pass)?;
-        Ok(contents.unwrap_or_else(|| "<byte>".to_owned()))
+        let config = config::read_config(LOCK_DIR);
+        let enc_ad = get_locked_file_ad(&config, *enc_index);
+        let (_, contents) =
+            read_locked_file(&enc_path, &config, pass, &enc_ad)?;
+        Ok(contents)
     } else {
         Err(SecretError::NonExistentPath)
     }
 }
 
+/// Returns the secret contents for the given path.
+/// Contents which are not valid utf-8 are replaced with `<byte>`.
+/// - If the path does not exist, returns `NonExistentPath`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn get_secret(path: &str, pass: &SecretString)
+    -> SecretResult<SecretString> {
+    let contents = get_secret_bytes(path, pass)?;
+    Ok(SecretString::from_utf8(contents)
+        .unwrap_or_else(|| SecretString::from("<byte>")))
+}
+
 /// Sets the secret contents for the given path.
 /// - If the password is incorrect, returns `IncorrectPassword`.
-pub fn set_secret(path: &str, contents: &str, pass: &str) -> SecretResult<()> {
-    let mut index_map = read_index_file(pass)?;
-    let enc_index = get_or_reserve_index(&mut index_map, path);
+pub fn set_secret(
+    path: &str,
+    contents: &str,
+    pass: &SecretString,
+) -> SecretResult<()> {
+    let mut index_map = read_checked_index_file(pass)?;
+    let config = config::read_or_init_config(LOCK_DIR);
+    let enc_index = get_or_reserve_index(&mut index_map, path, &config);
     let enc_path = get_locked_file_path(enc_index);
+    let enc_ad = get_locked_file_ad(&config, enc_index);
     write_index_file(&index_map, pass)?;
-    crypto::write_file(&enc_path, contents, pass)?;
+    write_locked_file(&enc_path, contents.as_bytes(), &config, pass, &enc_ad)?;
     crc::update_crc(enc_path, LOCK_DIR);
+    manifest::update_manifest(LOCK_DIR, pass);
     Ok(())
 }
 
 /// Removes the secret contents from the given path.
 /// - If the path does not exist, returns `NonExistentPath`.
 /// - If the password is incorrect, returns `IncorrectPassword`.
-pub fn remove_secret(path: &str, pass: &str) -> SecretResult<()> {
-    let mut index_map = read_index_file(pass)?;
+pub fn remove_secret(path: &str, pass: &SecretString) -> SecretResult<()> {
+    let mut index_map = read_checked_index_file(pass)?;
     if let Some(enc_index) = index_map.get(path) {
         let enc_path = get_locked_file_path(*enc_index);
         index_map.remove(path);
         write_index_file(&index_map, pass)?;
         fs::remove_file(&enc_path).unwrap();
         crc::update_crc(enc_path, LOCK_DIR);
+        manifest::update_manifest(LOCK_DIR, pass);
         Ok(())
     } else {
         Err(SecretError::NonExistentPath)
     }
 }
 
+/// Returns the secret paths for the given entry, which is either a secret
+/// path or a directory path ending with `/`, standing for all the secret
+/// paths below it.
+fn select_entry_paths(index_map: &IndexMap, path: &str) -> Vec<String> {
+    match path.ends_with('/') {
+        true => index_map
+            .keys()
+            .filter(|key| key.starts_with(path))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_sorted(),
+        false if index_map.contains_key(path) => vec![path.to_owned()],
+        false => Vec::new(),
+    }
+}
+
+/// Maps the secret paths of the source entry onto the destination entry.
+/// A directory destination keeps the source paths below it, so that
+/// `dir1/` onto `dir2/` maps `dir1/fil1` to `dir2/fil1`. A directory source
+/// is always mapped onto a directory, even if given without the `/`.
+fn map_entry_paths(src_paths: Vec<String>, src: &str, dst: &str)
+    -> Vec<(String, String)> {
+    src_paths
+        .into_iter()
+        .map(|src_path| {
+            let dst_path = match (src.ends_with('/'), dst.ends_with('/')) {
+                (true, true) => format!("{}{}", dst, &src_path[src.len()..]),
+                (true, false) =>
+                    format!("{}/{}", dst, &src_path[src.len()..]),
+                (false, true) => {
+                    let name_start =
+                        src_path.rfind('/').map_or(0, |index| index + 1);
+                    format!("{}{}", dst, &src_path[name_start..])
+                }
+                (false, false) => dst.to_owned(),
+            };
+            (src_path, dst_path)
+        })
+        .collect()
+}
+
+/// Copies or moves the secrets of the source entry onto the destination.
+/// Moved secrets only get their keys rewritten in the index file, since
+/// encrypted files are bound to their index rather than their path.
+/// Copied secrets are decrypted before anything is written, and then
+/// encrypted into fresh files before the index file refers to them, so that
+/// the vault is left untouched if any of them fails.
+/// Returns the number of secrets transferred.
+fn transfer_entry(src: &str, dst: &str, pass: &SecretString, keep_src: bool)
+    -> SecretResult<usize> {
+    let mut index_map = read_checked_index_file(pass)?;
+    let src_paths = select_entry_paths(&index_map, src);
+    if src_paths.is_empty() {
+        return Err(SecretError::NonExistentPath);
+    }
+    let transfers = map_entry_paths(src_paths, src, dst);
+    let dst_paths = transfers
+        .iter()
+        .map(|(_, dst_path)| dst_path.as_str())
+        .collect::<HashSet<_>>();
+    let is_taken = |path: &str| index_map.contains_key(path)
+        && (keep_src || transfers.iter().all(|(src, _)| src != path));
+    if dst_paths.len() < transfers.len()
+        || dst_paths.iter().any(|path| path.is_empty() || is_taken(path)) {
+        return Err(SecretError::ExistentPath);
+    }
+    if !keep_src {
+        let enc_indices = transfers
+            .iter()
+            .filter_map(|(src_path, _)| index_map.remove(src_path))
+            .collect::<Vec<_>>();
+        let transfers = transfers.iter().zip(enc_indices);
+        for ((_, dst_path), enc_index) in transfers {
+            index_map.insert(dst_path.clone(), enc_index);
+        }
+        write_index_file(&index_map, pass)?;
+        manifest::update_manifest(LOCK_DIR, pass);
+        return Ok(dst_paths.len());
+    }
+    let config = config::read_or_init_config(LOCK_DIR);
+    let contents = transfers
+        .iter()
+        .map(|(src_path, _)| {
+            let enc_index = index_map[src_path];
+            let enc_path = get_locked_file_path(enc_index);
+            crc::check_crc(&enc_path, LOCK_DIR)?;
+            let enc_ad = get_locked_file_ad(&config, enc_index);
+            let (_, contents) =
+                read_locked_file(&enc_path, &config, pass, &enc_ad)?;
+            Ok(contents)
+        })
+        .collect::<SecretResult<Vec<_>>>()?;
+    for ((_, dst_path), contents) in transfers.iter().zip(contents) {
+        let enc_index =
+            get_or_reserve_index(&mut index_map, dst_path, &config);
+        let enc_path = get_locked_file_path(enc_index);
+        let enc_ad = get_locked_file_ad(&config, enc_index);
+        write_locked_file(&enc_path, &contents, &config, pass, &enc_ad)?;
+        crc::update_crc(enc_path, LOCK_DIR);
+    }
+    write_index_file(&index_map, pass)?;
+    manifest::update_manifest(LOCK_DIR, pass);
+    Ok(dst_paths.len())
+}
+
+/// Moves the secret, or all the secrets below a directory path ending
+/// with `/`, onto the destination path.
+/// A destination ending with `/` keeps the source names below it.
+/// Returns the number of secrets moved.
+/// - If the source does not exist, returns `NonExistentPath`.
+/// - If any destination already exists, returns `ExistentPath`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+pub fn move_secret(src: &str, dst: &str, pass: &SecretString)
+    -> SecretResult<usize> {
+    transfer_entry(src, dst, pass, false)
+}
+
+/// Copies the secret, or all the secrets below a directory path ending
+/// with `/`, onto the destination path.
+/// A destination ending with `/` keeps the source names below it.
+/// Returns the number of secrets copied.
+/// - If the source does not exist, returns `NonExistentPath`.
+/// - If any destination already exists, returns `ExistentPath`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+pub fn copy_secret(src: &str, dst: &str, pass: &SecretString)
+    -> SecretResult<usize> {
+    transfer_entry(src, dst, pass, true)
+}
+
+/// Removes the secret, or all the secrets below a directory path ending
+/// with `/`. Returns the number of secrets removed.
+/// - If the path does not exist, returns `NonExistentPath`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If an encrypted file cannot be removed, returns `RemoveFailed`,
+///   after the index file has stopped referring to it.
+pub fn remove_entry(path: &str, pass: &SecretString) -> SecretResult<usize> {
+    let mut index_map = read_checked_index_file(pass)?;
+    let paths = select_entry_paths(&index_map, path);
+    if paths.is_empty() {
+        return Err(SecretError::NonExistentPath);
+    }
+    let enc_paths = paths
+        .iter()
+        .filter_map(|path| index_map.remove(path))
+        .map(get_locked_file_path)
+        .collect::<Vec<_>>();
+    write_index_file(&index_map, pass)?;
+    let mut result = Ok(paths.len());
+    for enc_path in enc_paths {
+        match fs::remove_file(&enc_path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                let file_name = enc_path.to_filename_str().to_owned();
+                result = Err(SecretError::RemoveFailed { file_name });
+            }
+            _ => crc::update_crc(enc_path, LOCK_DIR),
+        }
+    }
+    manifest::update_manifest(LOCK_DIR, pass);
+    result
+}
+
 /// Lists all the secret paths matching the given pattern.
 /// - If the password is incorrect, returns `IncorrectPassword`.
-pub fn list_secret_paths(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
-    let index_map = read_index_file(pass)?;
+pub fn list_secret_paths(pat: &str, pass: &SecretString)
+    -> SecretResult<Vec<String>> {
+    let index_map = read_checked_index_file(pass)?;
     let matches = index_map
         .into_keys()
         .filter_pattern(Pattern::from_str(pat))
@@ -131,8 +472,10 @@ pub fn list_secret_paths(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
 /// writes them into corresponding files in `unlock` directory.
 /// - If the password is incorrect, returns `IncorrectPassword`.
 /// - If the checksum verification fails, returns `CrcMismatch`.
-pub fn get_secret_files(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
-    let index_map = read_index_file(pass)?;
+pub fn get_secret_files(pat: &str, pass: &SecretString)
+    -> SecretResult<Vec<String>> {
+    let index_map = read_checked_index_file(pass)?;
+    let config = config::read_config(LOCK_DIR);
     let matched_str = index_map
         .keys()
         .filter_pattern(Pattern::from_str(pat))
@@ -142,8 +485,13 @@ pub fn get_secret_files(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
             let enc_index = index_map.get(&secret_path).unwrap();
             let enc_path = get_locked_file_path(*enc_index);
             let dec_path = get_unlocked_file_path(&secret_path);
+            let enc_ad = get_locked_file_ad(&config, *enc_index);
             crc::check_crc(&enc_path, LOCK_DIR)?;
-            crypto::decrypt_file(enc_path, dec_path, pass)?;
+            // checked before decrypting, so an unlocked file is never
+            // overwritten by the contents of a swapped file
+            let is_bound = crypto::has_header(&enc_path);
+            check_bound_file(&enc_path, is_bound, &config)?;
+            crypto::decrypt_file(&enc_path, &dec_path, pass, &enc_ad)?;
             Ok(secret_path.to_owned())
         });
     Result::from_iter(matched_str)
@@ -152,23 +500,134 @@ pub fn get_secret_files(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
 /// Encrypts the contents of paths matching the given pattern and
 /// writes them into corresponding secret files in `lock` directory.
 /// - If the password is incorrect, returns `IncorrectPassword`.
-pub fn set_secret_files(pat: &str, pass: &str) -> SecretResult<Vec<String>> {
-    let mut index_map = read_index_file(pass)?;
+pub fn set_secret_files(pat: &str, pass: &SecretString)
+    -> SecretResult<Vec<String>> {
+    let mut index_map = read_checked_index_file(pass)?;
+    let config = config::read_or_init_config(LOCK_DIR);
     let pattern = Pattern::from_str(pat);
     let matched_str = pattern.match_files(Path::new(UNLOCK_DIR))
         .into_sorted()
         .into_iter()
         .map(|pathbuf| {
             let path_str = pathbuf.to_path_str();
-            let enc_index = get_or_reserve_index(&mut index_map, path_str);
+            let enc_index =
+                get_or_reserve_index(&mut index_map, path_str, &config);
             let enc_path = get_locked_file_path(enc_index);
             let dec_path = get_unlocked_file_path(path_str);
+            let enc_ad = get_locked_file_ad(&config, enc_index);
             write_index_file(&index_map, pass)?;
-            crypto::encrypt_file(dec_path, &enc_path, pass)?;
+            crypto::encrypt_file(
+                dec_path,
+                &enc_path,
+                pass,
+                config.padding,
+                &enc_ad,
+            )?;
             crc::update_crc(enc_path, LOCK_DIR);
             Ok(path_str.to_owned())
         });
-    Result::from_iter(matched_str)
+    let matched_str = Result::from_iter(matched_str);
+    manifest::update_manifest(LOCK_DIR, pass);
+    matched_str
+}
+
+/// Switches the vault to or from storing the secrets under random opaque
+/// ids. All the encrypted files are rewritten under their new ids, before
+/// the old ones are removed.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn set_opaque(opaque: bool, pass: &SecretString) -> SecretResult<()> {
+    let index_map = read_checked_index_file(pass)?;
+    let old_config = config::read_or_init_config(LOCK_DIR);
+    if old_config.opaque == opaque {
+        return Ok(());
+    }
+    let new_config = VaultConfig { opaque, ..old_config };
+    let mut used = index_map.values().copied().collect::<HashSet<_>>();
+    let mut new_index_map = IndexMap::new();
+    for (secret_path, old_index) in index_map.iter() {
+        let old_path = get_locked_file_path(*old_index);
+        crc::check_crc(&old_path, LOCK_DIR)?;
+        let old_ad = get_locked_file_ad(&old_config, *old_index);
+        let (_, contents) =
+            read_locked_file(&old_path, &old_config, pass, &old_ad)?;
+        let new_index = match opaque {
+            true => find_random_free_index(&used),
+            false => (1..).find(|index| !used.contains(index)).unwrap(),
+        };
+        let new_path = get_locked_file_path(new_index);
+        let new_ad = get_locked_file_ad(&new_config, new_index);
+        write_locked_file(&new_path, &contents, &new_config, pass, &new_ad)?;
+        crc::update_crc(new_path, LOCK_DIR);
+        used.insert(new_index);
+        new_index_map.insert(secret_path.to_owned(), new_index);
+    }
+    write_index_file(&new_index_map, pass)?;
+    config::write_config(&new_config, LOCK_DIR);
+    for old_index in index_map.values() {
+        let old_path = get_locked_file_path(*old_index);
+        fs::remove_file(&old_path).unwrap();
+        crc::update_crc(old_path, LOCK_DIR);
+    }
+    manifest::update_manifest(LOCK_DIR, pass);
+    Ok(())
+}
+
+/// Changes the padding applied to the secret contents written from now on.
+/// The existing secrets keep their padding until they are written again.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn set_padding(padding: Padding, pass: &SecretString) -> SecretResult<()> {
+    read_checked_index_file(pass)?;
+    let old_config = config::read_config(LOCK_DIR);
+    let new_config = VaultConfig { padding, ..old_config };
+    config::write_config(&new_config, LOCK_DIR);
+    manifest::update_manifest(LOCK_DIR, pass);
+    Ok(())
+}
+
+/// Rewrites the encrypted files written in older formats into the latest
+/// format in place, keeping their contents and indices.
+/// The upgrade is recorded in the signed config, after which files of the
+/// first format, which are not bound to their index, are rejected.
+/// Returns the names of the upgraded files.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the checksum verification fails, returns `CrcMismatch`.
+/// - If the manifest check fails, returns `ManifestMismatch`.
+pub fn upgrade_secrets(pass: &SecretString) -> SecretResult<Vec<String>> {
+    let index_map = read_checked_index_file(pass)?;
+    let config = config::read_or_init_config(LOCK_DIR);
+    let mut upgraded = Vec::new();
+    let enc_indices = index_map.values().copied().collect::<Vec<_>>();
+    for enc_index in enc_indices.into_sorted() {
+        let enc_path = get_locked_file_path(enc_index);
+        let enc_ad = get_locked_file_ad(&config, enc_index);
+        crc::check_crc(&enc_path, LOCK_DIR)?;
+        let (format, contents) =
+            read_locked_file(&enc_path, &config, pass, &enc_ad)?;
+        if format != crypto::FORMAT_VERSION {
+            write_locked_file(&enc_path, &contents, &config, pass, &enc_ad)?;
+            crc::update_crc(&enc_path, LOCK_DIR);
+            upgraded.push(enc_path.to_filename_str().to_owned());
+        }
+    }
+    let index_file_path = get_index_file_path();
+    if let Ok(index_enc) = fs::read(&index_file_path) {
+        let index_ad = get_index_file_ad(&config);
+        let (format, _) =
+            crypto::decrypt_versioned(&index_enc, pass, &index_ad)?;
+        if format != crypto::FORMAT_VERSION {
+            write_index_file(&index_map, pass)?;
+            upgraded.push(index_file_path.to_filename_str().to_owned());
+        }
+    }
+    if !upgraded.is_empty() || !config.upgraded {
+        let new_config = VaultConfig { upgraded: true, ..config };
+        config::write_config(&new_config, LOCK_DIR);
+        manifest::update_manifest(LOCK_DIR, pass);
+    }
+    Ok(upgraded)
 }
 
 /// Removes all files matching the given pattern in the `unlock` directory.
@@ -181,11 +640,294 @@ pub fn clear_secret_files(pat: &str) -> Vec<String> {
         .collect()
 }
 
+/// Parses the index out of the given encrypted file name.
+/// - If the file name is not of an encrypted file, returns `None`.
+fn parse_locked_file_index(file_name: &str) -> Option<u32> {
+    file_name.strip_suffix(".vlt")?.parse().ok()
+}
+
+/// Checks whether the given encrypted file exists, but is not referenced
+/// by any path in the given index.
+fn is_orphan_file(index_map: &IndexMap, file_name: &str) -> bool {
+    let enc_index = parse_locked_file_index(file_name);
+    let is_referenced = index_map
+        .values()
+        .any(|value| Some(*value) == enc_index);
+    let enc_path = Path::new(LOCK_DIR).join(file_name);
+    enc_index.is_some() && !is_referenced && enc_path.is_file()
+}
+
+/// Removes the encrypted file which is not referenced by the index.
+/// The manifest is signed once all the repairs are done.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the file is not an orphan, returns `InvalidRepair`.
+pub fn delete_orphan_file(
+    file_name: &str,
+    pass: &SecretString,
+) -> SecretResult<()> {
+    let index_map = read_index_file(pass)?;
+    if !is_orphan_file(&index_map, file_name) {
+        return Err(SecretError::InvalidRepair {
+            file_name: file_name.to_owned(),
+        });
+    }
+    let enc_path = Path::new(LOCK_DIR).join(file_name);
+    fs::remove_file(&enc_path).unwrap();
+    crc::update_crc(enc_path, LOCK_DIR);
+    Ok(())
+}
+
+/// Re-attaches the encrypted file which is not referenced by the index
+/// under the given secret path.
+/// The manifest is signed once all the repairs are done.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the file is not an orphan, the path already exists or the file
+///   was not encrypted under its index, returns `InvalidRepair`.
+pub fn attach_orphan_file(
+    file_name: &str,
+    secret_path: &str,
+    pass: &SecretString,
+) -> SecretResult<()> {
+    let mut index_map = read_index_file(pass)?;
+    let is_attachable = is_orphan_file(&index_map, file_name)
+        && !index_map.contains_key(secret_path);
+    let enc_index = match parse_locked_file_index(file_name) {
+        Some(enc_index) if is_attachable => enc_index,
+        _ => return Err(SecretError::InvalidRepair {
+            file_name: file_name.to_owned(),
+        }),
+    };
+    let enc_path = get_locked_file_path(enc_index);
+    let config = config::read_config(LOCK_DIR);
+    let enc_ad = get_locked_file_ad(&config, enc_index);
+    let contents_enc = fs::read(&enc_path).unwrap();
+    let is_bound = matches!(
+        crypto::decrypt_versioned(&contents_enc, pass, &enc_ad),
+        Ok((format, _)) if format == crypto::FORMAT_VERSION || !config.upgraded
+    );
+    if !is_bound {
+        return Err(SecretError::InvalidRepair {
+            file_name: file_name.to_owned(),
+        });
+    }
+    index_map.insert(secret_path.to_owned(), enc_index);
+    write_index_file(&index_map, pass)?;
+    crc::update_crc(enc_path, LOCK_DIR);
+    Ok(())
+}
+
+/// Drops the index entry of the given path, whose encrypted file is missing.
+/// The manifest is signed once all the repairs are done.
+/// - If the path does not exist, returns `NonExistentPath`.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If the encrypted file is not missing, returns `InvalidRepair`.
+pub fn drop_missing_entry(
+    secret_path: &str,
+    pass: &SecretString,
+) -> SecretResult<()> {
+    let mut index_map = read_index_file(pass)?;
+    let enc_index = match index_map.get(secret_path) {
+        Some(enc_index) => *enc_index,
+        None => return Err(SecretError::NonExistentPath),
+    };
+    let enc_path = get_locked_file_path(enc_index);
+    if enc_path.exists() {
+        return Err(SecretError::InvalidRepair {
+            file_name: enc_path.to_filename_str().to_owned(),
+        });
+    }
+    index_map.remove(secret_path);
+    write_index_file(&index_map, pass)?;
+    crc::update_crc(enc_path, LOCK_DIR);
+    Ok(())
+}
+
+/// Replaces the encrypted file with the given contents, e.g. with the
+/// matching file from a backup. The index file itself can be replaced too.
+/// The manifest is signed once all the repairs are done.
+/// - If the contents cannot be decrypted, returns `IncorrectPassword`.
+/// - If the file is not referenced by the index, returns `InvalidRepair`.
+pub fn replace_locked_file(
+    file_name: &str,
+    contents: &[u8],
+    pass: &SecretString,
+) -> SecretResult<()> {
+    let index_file_path = get_index_file_path();
+    let config = config::read_config(LOCK_DIR);
+    let enc_ad = if file_name == index_file_path.to_filename_str() {
+        Some(get_index_file_ad(&config))
+    } else {
+        let index_map = read_index_file(pass)?;
+        parse_locked_file_index(file_name)
+            .filter(|enc_index| index_map.values().any(|v| v == enc_index))
+            .map(|enc_index| get_locked_file_ad(&config, enc_index))
+    };
+    let enc_ad = match enc_ad {
+        Some(enc_ad) => enc_ad,
+        None => return Err(SecretError::InvalidRepair {
+            file_name: file_name.to_owned(),
+        }),
+    };
+    let enc_path = Path::new(LOCK_DIR).join(file_name);
+    let (format, _) = crypto::decrypt_versioned(contents, pass, &enc_ad)?;
+    check_bound_file(&enc_path, format == crypto::FORMAT_VERSION, &config)?;
+    fs::write(&enc_path, contents).unwrap();
+    crc::update_crc(enc_path, LOCK_DIR);
+    Ok(())
+}
+
+/// Signs the manifest after the repairs of the given files are done.
+/// Files which were not repaired but do not match the manifest are never
+/// signed along, as they may have been substituted or rolled back.
+/// - If the password is incorrect, returns `IncorrectPassword`.
+/// - If any other file does not match the manifest, or the manifest itself
+///   was tampered with or rolled back, returns `ManifestMismatch`.
+pub fn sign_repaired_files(file_names: &[String], pass: &SecretString)
+    -> SecretResult<()> {
+    let (index_format, _) = read_index_file_versioned(pass)?;
+    let mismatches =
+        manifest::find_mismatched_files(LOCK_DIR, pass, index_format)?;
+    match mismatches.into_iter().find(|name| !file_names.contains(name)) {
+        Some(file_name) => Err(SecretError::ManifestMismatch {
+            error: ManifestError::HashMismatch { file_name },
+        }),
+        None => {
+            manifest::update_manifest(LOCK_DIR, pass);
+            Ok(())
+        }
+    }
+}
+
+/// Lists the names of all the encrypted files in the `lock` directory.
+fn list_locked_file_names() -> Vec<String> {
+    fs::read_dir(LOCK_DIR)
+        .map(|entries| entries
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|file_name| file_name.ends_with(".vlt"))
+            .collect::<Vec<_>>()
+        )
+        .unwrap_or_default()
+        .into_sorted()
+}
+
+/// Verifies that every index entry points at an existing encrypted file
+/// that can be decrypted, and that there are no orphan encrypted files.
+fn verify_index_entries(
+    index_map: &IndexMap,
+    file_names: &[String],
+    pass: &SecretString,
+) -> Vec<VerifyIssue> {
+    let index_file_path = get_index_file_path();
+    let index_file_name = index_file_path.to_filename_str();
+    let config = config::read_config(LOCK_DIR);
+    let mut entries = index_map.iter().collect::<Vec<_>>();
+    entries.sort();
+    let entry_issues = entries
+        .iter()
+        .filter_map(|(secret_path, enc_index)| {
+            let enc_path = get_locked_file_path(**enc_index);
+            let file_name = enc_path.to_filename_str().to_owned();
+            let enc_ad = get_locked_file_ad(&config, **enc_index);
+            let secret_path = secret_path.to_string();
+            match fs::read(&enc_path) {
+                Ok(contents_enc) => match crypto::decrypt_versioned(
+                    &contents_enc,
+                    pass,
+                    &enc_ad,
+                ) {
+                    Ok((format, _))
+                        if format == crypto::FORMAT_VERSION
+                            || !config.upgraded => None,
+                    _ => Some(VerifyIssue::UndecryptableFile {
+                        secret_path,
+                        file_name,
+                    }),
+                }
+                Err(_) => Some(VerifyIssue::MissingFile {
+                    secret_path,
+                    file_name,
+                }),
+            }
+        });
+    let enc_names = entries
+        .iter()
+        .map(|(_, enc_index)| get_locked_file_path(**enc_index))
+        .map(|enc_path| enc_path.to_filename_str().to_owned())
+        .collect::<Vec<_>>();
+    let orphan_issues = file_names
+        .iter()
+        .filter(|name| *name != index_file_name && !enc_names.contains(name))
+        .map(|name| VerifyIssue::OrphanFile { file_name: name.to_owned() });
+    entry_issues.chain(orphan_issues).collect()
+}
+
+/// Verifies the integrity of all the secrets by decrypting everything.
+/// Returns all the issues found, instead of stopping at the first one.
+/// - If the index file matches its checksum but cannot be decrypted,
+///   returns `IncorrectPassword`.
+/// - If the index file is corrupted, the encrypted files are only checked
+///   for checksum mismatches.
+pub fn verify_secrets(pass: &SecretString) -> SecretResult<Vec<VerifyIssue>> {
+    let file_names = list_locked_file_names();
+    let index = read_index_file_versioned(pass);
+    let mut issues = match &index {
+        Ok((_, index_map)) =>
+            verify_index_entries(index_map, &file_names, pass),
+        Err(CryptoError::IncorrectPassword)
+            if crc::check_crc(get_index_file_path(), LOCK_DIR).is_ok() =>
+            return Err(SecretError::IncorrectPassword),
+        Err(_) => vec![VerifyIssue::UndecryptableIndex],
+    };
+    let manifest_issues = match index {
+        Ok((index_format, _)) => {
+            let result =
+                manifest::find_mismatched_files(LOCK_DIR, pass, index_format);
+            match result {
+                Ok(file_names) => file_names
+                    .into_iter()
+                    .map(|file_name| ManifestError::HashMismatch { file_name })
+                    .collect(),
+                Err(error) => vec![error],
+            }
+        }
+        Err(_) => Vec::new(),
+    };
+    let crc_issues = file_names
+        .into_iter()
+        .filter(|name| {
+            let file_path = Path::new(LOCK_DIR).join(name);
+            crc::check_crc(file_path, LOCK_DIR).is_err()
+        })
+        .map(|file_name| VerifyIssue::CrcMismatch { file_name });
+    issues.extend(crc_issues);
+    issues.extend(manifest_issues
+        .into_iter()
+        .map(|error| VerifyIssue::ManifestMismatch { error }));
+    Ok(issues)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyIssue {
+    UndecryptableIndex,
+    MissingFile { secret_path: String, file_name: String },
+    UndecryptableFile { secret_path: String, file_name: String },
+    OrphanFile { file_name: String },
+    CrcMismatch { file_name: String },
+    ManifestMismatch { error: ManifestError },
+}
+
 #[derive(Debug, PartialEq)]
 pub enum SecretError {
+    CorruptContents,
     CrcMismatch { file_path: String },
+    ExistentPath,
     IncorrectPassword,
+    InvalidRepair { file_name: String },
+    ManifestMismatch { error: ManifestError },
     NonExistentPath,
+    RemoveFailed { file_name: String },
+    UnboundFile { file_name: String },
+    UnsupportedFormat { format: u8, kdf: u8, cipher: u8 },
 }
 
 impl From<CrcMismatchError> for SecretError {
@@ -194,15 +936,32 @@ impl From<CrcMismatchError> for SecretError {
     }
 }
 
-impl From<UnknownCryptoError> for SecretError {
-    fn from(_error: UnknownCryptoError) -> Self {
-        Self::IncorrectPassword
+impl From<ManifestError> for SecretError {
+    fn from(error: ManifestError) -> Self {
+        Self::ManifestMismatch { error }
+    }
+}
+
+impl From<CryptoError> for SecretError {
+    fn from(error: CryptoError) -> Self {
+        match error {
+            CryptoError::CorruptContents => Self::CorruptContents,
+            CryptoError::IncorrectPassword => Self::IncorrectPassword,
+            CryptoError::UnsupportedFormat { format, kdf, cipher } =>
+                Self::UnsupportedFormat { format, kdf, cipher },
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::constant::{LOCK_DIR, UNLOCK_DIR};
+    use crate::crc;
+    use crate::crypto;
+    use crate::crypto::Padding;
+    use crate::manifest::{self, ManifestError};
+    use crate::util::secure::SecretString;
+    use crate::util::serde::Serialize;
     use once_cell::sync::Lazy;
     use std::collections::HashMap;
     use std::fs;
@@ -212,18 +971,27 @@ mod test {
 
     static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-    fn run_test<T>(test: T) -> ()
-    where T: FnOnce() -> () + panic::UnwindSafe {
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
         let lock = DIR_LOCK.lock().unwrap();
         fs::create_dir_all(LOCK_DIR).unwrap();
         fs::create_dir_all(UNLOCK_DIR).unwrap();
-        let result = panic::catch_unwind(|| test());
+        let result = panic::catch_unwind(test);
         fs::remove_dir_all(LOCK_DIR).unwrap_or_default();
         fs::remove_dir_all(UNLOCK_DIR).unwrap_or_default();
+        let generation_file_path = Path::new(LOCK_DIR).with_extension("gen");
+        fs::remove_file(generation_file_path).unwrap_or_default();
         drop(lock);
         assert!(result.is_ok())
     }
 
+    /// Returns the secret contents copied out, so that they can be compared.
+    fn get_secret(path: &str, pass: &SecretString)
+        -> super::SecretResult<String> {
+        super::get_secret(path, pass)
+            .map(|contents| contents.as_str().to_owned())
+    }
+
     #[test]
     fn should_reserve_zero_in_empty_map() {
         let mut map = HashMap::new();
@@ -252,9 +1020,10 @@ mod test {
 
     #[test]
     fn should_set_secret() {
+        let pass = &SecretString::from("1234");
         let root_dir = Path::new(LOCK_DIR);
         run_test(|| {
-            super::set_secret("dir1/fil1", "cont1", "1234").unwrap();
+            super::set_secret("dir1/fil1", "cont1", pass).unwrap();
             assert!(fs::read(root_dir.join("001.vlt")).is_ok());
             assert!(fs::read(root_dir.join("index.vlt")).is_ok());
             assert!(fs::read(root_dir.join("index.crc")).is_ok());
@@ -263,56 +1032,113 @@ mod test {
 
     #[test]
     fn should_get_existent_secret_path() {
-        let (test_path, test_val, test_pass) = ("dir1/fil1", "cont1", "1234");
+        let (test_path, test_val) = ("dir1/fil1", "cont1");
+        let test_pass = &SecretString::from("1234");
         run_test(|| {
             super::set_secret(test_path, test_val, test_pass).unwrap();
-            let found_val = super::get_secret(test_path, test_pass).unwrap();
+            let found_val = get_secret(test_path, test_pass).unwrap();
             assert_eq!(found_val, test_val);
         })
     }
 
     #[test]
     fn should_not_get_non_existent_secret_path() {
-        let (test_path, test_pass) = ("dir1/fil1", "1234");
+        let test_path = "dir1/fil1";
+        let test_pass = &SecretString::from("1234");
         let error = Err(super::SecretError::NonExistentPath);
         run_test(|| {
-            assert_eq!(super::get_secret(test_path, test_pass), error);
+            assert_eq!(get_secret(test_path, test_pass), error);
         })
     }
 
     #[test]
     fn should_not_get_secret_using_incorrect_pass() {
-        let (test_path, test_val, test_pass) = ("dir1/fil1", "cont1", "1234");
+        let (test_path, test_val) = ("dir1/fil1", "cont1");
+        let test_pass = &SecretString::from("1234");
         let error = Err(super::SecretError::IncorrectPassword);
         run_test(|| {
             super::set_secret(test_path, test_val, test_pass).unwrap();
-            assert_eq!(super::get_secret(test_path, "4321"), error);
+            let wrong_pass = &SecretString::from("4321");
+            assert_eq!(get_secret(test_path, wrong_pass), error);
         })
     }
 
     #[test]
     fn should_remove_existent_secret_path() {
-        let (test_path, test_val, test_pass) = ("dir1/fil1", "cont1", "1234");
+        let (test_path, test_val) = ("dir1/fil1", "cont1");
+        let test_pass = &SecretString::from("1234");
         let error = Err(super::SecretError::NonExistentPath);
         run_test(|| {
             super::set_secret(test_path, test_val, test_pass).unwrap();
             super::remove_secret(test_path, test_pass).unwrap();
-            assert_eq!(super::get_secret(test_path, test_pass), error);
+            assert_eq!(get_secret(test_path, test_pass), error);
         })
     }
 
     #[test]
     fn should_not_remove_non_existent_secret_path() {
-        let (test_path, test_pass) = ("dir1/fil1", "1234");
+        let test_path = "dir1/fil1";
+        let test_pass = &SecretString::from("1234");
         let error = Err(super::SecretError::NonExistentPath);
         run_test(|| {
             assert_eq!(super::remove_secret(test_path, test_pass), error);
         })
     }
 
+    #[test]
+    fn should_move_secret_keeping_locked_file() {
+        let pass = &SecretString::from("1234");
+        let root_dir = Path::new(LOCK_DIR);
+        run_test(|| {
+            super::set_secret("dir1/fil1", "cont1", pass).unwrap();
+            let contents_enc = fs::read(root_dir.join("001.vlt")).unwrap();
+            let moved = super::move_secret("dir1/fil1", "dir2/", pass);
+            assert_eq!(moved, Ok(1));
+            let error = Err(super::SecretError::NonExistentPath);
+            assert_eq!(get_secret("dir1/fil1", pass), error);
+            assert_eq!(get_secret("dir2/fil1", pass).unwrap(), "cont1");
+            let moved_enc = fs::read(root_dir.join("001.vlt")).unwrap();
+            assert_eq!(moved_enc, contents_enc);
+            assert!(super::verify_secrets(pass).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn should_copy_and_remove_secret_dirs() {
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "cont1", pass).unwrap();
+            super::set_secret("dir1/sdir/fil2", "cont2", pass).unwrap();
+            let copied = super::copy_secret("dir1/", "dir2", pass);
+            assert_eq!(copied, Ok(2));
+            assert_eq!(get_secret("dir1/fil1", pass).unwrap(), "cont1");
+            assert_eq!(get_secret("dir2/sdir/fil2", pass).unwrap(), "cont2");
+            assert_eq!(super::remove_entry("dir1/", pass), Ok(2));
+            let list = super::list_secret_paths("**", pass).unwrap();
+            assert_eq!(list, ["dir2/fil1", "dir2/sdir/fil2"]);
+            assert!(super::verify_secrets(pass).unwrap().is_empty());
+        })
+    }
+
+    #[test]
+    fn should_not_move_secret_onto_existent_path() {
+        let pass = &SecretString::from("1234");
+        let error = Err(super::SecretError::ExistentPath);
+        run_test(|| {
+            super::set_secret("dir1/fil1", "cont1", pass).unwrap();
+            super::set_secret("dir2/fil1", "cont2", pass).unwrap();
+            assert_eq!(super::move_secret("dir1/", "dir2/", pass), error);
+            assert_eq!(super::copy_secret("dir1/fil1", "dir1/fil1", pass),
+                error);
+            assert_eq!(get_secret("dir1/fil1", pass).unwrap(), "cont1");
+            let error = Err(super::SecretError::NonExistentPath);
+            assert_eq!(super::move_secret("dir3/", "dir4/", pass), error);
+        })
+    }
+
     #[test]
     fn should_list_secret_paths_same_level() {
-        let (test_val, pass) = ("contents", "1234");
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
         run_test(|| {
             super::set_secret("dir1/fil1", test_val, pass).unwrap();
             super::set_secret("dir1/fil2", test_val, pass).unwrap();
@@ -325,7 +1151,7 @@ mod test {
 
     #[test]
     fn should_list_secret_paths_recursive() {
-        let (test_val, pass) = ("contents", "1234");
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
         run_test(|| {
             super::set_secret("dir1/fil1", test_val, pass).unwrap();
             super::set_secret("dir1/sdir/fil3", test_val, pass).unwrap();
@@ -339,7 +1165,8 @@ mod test {
     fn should_set_secret_files() {
         let lock_dir = Path::new(LOCK_DIR);
         let unlock_dir = Path::new(UNLOCK_DIR);
-        let (test_path, test_val, pass) = ("path", "contents", "1234");
+        let (test_path, test_val) = ("path", "contents");
+        let pass = &SecretString::from("1234");
         run_test(|| {
             fs::write(unlock_dir.join(test_path), test_val).unwrap();
             let matched = super::set_secret_files(test_path, pass).unwrap();
@@ -353,7 +1180,8 @@ mod test {
     #[test]
     fn should_get_existent_secret_files() {
         let unlock_dir = Path::new(UNLOCK_DIR);
-        let (test_path, test_val, pass) = ("path", "contents", "1234");
+        let (test_path, test_val) = ("path", "contents");
+        let pass = &SecretString::from("1234");
         let test_path_full = unlock_dir.join(test_path);
         run_test(|| {
             fs::write(&test_path_full, test_val).unwrap();
@@ -367,12 +1195,453 @@ mod test {
 
     #[test]
     fn should_not_get_non_existent_secret_files() {
+        let pass = &SecretString::from("1234");
         run_test(|| {
-            let matched = super::get_secret_files("path", "1234").unwrap();
+            let matched = super::get_secret_files("path", pass).unwrap();
             assert_eq!(matched, [] as [&str; 0]);
         })
     }
 
+    #[test]
+    fn should_verify_intact_secrets() {
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
+        run_test(|| {
+            super::set_secret("dir1/fil1", test_val, pass).unwrap();
+            super::set_secret("dir1/fil2", test_val, pass).unwrap();
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_verify_all_secret_issues() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
+        run_test(|| {
+            super::set_secret("dir1/fil1", test_val, pass).unwrap();
+            super::set_secret("dir1/fil2", test_val, pass).unwrap();
+            fs::remove_file(lock_dir.join("001.vlt")).unwrap();
+            fs::write(lock_dir.join("002.vlt"), "corrupt").unwrap();
+            fs::write(lock_dir.join("003.vlt"), "orphan").unwrap();
+            assert_eq!(super::verify_secrets(pass).unwrap(), [
+                super::VerifyIssue::MissingFile {
+                    secret_path: "dir1/fil1".to_owned(),
+                    file_name: "001.vlt".to_owned(),
+                },
+                super::VerifyIssue::UndecryptableFile {
+                    secret_path: "dir1/fil2".to_owned(),
+                    file_name: "002.vlt".to_owned(),
+                },
+                super::VerifyIssue::OrphanFile {
+                    file_name: "003.vlt".to_owned(),
+                },
+                super::VerifyIssue::CrcMismatch {
+                    file_name: "002.vlt".to_owned(),
+                },
+                super::VerifyIssue::CrcMismatch {
+                    file_name: "003.vlt".to_owned(),
+                },
+                super::VerifyIssue::ManifestMismatch {
+                    error: ManifestError::HashMismatch {
+                        file_name: "001.vlt".to_owned(),
+                    },
+                },
+                super::VerifyIssue::ManifestMismatch {
+                    error: ManifestError::HashMismatch {
+                        file_name: "002.vlt".to_owned(),
+                    },
+                },
+                super::VerifyIssue::ManifestMismatch {
+                    error: ManifestError::HashMismatch {
+                        file_name: "003.vlt".to_owned(),
+                    },
+                },
+            ]);
+        })
+    }
+
+    #[test]
+    fn should_verify_undecryptable_index() {
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
+        let issues = [
+            super::VerifyIssue::UndecryptableIndex,
+            super::VerifyIssue::CrcMismatch { file_name: "index.vlt".into() },
+        ];
+        run_test(|| {
+            super::set_secret("dir1/fil1", test_val, pass).unwrap();
+            fs::write(super::get_index_file_path(), "corrupted").unwrap();
+            assert_eq!(super::verify_secrets(pass).unwrap(), issues);
+        })
+    }
+
+    #[test]
+    fn should_not_verify_with_incorrect_password() {
+        let (test_val, pass) = ("contents", &SecretString::from("1234"));
+        let error = Err(super::SecretError::IncorrectPassword);
+        run_test(|| {
+            super::set_secret("dir1/fil1", test_val, pass).unwrap();
+            let wrong_pass = &SecretString::from("4321");
+            assert_eq!(super::verify_secrets(wrong_pass), error);
+        })
+    }
+
+    #[test]
+    fn should_not_get_secret_when_file_substituted() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let (test_path, pass) = ("dir1/fil1", &SecretString::from("1234"));
+        let error = Err(super::SecretError::ManifestMismatch {
+            error: ManifestError::HashMismatch {
+                file_name: "001.vlt".to_owned(),
+            },
+        });
+        run_test(|| {
+            super::set_secret(test_path, "old_val", pass).unwrap();
+            let old_contents = fs::read(lock_dir.join("001.vlt")).unwrap();
+            super::set_secret(test_path, "new_val", pass).unwrap();
+            fs::write(lock_dir.join("001.vlt"), old_contents).unwrap();
+            crc::update_crc(lock_dir.join("001.vlt"), lock_dir);
+            assert_eq!(get_secret(test_path, pass), error);
+        })
+    }
+
+    #[test]
+    fn should_not_get_secret_when_manifest_removed() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let (test_path, pass) = ("dir1/fil1", &SecretString::from("1234"));
+        let error = Err(super::SecretError::ManifestMismatch {
+            error: ManifestError::InvalidMac,
+        });
+        run_test(|| {
+            super::set_secret(test_path, "val", pass).unwrap();
+            fs::remove_file(lock_dir.join("index.mac")).unwrap();
+            fs::remove_file(lock_dir.with_extension("gen")).unwrap();
+            assert_eq!(get_secret(test_path, pass), error);
+        })
+    }
+
+    #[test]
+    fn should_not_get_secret_when_rolled_back() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let (test_path, pass) = ("dir1/fil1", &SecretString::from("1234"));
+        let file_names = ["001.vlt", "index.vlt", "index.crc", "index.mac"];
+        run_test(|| {
+            super::set_secret(test_path, "old_val", pass).unwrap();
+            let old_contents = file_names
+                .map(|file_name| fs::read(lock_dir.join(file_name)).unwrap());
+            super::set_secret(test_path, "new_val", pass).unwrap();
+            for (file_name, contents) in file_names.iter().zip(old_contents) {
+                fs::write(lock_dir.join(file_name), contents).unwrap();
+            }
+            let result = get_secret(test_path, pass);
+            assert!(matches!(result, Err(super::SecretError::ManifestMismatch {
+                error: ManifestError::Rollback { .. },
+            })));
+        })
+    }
+
+    #[test]
+    fn should_delete_orphan_file() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            fs::write(lock_dir.join("002.vlt"), "orphan").unwrap();
+            super::delete_orphan_file("002.vlt", pass).unwrap();
+            assert!(!lock_dir.join("002.vlt").exists());
+            super::sign_repaired_files(&["002.vlt".into()], pass).unwrap();
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_not_delete_referenced_file() {
+        let pass = &SecretString::from("1234");
+        let error = Err(super::SecretError::InvalidRepair {
+            file_name: "001.vlt".to_owned(),
+        });
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            assert_eq!(super::delete_orphan_file("001.vlt", pass), error);
+        })
+    }
+
+    #[test]
+    fn should_attach_orphan_file() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            let contents_enc = crypto::encrypt(b"recovered", pass).unwrap();
+            fs::write(lock_dir.join("002.vlt"), contents_enc).unwrap();
+            super::attach_orphan_file("002.vlt", "dir1/fil2", pass).unwrap();
+            let repaired = ["002.vlt".into(), "index.vlt".into()];
+            super::sign_repaired_files(&repaired, pass).unwrap();
+            let found_val = get_secret("dir1/fil2", pass).unwrap();
+            assert_eq!(found_val, "recovered");
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_attach_orphan_file_only_under_free_path() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::set_secret("dir1/fil2", "recovered", pass).unwrap();
+            let contents_enc = fs::read(lock_dir.join("002.vlt")).unwrap();
+            super::remove_secret("dir1/fil2", pass).unwrap();
+            fs::write(lock_dir.join("002.vlt"), contents_enc).unwrap();
+            let error = Err(super::SecretError::InvalidRepair {
+                file_name: "002.vlt".to_owned(),
+            });
+            let result =
+                super::attach_orphan_file("002.vlt", "dir1/fil1", pass);
+            assert_eq!(result, error);
+            super::attach_orphan_file("002.vlt", "dir1/fil3", pass).unwrap();
+            let repaired = ["002.vlt".into(), "index.vlt".into()];
+            super::sign_repaired_files(&repaired, pass).unwrap();
+            let found_val = get_secret("dir1/fil3", pass).unwrap();
+            assert_eq!(found_val, "recovered");
+        })
+    }
+
+    #[test]
+    fn should_drop_missing_entry() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::set_secret("dir1/fil2", "contents", pass).unwrap();
+            fs::remove_file(lock_dir.join("001.vlt")).unwrap();
+            super::drop_missing_entry("dir1/fil1", pass).unwrap();
+            let repaired = ["001.vlt".into(), "index.vlt".into()];
+            super::sign_repaired_files(&repaired, pass).unwrap();
+            let list = super::list_secret_paths("**", pass).unwrap();
+            assert_eq!(list, ["dir1/fil2"]);
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_replace_corrupt_locked_file() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            let contents = fs::read(lock_dir.join("001.vlt")).unwrap();
+            fs::write(lock_dir.join("001.vlt"), "corrupt").unwrap();
+            let error = Err(super::SecretError::IncorrectPassword);
+            let result = super::replace_locked_file("001.vlt", b"junk", pass);
+            assert_eq!(result, error);
+            super::replace_locked_file("001.vlt", &contents, pass).unwrap();
+            super::sign_repaired_files(&["001.vlt".into()], pass).unwrap();
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_not_sign_repair_along_substituted_file() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        let error = Err(super::SecretError::ManifestMismatch {
+            error: ManifestError::HashMismatch {
+                file_name: "002.vlt".to_owned(),
+            },
+        });
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::set_secret("dir1/fil2", "old_val", pass).unwrap();
+            let old_contents = fs::read(lock_dir.join("002.vlt")).unwrap();
+            super::set_secret("dir1/fil2", "new_val", pass).unwrap();
+            fs::write(lock_dir.join("002.vlt"), old_contents).unwrap();
+            fs::write(lock_dir.join("003.vlt"), "orphan").unwrap();
+            super::delete_orphan_file("003.vlt", pass).unwrap();
+            let result = super::sign_repaired_files(&["003.vlt".into()], pass);
+            assert_eq!(result, error);
+            assert!(get_secret("dir1/fil2", pass).is_err());
+        })
+    }
+
+    #[test]
+    fn should_not_get_secret_from_swapped_files() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents1", pass).unwrap();
+            super::set_secret("dir1/fil2", "contents2", pass).unwrap();
+            let contents1 = fs::read(lock_dir.join("001.vlt")).unwrap();
+            let contents2 = fs::read(lock_dir.join("002.vlt")).unwrap();
+            fs::write(lock_dir.join("001.vlt"), contents2).unwrap();
+            fs::write(lock_dir.join("002.vlt"), contents1).unwrap();
+            crc::update_crc_all(lock_dir);
+            manifest::update_manifest(lock_dir, pass);
+            let error = Err(super::SecretError::IncorrectPassword);
+            assert_eq!(get_secret("dir1/fil1", pass), error);
+            assert_eq!(get_secret("dir1/fil2", pass), error);
+        })
+    }
+
+    #[test]
+    fn should_hide_length_of_secrets() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "1234", pass).unwrap();
+            super::set_secret("dir1/fil2", &"a".repeat(200), pass).unwrap();
+            let short_meta = fs::metadata(lock_dir.join("001.vlt")).unwrap();
+            let long_meta = fs::metadata(lock_dir.join("002.vlt")).unwrap();
+            assert_eq!(short_meta.len(), long_meta.len());
+            super::set_padding(Padding::Block(16), pass).unwrap();
+            super::set_secret("dir1/fil1", "1234", pass).unwrap();
+            let short_meta = fs::metadata(lock_dir.join("001.vlt")).unwrap();
+            assert!(short_meta.len() < long_meta.len());
+            let found_val = get_secret("dir1/fil2", pass).unwrap();
+            assert_eq!(found_val, "a".repeat(200));
+        })
+    }
+
+    #[test]
+    fn should_copy_secret_in_vault_without_config() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        let index_map = HashMap::from([("dir1/fil1".to_owned(), 1)]);
+        run_test(|| {
+            let index_str = index_map.serialize();
+            let index_enc = crypto::encrypt(index_str.as_bytes(), pass);
+            fs::write(lock_dir.join("index.vlt"), index_enc.unwrap()).unwrap();
+            let contents_enc = crypto::encrypt(b"contents", pass).unwrap();
+            fs::write(lock_dir.join("001.vlt"), contents_enc).unwrap();
+            crc::update_crc_all(lock_dir);
+            let copied = super::copy_secret("dir1/fil1", "dir2/", pass);
+            assert_eq!(copied, Ok(1));
+            assert!(fs::read(lock_dir.join("index.cfg")).is_ok());
+            let found_val = get_secret("dir2/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+            let found_val = get_secret("dir1/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+        })
+    }
+
+    #[test]
+    fn should_upgrade_unversioned_files() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        let index_map = HashMap::from([("dir1/fil1".to_owned(), 1)]);
+        run_test(|| {
+            let index_str = index_map.serialize();
+            let index_enc = crypto::encrypt(index_str.as_bytes(), pass);
+            fs::write(lock_dir.join("index.vlt"), index_enc.unwrap()).unwrap();
+            let contents_enc = crypto::encrypt(b"contents", pass).unwrap();
+            fs::write(lock_dir.join("001.vlt"), contents_enc).unwrap();
+            crc::update_crc_all(lock_dir);
+            let upgraded = super::upgrade_secrets(pass).unwrap();
+            assert_eq!(upgraded, vec!["001.vlt", "index.vlt"]);
+            for file_name in ["index.vlt", "001.vlt"] {
+                let contents_enc = fs::read(lock_dir.join(file_name)).unwrap();
+                assert!(contents_enc.starts_with(b"VLT\0"));
+            }
+            let found_val = get_secret("dir1/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+            assert_eq!(super::verify_secrets(pass).unwrap(), vec![]);
+            assert_eq!(super::upgrade_secrets(pass), Ok(vec![]));
+        })
+    }
+
+    #[test]
+    fn should_not_get_unversioned_file_after_upgrade() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            assert_eq!(super::upgrade_secrets(pass), Ok(vec![]));
+            let contents_enc = crypto::encrypt(b"swapped", pass).unwrap();
+            fs::write(lock_dir.join("001.vlt"), contents_enc).unwrap();
+            crc::update_crc_all(lock_dir);
+            manifest::update_manifest(lock_dir, pass);
+            let error = super::SecretError::UnboundFile {
+                file_name: "001.vlt".to_owned(),
+            };
+            let result = get_secret("dir1/fil1", pass);
+            assert_eq!(result.unwrap_err(), error);
+            let result = super::get_secret_files("dir1/fil1", pass);
+            assert_eq!(result.unwrap_err(), error);
+            assert!(!Path::new(UNLOCK_DIR).join("dir1/fil1").exists());
+        })
+    }
+
+    #[test]
+    fn should_get_secret_from_unversioned_files() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        let index_map = HashMap::from([("dir1/fil1".to_owned(), 1)]);
+        run_test(|| {
+            let index_str = index_map.serialize();
+            let index_enc = crypto::encrypt(index_str.as_bytes(), pass);
+            fs::write(lock_dir.join("index.vlt"), index_enc.unwrap()).unwrap();
+            let contents_enc = crypto::encrypt(b"contents", pass).unwrap();
+            fs::write(lock_dir.join("001.vlt"), contents_enc).unwrap();
+            crc::update_crc_all(lock_dir);
+            let found_val = get_secret("dir1/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+        })
+    }
+
+    #[test]
+    fn should_store_opaque_secrets_padded() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::set_secret("dir1/fil2", "other", pass).unwrap();
+            super::set_opaque(true, pass).unwrap();
+            assert!(!lock_dir.join("001.vlt").exists());
+            assert!(!lock_dir.join("002.vlt").exists());
+            super::set_secret("dir1/fil3", "more", pass).unwrap();
+            let index_map = super::read_index_file(pass).unwrap();
+            let file_sizes = index_map
+                .values()
+                .map(|index| super::get_locked_file_path(*index))
+                .map(|path| fs::metadata(path).unwrap().len())
+                .collect::<Vec<_>>();
+            assert_eq!(file_sizes.len(), 3);
+            assert!(file_sizes.iter().all(|size| *size == file_sizes[0]));
+            let found_val = get_secret("dir1/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_switch_back_from_opaque_secrets() {
+        let lock_dir = Path::new(LOCK_DIR);
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::set_opaque(true, pass).unwrap();
+            super::set_opaque(false, pass).unwrap();
+            assert!(lock_dir.join("001.vlt").exists());
+            let found_val = get_secret("dir1/fil1", pass).unwrap();
+            assert_eq!(found_val, "contents");
+            assert_eq!(super::verify_secrets(pass).unwrap(), []);
+        })
+    }
+
+    #[test]
+    fn should_init_vault_with_first_password() {
+        let pass = &SecretString::from("1234");
+        run_test(|| {
+            assert!(!super::vault_exists());
+            super::init_vault(pass).unwrap();
+            assert!(super::vault_exists());
+            assert_eq!(super::check_password(pass), Ok(()));
+            let error = Err(super::SecretError::IncorrectPassword);
+            assert_eq!(super::init_vault(&SecretString::from("4321")), error);
+            super::set_secret("dir1/fil1", "contents", pass).unwrap();
+            super::init_vault(pass).unwrap();
+            assert_eq!(get_secret("dir1/fil1", pass).unwrap(), "contents");
+        })
+    }
+
     #[test]
     fn should_clear_specified_files() {
         let unlock_dir = Path::new(UNLOCK_DIR);
@@ -10,6 +10,7 @@ pub enum Color {
 }
 
 /// Easy cursor control over ANSI terminals.
+#[allow(dead_code)]
 pub trait TermControl {
     fn write_str<S: AsRef<str>>(&mut self, value: S);
     fn clear_line_to_end(&mut self);
@@ -116,7 +117,7 @@ impl<W: Write> TermControl for W {
     }
 
     fn apply_space(&mut self, cols: u16) {
-        (0..cols).for_each(|_| self.write_all(&[' ' as u8]).unwrap());
+        (0..cols).for_each(|_| self.write_all(b" ").unwrap());
     }
 
     fn apply_backspace(&mut self, cols: u16) {
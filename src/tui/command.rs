@@ -0,0 +1,281 @@
+use crate::util::algo;
+
+type CommandResult<T> = Result<T, CommandError>;
+
+/// Kind of argument taken by a command, which decides how it is completed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    Command,
+    Path,
+}
+
+/// Command available in the command line of the explorer.
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub args: &'static [ArgKind],
+    pub required_args: usize,
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+impl CommandSpec {
+    /// Checks whether the command accepts the given number of arguments.
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        (self.required_args..=self.args.len()).contains(&arg_count)
+    }
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "help",
+        args: &[ArgKind::Command],
+        required_args: 0,
+        usage: "help [command]",
+        help: "shows the available commands",
+    },
+    CommandSpec {
+        name: "new",
+        args: &[ArgKind::Path],
+        required_args: 1,
+        usage: "new <path>",
+        help: "creates an empty secret",
+    },
+    CommandSpec {
+        name: "mv",
+        args: &[ArgKind::Path, ArgKind::Path],
+        required_args: 2,
+        usage: "mv <src> <dst>",
+        help: "moves a secret or directory",
+    },
+    CommandSpec {
+        name: "cp",
+        args: &[ArgKind::Path, ArgKind::Path],
+        required_args: 2,
+        usage: "cp <src> <dst>",
+        help: "copies a secret or directory",
+    },
+    CommandSpec {
+        name: "rm",
+        args: &[ArgKind::Path],
+        required_args: 1,
+        usage: "rm <path>",
+        help: "removes a secret or directory",
+    },
+    CommandSpec {
+        name: "r",
+        args: &[],
+        required_args: 0,
+        usage: "r",
+        help: "lays out again at the terminal size",
+    },
+    CommandSpec {
+        name: "q",
+        args: &[],
+        required_args: 0,
+        usage: "q",
+        help: "quits the explorer",
+    },
+];
+
+/// Finds the command with the given name.
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+/// Argument of a command line, along with the byte range it spans.
+#[derive(Debug, PartialEq)]
+struct Arg {
+    value: String,
+    start: usize,
+    end: usize,
+}
+
+/// Splits the command line into arguments separated by whitespace.
+/// Arguments can be quoted with `'` or `"`, and `\` escapes the next char
+/// outside of single quotes.
+/// Returns the arguments, along with the quote left open at the end.
+fn split_args(line: &str) -> (Vec<Arg>, Option<char>) {
+    let mut args = Vec::new();
+    let mut current: Option<Arg> = None;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((index, char)) = chars.next() {
+        if quote.is_none() && char.is_whitespace() {
+            if let Some(mut arg) = current.take() {
+                arg.end = index;
+                args.push(arg);
+            }
+            continue;
+        }
+        let arg = current.get_or_insert_with(|| Arg {
+            value: String::new(),
+            start: index,
+            end: line.len(),
+        });
+        match (quote, char) {
+            (None, '\'' | '"') => quote = Some(char),
+            (Some(open), _) if open == char => quote = None,
+            (None | Some('"'), '\\') => match chars.next() {
+                Some((_, escaped)) => arg.value.push(escaped),
+                None => arg.value.push(char),
+            },
+            _ => arg.value.push(char),
+        }
+    }
+    args.extend(current);
+    (args, quote)
+}
+
+/// Parses the command line into its arguments.
+/// - If a quote is left open, returns `UnclosedQuote`.
+pub fn parse_args(line: &str) -> CommandResult<Vec<String>> {
+    match split_args(line) {
+        (args, None) => Ok(args.into_iter().map(|arg| arg.value).collect()),
+        (_, Some(quote)) => Err(CommandError::UnclosedQuote { quote }),
+    }
+}
+
+/// Quotes the argument if needed, so that it is parsed back as it is.
+pub fn quote_arg(value: &str) -> String {
+    let is_plain = !value.is_empty() && !value.chars().any(|char| {
+        char.is_whitespace() || matches!(char, '\'' | '"' | '\\')
+    });
+    match is_plain {
+        true => value.to_owned(),
+        false => {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("\"{}\"", escaped)
+        }
+    }
+}
+
+/// Returns the candidates for the given argument value.
+/// Paths are completed one level at a time, so that a directory is
+/// offered once for all the secrets below it.
+fn find_candidates<'a, I>(kind: ArgKind, value: &str, paths: I) -> Vec<String>
+where I: Iterator<Item = &'a str> {
+    let mut candidates = match kind {
+        ArgKind::Command => COMMANDS
+            .iter()
+            .filter(|spec| spec.name.starts_with(value))
+            .map(|spec| spec.name.to_owned())
+            .collect::<Vec<_>>(),
+        ArgKind::Path => {
+            let root = if value.starts_with('/') { "/" } else { "" };
+            let prefix = &value[root.len()..];
+            paths
+                .filter(|path| path.starts_with(prefix))
+                .map(|path| {
+                    let rest = &path[prefix.len()..];
+                    let level_len =
+                        rest.find('/').map_or(rest.len(), |index| index + 1);
+                    format!("{}{}", root, &path[..prefix.len() + level_len])
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// Completes the last argument of the command line, using the command
+/// names and the given secret paths.
+/// Returns the completed command lines, in order. A complete argument is
+/// followed by a space, which a directory path is not.
+pub fn complete<'a, I>(line: &str, paths: I) -> Vec<String>
+where I: Iterator<Item = &'a str> {
+    let (mut args, _) = split_args(line);
+    if args.last().map_or(true, |arg| arg.end < line.len()) {
+        let end = line.len();
+        args.push(Arg { value: String::new(), start: end, end });
+    }
+    let kind = match args.len() {
+        1 => Some(ArgKind::Command),
+        arg_count => find_command(&args[0].value)
+            .and_then(|spec| spec.args.get(arg_count - 2).copied()),
+    };
+    let last_arg = args.last().unwrap();
+    let kind = match kind {
+        Some(kind) => kind,
+        None => return Vec::new(),
+    };
+    find_candidates(kind, &last_arg.value, paths)
+        .into_iter()
+        .map(|candidate| {
+            let separator = if candidate.ends_with('/') { "" } else { " " };
+            let quoted = quote_arg(&candidate);
+            format!("{}{}{}", &line[..last_arg.start], quoted, separator)
+        })
+        .collect()
+}
+
+/// Returns the longest common prefix of the given lines.
+pub fn find_common_prefix(lines: &[String]) -> &str {
+    let first = match lines.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+    let mut common_len = lines
+        .iter()
+        .map(|line| algo::find_max_common_index(first, line))
+        .min()
+        .unwrap_or(0);
+    while !first.is_char_boundary(common_len) {
+        common_len -= 1;
+    }
+    &first[..common_len]
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    UnclosedQuote { quote: char },
+}
+
+#[cfg(test)]
+mod test {
+    use super::CommandError;
+
+    const PATHS: [&str; 4] = ["dir1/fil1", "dir1/fil2", "dir2/a b", "top"];
+
+    fn complete(line: &str) -> Vec<String> {
+        super::complete(line, PATHS.iter().copied())
+    }
+
+    #[test]
+    fn should_parse_quoted_args() {
+        let args = super::parse_args(r#" mv 'a b'  "c \"d\"" e\ f "#);
+        assert_eq!(args.unwrap(), ["mv", "a b", "c \"d\"", "e f"]);
+        let args = super::parse_args(r#"rm 'a\b'"#);
+        assert_eq!(args.unwrap(), ["rm", "a\\b"]);
+        let error = Err(CommandError::UnclosedQuote { quote: '"' });
+        assert_eq!(super::parse_args("rm \"a b"), error);
+    }
+
+    #[test]
+    fn should_quote_args_when_needed() {
+        for value in ["dir1/fil1", "a b", "", "it's", "a\\\"b"] {
+            let quoted = super::quote_arg(value);
+            assert_eq!(super::parse_args(&quoted).unwrap(), [value]);
+        }
+        assert_eq!(super::quote_arg("dir1/fil1"), "dir1/fil1");
+    }
+
+    #[test]
+    fn should_complete_command_names() {
+        assert_eq!(complete("m"), ["mv "]);
+        assert_eq!(complete("help r"), ["help r ", "help rm "]);
+        assert!(complete("x").is_empty());
+        assert!(complete("q ").is_empty());
+    }
+
+    #[test]
+    fn should_complete_paths_by_level() {
+        assert_eq!(complete("rm "), ["rm dir1/", "rm dir2/", "rm top "]);
+        assert_eq!(complete("mv /dir1/f"),
+            ["mv /dir1/fil1 ", "mv /dir1/fil2 "]);
+        assert_eq!(complete("cp top \"dir2/a"), ["cp top \"dir2/a b\" "]);
+        let lines = complete("rm dir1/");
+        assert_eq!(super::find_common_prefix(&lines), "rm dir1/fil");
+    }
+}
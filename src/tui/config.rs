@@ -1,37 +1,198 @@
 use crate::util::sync::{SingleLock, SingleLockGuard};
 use once_cell::sync::Lazy;
 use std::io::{self, Write, BufWriter};
-use std::sync::atomic::{AtomicU16, Ordering};
+use std::mem;
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, AtomicU16, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 use super::ansi::{Color, TermControl};
 use termion::event::Event;
-use termion::input::TermRead;
+use termion::input::{MouseTerminal, TermRead};
 use termion::raw::IntoRawMode;
 
 type DynWrite = Box<dyn Write + Send + Sync>;
-type DynEvents = Box<dyn Iterator<Item = Event> + Send + Sync>;
 
 static TERM_CONF: Lazy<TermConfig> = Lazy::new(TermConfig::new);
 
+/// Write end of the pipe which signals the terminal resizes.
+/// The signal handler only writes into it, which is safe to do there.
+static RESIZE_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// Seconds without input after which `Idle` is sent, or 0 to never send it.
+static IDLE_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// Event the pages react to, from either the input or the terminal.
+pub enum TermEvent {
+    Input(Event),
+    Resize,
+    /// Sent once per idle timeout elapsed without any input.
+    Idle,
+}
+
+/// Returns the location of `errno` for the calling thread.
+unsafe fn errno_location() -> *mut c_int {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return libc::__errno_location();
+    #[cfg(any(target_os = "macos", target_os = "ios",
+        target_os = "freebsd", target_os = "dragonfly"))]
+    return libc::__error();
+    #[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+    return libc::__errno();
+}
+
+extern "C" fn handle_resize_signal(_: c_int) {
+    let fd = RESIZE_PIPE.load(Ordering::Relaxed);
+    let byte = [0u8];
+    // the handler may run between a failed call and the read of its errno
+    // in the interrupted thread, so errno is left as it was
+    unsafe {
+        let errno = *errno_location();
+        libc::write(fd, byte.as_ptr() as *const libc::c_void, 1);
+        *errno_location() = errno;
+    }
+}
+
+/// Starts reading the input and waiting for resize signals in the
+/// background, feeding both into the returned channel.
+fn spawn_event_sources() -> Receiver<TermEvent> {
+    let (sender, receiver) = mpsc::channel();
+    let input_sender = sender.clone();
+    thread::spawn(move || {
+        for event in io::stdin().events() {
+            let event = match event {
+                Ok(event) => event,
+                Err(_) => break,
+            };
+            if input_sender.send(TermEvent::Input(event)).is_err() {
+                break;
+            }
+        }
+    });
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return receiver;
+    }
+    // a full pipe already signals a resize, so the handler never blocks
+    unsafe {
+        let flags = libc::fcntl(fds[1], libc::F_GETFL);
+        libc::fcntl(fds[1], libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    RESIZE_PIPE.store(fds[1], Ordering::Relaxed);
+    unsafe {
+        // reads interrupted by the signal are restarted, so that the input
+        // is not cut off on resize
+        let mut action: libc::sigaction = mem::zeroed();
+        let handler = handle_resize_signal as *const ();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, ptr::null_mut());
+    }
+    thread::spawn(move || {
+        // resizes signalled in a row are read at once, and sent as one
+        let mut buf = [0u8; 64];
+        loop {
+            let buf_ptr = buf.as_mut_ptr() as *mut libc::c_void;
+            if unsafe { libc::read(fds[0], buf_ptr, buf.len()) } <= 0 {
+                break;
+            }
+            if sender.send(TermEvent::Resize).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Events received from the event sources, with `Idle` sent in between
+/// once the idle timeout elapsed since the last input.
+pub struct TermEvents {
+    receiver: Receiver<TermEvent>,
+    last_input: Instant,
+}
+
+impl TermEvents {
+    fn new(receiver: Receiver<TermEvent>) -> Self {
+        Self { receiver, last_input: Instant::now() }
+    }
+
+    /// Drops the input received so far, e.g. typed ahead during a delay.
+    pub fn discard_input(&mut self) {
+        while let Ok(event) = self.receiver.try_recv() {
+            if let TermEvent::Resize = event {
+                TermConfig::recalculate_size();
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Option<TermEvent> {
+        let timeout = match IDLE_TIMEOUT_SECS.load(Ordering::Relaxed) {
+            0 => return self.receiver.recv().ok(),
+            secs => Duration::from_secs(secs),
+        };
+        let remaining = timeout.saturating_sub(self.last_input.elapsed());
+        match self.receiver.recv_timeout(remaining) {
+            Ok(event) => {
+                if let TermEvent::Input(_) = event {
+                    self.last_input = Instant::now();
+                }
+                Some(event)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                self.last_input = Instant::now();
+                Some(TermEvent::Idle)
+            }
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+impl Iterator for TermEvents {
+    type Item = TermEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.receive();
+        if let Some(TermEvent::Resize) = event {
+            TermConfig::recalculate_size();
+        }
+        event
+    }
+}
+
 pub struct TermConfig {
     out: SingleLock<DynWrite>,
-    events: SingleLock<DynEvents>,
+    events: SingleLock<TermEvents>,
     rows: AtomicU16,
     cols: AtomicU16,
 }
 
 impl TermConfig {
+    /// Takes over the terminal, switching it to raw mode.
+    /// In test context, the output is only buffered and there is no input,
+    /// so that the widgets can be driven without a terminal.
     pub fn new() -> Self {
+        if cfg!(test) {
+            let (_, receiver) = mpsc::channel();
+            return Self {
+                out: SingleLock::new(Box::new(Vec::new())),
+                events: SingleLock::new(TermEvents::new(receiver)),
+                rows: AtomicU16::new(24),
+                cols: AtomicU16::new(80),
+            };
+        }
         let stdout_raw = io::stdout()
             .into_raw_mode()
             .unwrap();
-        let stdout_buf = BufWriter::with_capacity(256, stdout_raw);
-        let events = io::stdin()
-            .events()
-            .map(|res| res.unwrap());
+        let stdout_mouse = MouseTerminal::from(stdout_raw);
+        let stdout_buf = BufWriter::with_capacity(256, stdout_mouse);
+        let events = TermEvents::new(spawn_event_sources());
         let size = termion::terminal_size().unwrap();
         Self {
             out: SingleLock::new(Box::new(stdout_buf)),
-            events: SingleLock::new(Box::new(events)),
+            events: SingleLock::new(events),
             rows: AtomicU16::new(size.1),
             cols: AtomicU16::new(size.0),
         }
@@ -54,10 +215,16 @@ impl TermConfig {
         TERM_CONF.out.lock()
     }
 
-    pub fn get_events<'a>() -> SingleLockGuard<'a, DynEvents> {
+    pub fn get_events<'a>() -> SingleLockGuard<'a, TermEvents> {
         TERM_CONF.events.lock()
     }
 
+    /// Sets the time without input after which `Idle` is sent.
+    /// - If the timeout is zero, `Idle` is never sent.
+    pub fn set_idle_timeout(timeout: Duration) {
+        IDLE_TIMEOUT_SECS.store(timeout.as_secs(), Ordering::Relaxed);
+    }
+
     pub fn get_size() -> (u16, u16) {
         let rows = TERM_CONF.rows.load(Ordering::Relaxed);
         let cols = TERM_CONF.cols.load(Ordering::Relaxed);
@@ -1,49 +1,135 @@
+use crate::VaultCliError;
 use crate::secret::{self, IndexMap};
+use crate::util::algo;
 use crate::util::pattern::PatternFilter;
+use crate::util::secure::SecretString;
 use crate::util::sync::{SingleLock, SingleLockGuard};
 use once_cell::sync::Lazy;
-use std::collections::VecDeque;
-use super::config::TermConfig;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::mem;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::command::{self, CommandError};
+use super::config::{TermConfig, TermEvent};
 use super::ansi::{Color, TermControl};
-use super::widget::ListWidgetBuilder;
-use termion::event::{Key, Event};
+use super::widget::{
+    ListWidget,
+    ListWidgetBuilder,
+    MatchListWidgetBuilder,
+    SecretViewWidget,
+    SecretViewWidgetBuilder,
+    TextEditWidget,
+    TextEditWidgetBuilder,
+};
+use termion::event::{Key, Event, MouseButton, MouseEvent};
 // use termion::input::TermRead;
 // use termion::raw::IntoRawMode;
 
 static GLOBAL_CACHE: Lazy<GlobalCache> = Lazy::new(GlobalCache::new);
 
+/// Least number of rows and columns the full screen pages are laid out in.
+const MIN_TERM_SIZE: (u16, u16) = (8, 30);
+
+/// Delay before the password can be entered again, multiplied by the
+/// number of incorrect attempts so far.
+const PASS_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest time between the clicks of a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// Number of password attempts before the explorer exits.
+static MAX_PASS_ATTEMPTS: AtomicU32 = AtomicU32::new(1);
+
+/// Number of commands kept in the command line history.
+const COMMAND_HISTORY_LEN: usize = 50;
+
+/// Reads the scroll wheel as the up and down keys, which move the same.
+fn map_wheel_to_keys(event: Event) -> Event {
+    match event {
+        Event::Mouse(MouseEvent::Press(button, ..)) => match button {
+            MouseButton::WheelUp => Event::Key(Key::Up),
+            MouseButton::WheelDown => Event::Key(Key::Down),
+            _ => event,
+        },
+        event => event,
+    }
+}
+
 struct GlobalCache {
-    pass: SingleLock<String>,
+    pass: SingleLock<SecretString>,
     index_map: SingleLock<IndexMap>,
+    command_history: SingleLock<Vec<String>>,
 }
 
 impl GlobalCache {
     pub fn new() -> Self {
         Self {
-            pass: SingleLock::new(String::new()),
-            index_map: SingleLock::new(IndexMap::new())
+            pass: SingleLock::new(SecretString::new()),
+            index_map: SingleLock::new(IndexMap::new()),
+            command_history: SingleLock::new(Vec::new()),
         }
     }
 
-    pub fn load_protected(pass: &str) -> bool {
+    /// Reads the index using the password, after checking it against the
+    /// manifest, and keeps both for the rest of the session.
+    /// - If the password is incorrect, returns `IncorrectPassword`.
+    /// - If the manifest check fails, returns `ManifestMismatch`.
+    pub fn load_protected(pass: &SecretString)
+        -> Result<(), secret::SecretError> {
         let mut current_pass = GLOBAL_CACHE.pass.lock();
-        if current_pass.is_empty() || *current_pass != pass {
-            if let Ok(map) = secret::read_index_file(pass) {
-                let mut index_map = GLOBAL_CACHE.index_map.lock();
-                *current_pass = pass.to_owned();
-                *index_map = map;
-                true
-            } else {
-                false
-            }
-        } else {
-            true
+        if current_pass.is_empty() || current_pass.as_str() != pass.as_str() {
+            let map = secret::read_checked_index_file(pass)?;
+            let mut index_map = GLOBAL_CACHE.index_map.lock();
+            *current_pass = pass.clone();
+            *index_map = map;
         }
+        Ok(())
+    }
+
+    /// Reads the index again, after the secrets were changed.
+    /// - If the manifest check fails, returns `ManifestMismatch`.
+    pub fn reload_index_map() -> Result<(), secret::SecretError> {
+        let pass = GLOBAL_CACHE.pass.lock();
+        let map = secret::read_checked_index_file(&pass)?;
+        *GLOBAL_CACHE.index_map.lock() = map;
+        Ok(())
+    }
+
+    /// Wipes the password, along with everything read using it.
+    pub fn clear() {
+        *GLOBAL_CACHE.pass.lock() = SecretString::new();
+        *GLOBAL_CACHE.index_map.lock() = IndexMap::new();
+        GLOBAL_CACHE.command_history.lock().clear();
     }
 
     pub fn get_index_map<'a>() -> SingleLockGuard<'a, IndexMap> {
         GLOBAL_CACHE.index_map.lock()
     }
+
+    pub fn get_pass<'a>() -> SingleLockGuard<'a, SecretString> {
+        GLOBAL_CACHE.pass.lock()
+    }
+
+    /// Adds the command to the history, unless it is blank or the same as
+    /// the last one. Only the latest commands are kept.
+    pub fn push_command_history(command: &str) {
+        let mut history = GLOBAL_CACHE.command_history.lock();
+        let command = command.trim();
+        if command.is_empty() || history.last().map(String::as_str)
+            == Some(command) {
+            return;
+        }
+        if history.len() >= COMMAND_HISTORY_LEN {
+            history.remove(0);
+        }
+        history.push(command.to_owned());
+    }
+
+    pub fn get_command_history<'a>() -> SingleLockGuard<'a, Vec<String>> {
+        GLOBAL_CACHE.command_history.lock()
+    }
 }
 
 struct HistoryStack {
@@ -63,35 +149,161 @@ impl HistoryStack {
         self.queue.push_front(page);
     }
 
+    /// Closes the page on top, handing its result over to the page below,
+    /// which is returned to render again.
     pub fn pop(&mut self) -> Option<Page> {
-        assert!(self.queue.pop_front().unwrap() == Page::Back);
-        self.queue.pop_front().unwrap();
+        assert!(matches!(self.queue.pop_front(), Some(Page::Back)));
+        let closed = self.queue.pop_front().unwrap();
+        let mut page = self.queue.pop_front();
+        if let Some(page) = &mut page {
+            page.take_over(closed);
+        }
+        page
+    }
+
+    /// Drops the refresh request, returning the page to render again.
+    pub fn refresh(&mut self) -> Option<Page> {
+        assert!(matches!(self.queue.pop_front(), Some(Page::Refresh)));
         self.queue.pop_front()
     }
 
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
     pub fn peek_mut(&mut self) -> &mut Page {
         self.queue.front_mut().unwrap()
     }
 }
 
-#[derive(PartialEq)]
 enum Page {
     Password(PasswordPageParams),
     List(ListPageParams),
     Command(CommandPageParams),
+    Edit(EditPageParams),
+    Find(FindPageParams),
+    Refresh,
+    /// Locks after inactivity, along with the path of the secret whose
+    /// unsaved edits were discarded, if any.
+    Lock(Option<String>),
     Back,
     Exit,
 }
 
 impl Page {
+    /// Takes over the result of the page closed on top of this one.
+    /// The explorer moves to the path chosen in the finder.
+    fn take_over(&mut self, closed: Page) {
+        if let (Self::List(params), Self::Find(closed)) = (self, closed) {
+            if let Some(path) = closed.chosen_path {
+                params.select_path(&GlobalCache::get_index_map(), &path);
+            }
+        }
+    }
+
+    /// Checks whether the page is laid out over the whole terminal.
+    /// The command line is not, but it is only opened over one which is.
+    fn is_full_screen(&self) -> bool {
+        matches!(self, Self::List(_) | Self::Edit(_) | Self::Find(_))
+    }
+
+    /// Waits for the terminal to be large enough for full screen pages,
+    /// showing a message until it is.
+    /// Returns the page to go to instead, if quit or idle while waiting.
+    fn wait_for_min_size() -> Option<Page> {
+        let is_large_enough = || {
+            let (term_rows, term_cols) = TermConfig::get_size();
+            term_rows >= MIN_TERM_SIZE.0 && term_cols >= MIN_TERM_SIZE.1
+        };
+        let render_message = || {
+            let mut out = TermConfig::get_out();
+            let term_cols = TermConfig::get_size().1 as usize;
+            out.clear_screen();
+            out.hide_cursor();
+            ["terminal too small", "press q to quit"]
+                .iter()
+                .enumerate()
+                .for_each(|(row, line)| {
+                    let line = line.chars().take(term_cols);
+                    out.move_cursor_to(row as u16, 0);
+                    out.write_str(line.collect::<String>());
+                });
+            out.flush().unwrap();
+        };
+        if is_large_enough() {
+            return None;
+        }
+        render_message();
+        let mut events = TermConfig::get_events();
+        let next_page = events.find_map(|event| match event {
+            TermEvent::Resize if is_large_enough() => Some(None),
+            TermEvent::Resize => {
+                render_message();
+                None
+            }
+            TermEvent::Input(Event::Key(Key::Char('q'))) =>
+                Some(Some(Page::Exit)),
+            TermEvent::Idle => Some(Some(Page::Lock(None))),
+            TermEvent::Input(_) => None,
+        });
+        drop(events);
+        let next_page = next_page.unwrap_or(Some(Page::Exit));
+        if let Some(Page::Exit) = next_page {
+            let mut out = TermConfig::get_out();
+            out.move_cursor_to(0, 0);
+            out.clear_screen();
+            out.flush().unwrap();
+        }
+        next_page
+    }
+
+    /// Clears the screen and wipes the cache, then asks for the password
+    /// again. Mentions the secret whose unsaved edits were discarded.
+    fn lock(unsaved_path: Option<String>) -> Page {
+        GlobalCache::clear();
+        let mut out = TermConfig::get_out();
+        out.use_color_fg(Color::Reset);
+        out.use_color_bg(Color::Reset);
+        out.move_cursor_to(0, 0);
+        out.clear_screen();
+        out.show_cursor();
+        out.write_str("locked after inactivity");
+        out.move_cursor_to(1, 0);
+        if let Some(path) = unsaved_path {
+            let message = format!("unsaved edits to /{} were discarded", path);
+            out.write_str(message);
+            out.move_cursor_to(2, 0);
+        }
+        out.flush().unwrap();
+        Page::Password(PasswordPageParams)
+    }
+
     pub fn render(self) {
         let mut history = HistoryStack::with_max_capacity(5);
         std::iter::repeat(()).try_fold(self, |page, _| {
             history.push(page);
-            match history.peek_mut() {
+            let page = history.peek_mut();
+            if page.is_full_screen() {
+                if let Some(mut next_page) = Self::wait_for_min_size() {
+                    if let (Self::Lock(unsaved_path), Self::Edit(params)) =
+                        (&mut next_page, &*page) {
+                        *unsaved_path = params.get_unsaved_path();
+                    }
+                    return Some(next_page);
+                }
+            }
+            match page {
                 Self::Password(params) => params.render(),
                 Self::List(params) => params.render(),
                 Self::Command(params) => params.render(),
+                Self::Edit(params) => params.render(),
+                Self::Find(params) => params.render(),
+                Self::Refresh => history.refresh(),
+                Self::Lock(unsaved_path) => {
+                    let unsaved_path = unsaved_path.take();
+                    history.clear();
+                    Some(Self::lock(unsaved_path))
+                }
                 Self::Back => history.pop(),
                 Self::Exit => None,
             }
@@ -103,162 +315,824 @@ impl Page {
 struct PasswordPageParams;
 
 impl PasswordPageParams {
-    pub fn render(&self) -> Option<Page> {
-        let mut out = TermConfig::get_out();
-        let mut pass = String::with_capacity(10);
-        out.write_str("password: ");
+    /// Moves to the start of the next line, below what was written.
+    /// Each line of a message spanning several starts at the left edge.
+    fn write_line<W: Write>(out: &mut W, message: &str) {
+        for line in message.split('\n') {
+            out.write_str(line);
+            out.write_str("\n");
+            out.move_cursor_to_horiz(0);
+        }
+    }
+
+    /// Reads the password, showing one `*` for each char typed.
+    /// Returns `None` if quit with esc or ctrl-c instead.
+    fn read_password<W: Write>(out: &mut W, prompt: &str)
+        -> Option<SecretString> {
+        let mut pass = SecretString::with_capacity(10);
+        out.write_str(prompt);
         out.flush().unwrap();
-        TermConfig::get_events().find_map(|event| match event {
-            Event::Key(Key::Char('\n')) => {
-                if GlobalCache::load_protected(&pass) {
-                    Some(Page::List(ListPageParams::new()))
-                } else {
-                    out.apply_backspace(pass.len() as u16);
-                    out.write_str("incorrect\n");
-                    out.move_cursor_to_horiz(0);
+        let is_entered = TermConfig::get_events().find_map(|event| {
+            let event = match event {
+                TermEvent::Input(event) => event,
+                TermEvent::Resize | TermEvent::Idle => return None,
+            };
+            match event {
+                Event::Key(Key::Char('\n')) => Some(true),
+                Event::Key(Key::Esc | Key::Ctrl('c')) => Some(false),
+                Event::Key(Key::Char(char)) => {
+                    pass.push(char);
+                    out.write_str("*");
                     out.flush().unwrap();
-                    Some(Page::Exit)
+                    None
                 }
+                Event::Key(Key::Backspace) if pass.pop().is_some() => {
+                    out.apply_backspace(1);
+                    out.flush().unwrap();
+                    None
+                }
+                _ => None
             }
-            Event::Key(Key::Char(char)) => {
-                pass.push(char);
-                out.write_str("*");
+        });
+        out.apply_backspace(pass.len() as u16);
+        match is_entered {
+            Some(true) => Some(pass),
+            _ => {
+                Self::write_line(out, "");
                 out.flush().unwrap();
                 None
             }
-            Event::Key(Key::Backspace) if pass.pop().is_some() => {
-                out.apply_backspace(1);
-                out.flush().unwrap();
-                None
+        }
+    }
+
+    /// Checks whether the letters typed are all uppercase, which is likely
+    /// when caps lock is on.
+    fn is_caps_lock_likely(pass: &SecretString) -> bool {
+        let mut letters = pass
+            .chars()
+            .filter(|char| char.is_alphabetic())
+            .peekable();
+        letters.peek().is_some() && letters.all(char::is_uppercase)
+    }
+
+    /// Asks for the password of a vault which does not exist yet, twice
+    /// so that a typo does not lock it for good, then creates the vault.
+    fn render_new_vault<W: Write>(out: &mut W) -> Option<Page> {
+        Self::write_line(out, "no vault found, this password creates one");
+        loop {
+            let pass = Self::read_password(out, "password: ")?;
+            if pass.is_empty() {
+                Self::write_line(out, "empty, try again");
+                continue;
             }
-            _ => None
-        })
+            Self::write_line(out, "");
+            let confirmed = Self::read_password(out, "confirm password: ")?;
+            if pass.as_str() != confirmed.as_str() {
+                Self::write_line(out, "not matching, try again");
+                continue;
+            }
+            Self::write_line(out, "");
+            out.flush().unwrap();
+            let result = secret::init_vault(&pass)
+                .and_then(|_| GlobalCache::load_protected(&pass));
+            return match result {
+                Ok(()) => Some(Page::List(ListPageParams::new())),
+                Err(error) => {
+                    Self::write_line(out, &VaultCliError::from(error));
+                    out.flush().unwrap();
+                    Some(Page::Exit)
+                }
+            };
+        }
+    }
+
+    pub fn render(&self) -> Option<Page> {
+        let mut out = TermConfig::get_out();
+        if !secret::vault_exists() {
+            return Self::render_new_vault(&mut *out);
+        }
+        let max_attempts = MAX_PASS_ATTEMPTS.load(Ordering::Relaxed);
+        for attempt in 1..=max_attempts {
+            let pass = match Self::read_password(&mut *out, "password: ") {
+                Some(pass) => pass,
+                None => return Some(Page::Exit),
+            };
+            // only an incorrect password counts as a failed attempt, since
+            // trying again does not help with anything else
+            match GlobalCache::load_protected(&pass) {
+                Ok(()) => return Some(Page::List(ListPageParams::new())),
+                Err(secret::SecretError::IncorrectPassword) => {}
+                Err(error) => {
+                    Self::write_line(&mut *out, &VaultCliError::from(error));
+                    out.flush().unwrap();
+                    return Some(Page::Exit);
+                }
+            }
+            let attempts_left = max_attempts - attempt;
+            match attempts_left {
+                0 => Self::write_line(&mut *out, "incorrect"),
+                1 => Self::write_line(&mut *out, "incorrect, 1 attempt left"),
+                _ => {
+                    let message =
+                        format!("incorrect, {} attempts left", attempts_left);
+                    Self::write_line(&mut *out, &message);
+                }
+            }
+            if Self::is_caps_lock_likely(&pass) {
+                Self::write_line(&mut *out, "caps lock may be on");
+            }
+            out.flush().unwrap();
+            if attempts_left > 0 {
+                // each retry waits longer, and what was typed meanwhile is
+                // dropped so that it is not taken as the next password
+                thread::sleep(PASS_RETRY_DELAY * attempt);
+                TermConfig::get_events().discard_input();
+            }
+        }
+        Some(Page::Exit)
     }
 }
 
+/// Position in the explore list, remembered for a directory while one of
+/// its subdirectories is explored.
+#[derive(PartialEq)]
+struct DirPosition {
+    dir: String,
+    selected_index: u16,
+    viewport_index: u16,
+}
+
 #[derive(PartialEq)]
 struct ListPageParams {
     dir: String,
     selected_index: u16,
     viewport_index: u16,
+    positions: HashMap<String, DirPosition>,
 }
 
 impl ListPageParams {
+    const MASKED_CONTENTS: &'static str =
+        "********\n\npress enter to reveal\npress ctrl-e to edit";
+
     pub fn new() -> Self {
         Self {
             dir: String::new(),
             selected_index: 0,
             viewport_index: 0,
+            positions: HashMap::new(),
         }
     }
 
+    /// Moves the explorer to the directory of the given secret path, with
+    /// the secret selected, remembering the position in the current one.
+    fn select_path(&mut self, index_map: &IndexMap, path: &str) {
+        let dir_len = path.rfind('/').map_or(0, |index| index + 1);
+        let position = DirPosition {
+            dir: mem::replace(&mut self.dir, path[..dir_len].to_owned()),
+            selected_index: self.selected_index,
+            viewport_index: self.viewport_index,
+        };
+        let base_dir = position.dir.rfind('/').map_or(0, |index| index + 1);
+        self.positions.insert(position.dir[..base_dir].to_owned(), position);
+        self.selected_index = index_map
+            .keys()
+            .explore_contents(&self.dir)
+            .iter()
+            .position(|entry| entry == &path[dir_len..])
+            .unwrap_or_default() as u16;
+        self.viewport_index = self.selected_index;
+    }
+
     fn render_before_all(&self) {
         let mut out = TermConfig::get_out();
         out.clear_screen();
         out.hide_cursor();
     }
 
+    /// Returns the explored directory, without the typed filter.
+    fn get_base_dir(&self) -> &str {
+        let base_len = self.dir.rfind('/').map_or(0, |index| index + 1);
+        &self.dir[..base_len]
+    }
+
+    /// Checks whether a filter is typed after the explored directory.
+    fn has_filter(&self) -> bool {
+        self.get_base_dir().len() < self.dir.len()
+    }
+
+    /// Returns the full path of the selected entry, if any.
+    fn get_selected_path(&self, explore_list: &ListWidget) -> Option<String> {
+        explore_list
+            .get_selected()
+            .map(|entry| format!("{}{}", self.get_base_dir(), entry))
+    }
+
+    /// Goes into the given directory, remembering the position in the
+    /// current one for the way back up.
+    fn enter_dir(&mut self, dir: String, explore_list: &ListWidget) {
+        let position = DirPosition {
+            dir: self.dir.clone(),
+            selected_index: explore_list.get_selected_index(),
+            viewport_index: explore_list.get_viewport_index(),
+        };
+        self.positions.insert(self.get_base_dir().to_owned(), position);
+        self.dir = dir;
+    }
+
+    /// Goes up one level, dropping any typed filter.
+    /// Returns the selected and viewport index remembered for the parent.
+    /// - If already at the top level without a filter, returns `None`.
+    fn leave_dir(&mut self) -> Option<(u16, u16)> {
+        let base_dir = self.get_base_dir();
+        if base_dir.is_empty() {
+            let had_filter = self.has_filter();
+            self.dir.clear();
+            return match had_filter {
+                true => Some((0, 0)),
+                false => None,
+            };
+        }
+        let parent_len = base_dir
+            .trim_end_matches('/')
+            .rfind('/')
+            .map_or(0, |index| index + 1);
+        let parent_dir = base_dir[..parent_len].to_owned();
+        match self.positions.get(&parent_dir) {
+            Some(position) => {
+                self.dir = position.dir.clone();
+                Some((position.selected_index, position.viewport_index))
+            }
+            None => {
+                self.dir = parent_dir;
+                Some((0, 0))
+            }
+        }
+    }
+
+    /// Goes up to the given directory, which is one of the parents.
+    /// Returns the selected and viewport index remembered for it.
+    /// - If already there, only drops any typed filter.
+    /// - If already there without a filter, returns `None`.
+    fn leave_dir_to(&mut self, dir: &str) -> Option<(u16, u16)> {
+        if self.get_base_dir().len() <= dir.len() {
+            let had_filter = self.has_filter();
+            self.dir.truncate(dir.len());
+            return match had_filter {
+                true => Some((0, 0)),
+                false => None,
+            };
+        }
+        let mut position = None;
+        while self.get_base_dir().len() > dir.len() {
+            position = self.leave_dir();
+        }
+        position
+    }
+
+    /// Returns the title of the explore list, in which the explored
+    /// directory reads as breadcrumbs.
+    fn format_title(&self) -> String {
+        format!("explore /{}", self.dir)
+    }
+
+    /// Returns the directory of the breadcrumb at the given title offset,
+    /// which is the top level for the leading `/`.
+    /// - If the offset is not on a directory, returns `None`.
+    fn find_breadcrumb(&self, title_offset: usize) -> Option<String> {
+        let root_offset = self.format_title().len() - self.dir.len() - 1;
+        match title_offset.checked_sub(root_offset)? {
+            0 => Some(String::new()),
+            offset => {
+                let dir_len = self.dir[offset - 1..].find('/')? + offset;
+                Some(self.dir[..dir_len].to_owned())
+            }
+        }
+    }
+
+    /// Shows the contents of the explored directory, with the given
+    /// selected and viewport index, and updates the secret view to match.
+    fn show_dir(
+        &self,
+        index_map: &IndexMap,
+        explore_list: &mut ListWidget,
+        secret_view: &mut SecretViewWidget,
+        position: (u16, u16),
+    ) {
+        let list = index_map.keys().explore_contents(&self.dir);
+        explore_list.set_list(list, position.0, position.1);
+        explore_list.set_title(self.format_title());
+        self.update_secret_view(explore_list, secret_view, false);
+    }
+
+    /// Returns the title and contents shown for the given path.
+    /// The secret is only decrypted once revealed, and masked otherwise.
+    fn describe_path(path: Option<&str>, revealed: bool)
+        -> (String, SecretString) {
+        let contents = match path {
+            None => SecretString::new(),
+            Some(path) if path.ends_with('/') =>
+                SecretString::from("directory"),
+            Some(_) if !revealed =>
+                SecretString::from(Self::MASKED_CONTENTS),
+            Some(path) => {
+                let pass = GlobalCache::get_pass();
+                match secret::get_secret(path, &pass) {
+                    Ok(contents) => contents,
+                    Err(error) => {
+                        let message = VaultCliError::from(error);
+                        SecretString::from(message)
+                    }
+                }
+            }
+        };
+        let title = path.map_or_else(String::new, |path| format!("/{}", path));
+        (title, contents)
+    }
+
+    /// Updates the secret view to show the selected entry.
+    fn update_secret_view(
+        &self,
+        explore_list: &ListWidget,
+        secret_view: &mut SecretViewWidget,
+        revealed: bool,
+    ) {
+        let path = self.get_selected_path(explore_list);
+        let (title, contents) = Self::describe_path(path.as_deref(), revealed);
+        secret_view.set_contents(title, contents);
+    }
+
     pub fn render(&mut self) -> Option<Page> {
         let (term_rows, term_cols) = TermConfig::get_size();
         let index_map = GlobalCache::get_index_map();
         let mut explore_list = ListWidgetBuilder {
             start: (0, 0),
             size: (term_rows - 1, term_cols / 2),
-            title: format!("explore /{}", self.dir),
+            title: self.format_title(),
             list: index_map.keys().explore_contents(&self.dir),
             selected_index: self.selected_index,
             viewport_index: self.viewport_index,
         }.build();
-        // let mut secret_view = 
+        let path = self.get_selected_path(&explore_list);
+        let (title, contents) = Self::describe_path(path.as_deref(), false);
+        let mut secret_view = SecretViewWidgetBuilder {
+            start: (0, term_cols / 2),
+            size: (term_rows - 1, term_cols - term_cols / 2),
+            title,
+            contents,
+        }.build();
+        let mut revealed = false;
+        let mut last_click: Option<(u16, Instant)> = None;
         self.render_before_all();
         explore_list.render();
-        TermConfig::get_events().find_map(|event| match event {
-            Event::Key(Key::Up) => {
-                explore_list.select_prev();
-                None
-            }
-            Event::Key(Key::Down) => {
-                explore_list.select_next();
-                None
-            }
-            Event::Key(Key::Backspace) => {
-                let popped = self.dir.pop();
-                if popped == Some('/') {
-                    self.dir.push('/')
-                } else if popped.is_some() {
-                    let list = index_map.keys().explore_contents(&self.dir);
-                    explore_list.set_list(list);
-                    explore_list.set_title(format!("explore /{}", self.dir));
+        secret_view.render();
+        TermConfig::get_events().find_map(|event| {
+            let event = match event {
+                TermEvent::Input(event) => map_wheel_to_keys(event),
+                TermEvent::Resize => {
+                    self.selected_index = explore_list.get_selected_index();
+                    self.viewport_index = explore_list.get_viewport_index();
+                    return Some(Page::Refresh);
                 }
-                None
-            }
-            Event::Key(Key::Char('\n')) => {
-                // let pass = pass.to_owned();
-                // Some(Page::List { pass, dir: dir.to_owned() + "a" })
-                None
-            }
-            Event::Key(Key::Char(':')) => {
-                self.selected_index = explore_list.get_selected_index();
-                self.viewport_index = explore_list.get_viewport_index();
-                Some(Page::Command(CommandPageParams))
-            }
-            Event::Key(Key::Char(char)) if char != '/' => {
-                self.dir.push(char);
-                let list = index_map.keys().explore_contents(&self.dir);
-                explore_list.set_list(list);
-                explore_list.set_title(format!("explore /{}", self.dir));
-                None
+                TermEvent::Idle => return Some(Page::Lock(None)),
+            };
+            // a click selects an entry, a double click opens it as enter
+            // does, and a click in the title goes up to that breadcrumb
+            let event = match event {
+                Event::Mouse(MouseEvent::Press(MouseButton::Left, x, y)) => {
+                    // mouse positions start at 1, unlike cursor positions
+                    let row = y.saturating_sub(1);
+                    let col = x.saturating_sub(1);
+                    if let Some(index) = explore_list.find_index_at(row, col) {
+                        let is_double = last_click
+                            .take()
+                            .map_or(false, |(last_index, time)| {
+                                last_index == index
+                                    && time.elapsed() < DOUBLE_CLICK_TIME
+                            });
+                        if is_double {
+                            Event::Key(Key::Char('\n'))
+                        } else {
+                            last_click = Some((index, Instant::now()));
+                            if index != explore_list.get_selected_index() {
+                                explore_list.set_selected_index(index);
+                                revealed = false;
+                                self.update_secret_view(
+                                    &explore_list,
+                                    &mut secret_view,
+                                    revealed,
+                                );
+                            }
+                            return None;
+                        }
+                    } else {
+                        let dir = explore_list
+                            .find_title_offset_at(row, col)
+                            .and_then(|offset| self.find_breadcrumb(offset))?;
+                        if let Some(position) = self.leave_dir_to(&dir) {
+                            revealed = false;
+                            let list = &mut explore_list;
+                            let view = &mut secret_view;
+                            self.show_dir(&index_map, list, view, position);
+                        }
+                        return None;
+                    }
+                }
+                event => event,
+            };
+            match event {
+                Event::Key(Key::Up) => {
+                    explore_list.select_prev();
+                    revealed = false;
+                    self.update_secret_view(
+                        &explore_list,
+                        &mut secret_view,
+                        revealed,
+                    );
+                    None
+                }
+                Event::Key(Key::Down) => {
+                    explore_list.select_next();
+                    revealed = false;
+                    self.update_secret_view(
+                        &explore_list,
+                        &mut secret_view,
+                        revealed,
+                    );
+                    None
+                }
+                Event::Key(Key::Backspace) if self.has_filter() => {
+                    self.dir.pop();
+                    revealed = false;
+                    let list = &mut explore_list;
+                    self.show_dir(&index_map, list, &mut secret_view, (0, 0));
+                    None
+                }
+                Event::Key(Key::Backspace) | Event::Key(Key::Left) => {
+                    if let Some(position) = self.leave_dir() {
+                        revealed = false;
+                        let list = &mut explore_list;
+                        let view = &mut secret_view;
+                        self.show_dir(&index_map, list, view, position);
+                    }
+                    None
+                }
+                Event::Key(Key::Char('\n')) => {
+                    match self.get_selected_path(&explore_list) {
+                        Some(path) if path.ends_with('/') => {
+                            self.enter_dir(path, &explore_list);
+                            revealed = false;
+                            let list = &mut explore_list;
+                            let view = &mut secret_view;
+                            self.show_dir(&index_map, list, view, (0, 0));
+                        }
+                        Some(_) => {
+                            revealed = !revealed;
+                            self.update_secret_view(
+                                &explore_list,
+                                &mut secret_view,
+                                revealed,
+                            );
+                        }
+                        None => {}
+                    }
+                    None
+                }
+                Event::Key(Key::Ctrl('e')) => {
+                    match self.get_selected_path(&explore_list) {
+                        Some(path) if !path.ends_with('/') => {
+                            let list = &explore_list;
+                            self.selected_index = list.get_selected_index();
+                            self.viewport_index = list.get_viewport_index();
+                            let params = EditPageParams { path, state: None };
+                            Some(Page::Edit(params))
+                        }
+                        _ => None,
+                    }
+                }
+                Event::Key(Key::Ctrl(key @ ('n' | 'r' | 'y' | 'd'))) => {
+                    let path = self.get_selected_path(&explore_list)
+                        .map(|path| command::quote_arg(&path));
+                    let base_dir = match self.get_base_dir() {
+                        "" => String::new(),
+                        base_dir => command::quote_arg(base_dir),
+                    };
+                    let command = match (key, path) {
+                        ('n', _) => format!("new {}", base_dir),
+                        ('r', Some(path)) => format!("mv {} {}", path, path),
+                        ('y', Some(path)) => format!("cp {} {}", path, path),
+                        ('d', Some(path)) => format!("rm {}", path),
+                        _ => return None,
+                    };
+                    self.selected_index = explore_list.get_selected_index();
+                    self.viewport_index = explore_list.get_viewport_index();
+                    let params = CommandPageParams::new(command, key == 'd');
+                    Some(Page::Command(params))
+                }
+                Event::Key(Key::Char('/')) | Event::Key(Key::Ctrl('p')) => {
+                    self.selected_index = explore_list.get_selected_index();
+                    self.viewport_index = explore_list.get_viewport_index();
+                    let params = FindPageParams {
+                        query: String::new(),
+                        chosen_path: None,
+                    };
+                    Some(Page::Find(params))
+                }
+                Event::Key(Key::Char(':')) => {
+                    self.selected_index = explore_list.get_selected_index();
+                    self.viewport_index = explore_list.get_viewport_index();
+                    let params = CommandPageParams::new(String::new(), false);
+                    Some(Page::Command(params))
+                }
+                Event::Key(Key::Char(char)) if char != '/' => {
+                    self.dir.push(char);
+                    revealed = false;
+                    let list = &mut explore_list;
+                    self.show_dir(&index_map, list, &mut secret_view, (0, 0));
+                    None
+                }
+                _ => None
             }
-            _ => None
         })
     }
 }
 
 #[derive(Clone, PartialEq)]
-struct CommandPageParams;
+struct CommandPageParams {
+    command: String,
+    submitted: bool,
+    pending_removal: Option<String>,
+    history_index: Option<usize>,
+    draft: String,
+    completion: Option<(Vec<String>, usize)>,
+}
 
 impl CommandPageParams {
-    fn evaluate_command(command: &str) -> Option<Page> {
-        match command {
-            "q" => {
+    const KEY_HELP: &'static [(&'static str, &'static str)] = &[
+        ("enter", "opens a directory or reveals a secret"),
+        ("click", "selects an entry, or goes up to a title segment"),
+        ("double-click", "opens a directory or reveals a secret"),
+        ("left", "goes up one level"),
+        ("/, ctrl-p", "finds a secret by fuzzy matching"),
+        ("ctrl-e", "edits the selected secret"),
+        ("ctrl-n", "creates a secret in this directory"),
+        ("ctrl-r", "moves the selected entry"),
+        ("ctrl-y", "copies the selected entry"),
+        ("ctrl-d", "removes the selected entry"),
+        (":", "opens the command line"),
+    ];
+
+    /// Creates the command line with the given command typed in.
+    /// A submitted command is evaluated right away.
+    pub fn new(command: String, submitted: bool) -> Self {
+        Self {
+            command,
+            submitted,
+            pending_removal: None,
+            history_index: None,
+            draft: String::new(),
+            completion: None,
+        }
+    }
+
+    /// Returns the secret path given in a command argument, which may
+    /// start with `/` same as the paths shown in titles.
+    fn parse_path(arg: &str) -> &str {
+        arg.trim_start_matches('/')
+    }
+
+    /// Runs the change to the secrets, going back to the refreshed explorer
+    /// list once it succeeds.
+    fn apply_change<F>(change: F) -> Option<Page>
+    where F: FnOnce(&SecretString) -> Result<usize, secret::SecretError> {
+        let result = change(&GlobalCache::get_pass());
+        let result = result.and_then(|_| GlobalCache::reload_index_map());
+        match result {
+            Ok(()) => {
+                Self::render_after_all();
+                Some(Page::Back)
+            }
+            Err(error) => {
+                Self::render_result(&VaultCliError::from(error), false);
+                None
+            }
+        }
+    }
+
+    fn evaluate_command(&mut self) -> Option<Page> {
+        GlobalCache::push_command_history(&self.command);
+        let args = match command::parse_args(&self.command) {
+            Ok(args) => args,
+            Err(CommandError::UnclosedQuote { quote }) => {
+                let message = format!("unclosed quote: {}", quote);
+                Self::render_result(&message, false);
+                return None;
+            }
+        };
+        let (name, args) = match args.split_first() {
+            Some((name, args)) => (name.as_str(), args),
+            None => {
+                Self::render_after_all();
+                return Some(Page::Back);
+            }
+        };
+        let spec = match command::find_command(name) {
+            Some(spec) => spec,
+            None => {
+                let message = format!("unrecognized command: {}", name);
+                Self::render_result(&message, false);
+                return None;
+            }
+        };
+        if !spec.accepts(args.len()) {
+            Self::render_result(&format!("usage: {}", spec.usage), false);
+            return None;
+        }
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        match (name, args.as_slice()) {
+            ("help", []) => {
+                Self::render_help();
+                Self::render_result("press esc to close", true);
+                None
+            }
+            ("help", [name]) => {
+                let (message, success) = match command::find_command(name) {
+                    Some(spec) =>
+                        (format!("{}: {}", spec.usage, spec.help), true),
+                    None =>
+                        (format!("unrecognized command: {}", name), false),
+                };
+                Self::render_result(&message, success);
+                None
+            }
+            ("q", _) => {
                 let mut out = TermConfig::get_out();
                 out.move_cursor_to(0, 0);
                 out.clear_screen();
                 out.flush().unwrap();
                 Some(Page::Exit)
             }
-            "r" => {
-                let (rows, cols) = TermConfig::recalculate_size();
-                let message = format!("resized to {} x {}", cols, rows);
-                Self::render_result(&message, true);
-                None
+            ("r", _) => {
+                TermConfig::recalculate_size();
+                Self::render_after_all();
+                Some(Page::Back)
             }
-            cmd => {
-                let message = format!("unrecognized command: {}", cmd);
-                Self::render_result(&message, false);
+            ("new", [path]) => {
+                let path = Self::parse_path(path);
+                if path.is_empty() || path.ends_with('/') {
+                    Self::render_result("secret path expected", false);
+                    return None;
+                }
+                if GlobalCache::get_index_map().contains_key(path) {
+                    let error = secret::SecretError::ExistentPath;
+                    Self::render_result(&VaultCliError::from(error), false);
+                    return None;
+                }
+                Self::apply_change(|pass| {
+                    secret::set_secret(path, "", pass).map(|_| 1)
+                })
+            }
+            ("mv", [src, dst]) => {
+                let src = Self::parse_path(src);
+                let dst = Self::parse_path(dst);
+                Self::apply_change(|pass| secret::move_secret(src, dst, pass))
+            }
+            ("cp", [src, dst]) => {
+                let src = Self::parse_path(src);
+                let dst = Self::parse_path(dst);
+                Self::apply_change(|pass| secret::copy_secret(src, dst, pass))
+            }
+            ("rm", [path]) => {
+                let path = Self::parse_path(path);
+                let count = GlobalCache::get_index_map()
+                    .keys()
+                    .filter(|key| match path.ends_with('/') {
+                        true => key.starts_with(path),
+                        false => key.as_str() == path,
+                    })
+                    .count();
+                if count == 0 {
+                    let error = secret::SecretError::NonExistentPath;
+                    Self::render_result(&VaultCliError::from(error), false);
+                    return None;
+                }
+                let message = match path.ends_with('/') {
+                    true => format!("remove {} secrets in /{}? (y/n)",
+                        count, path),
+                    false => format!("remove /{}? (y/n)", path),
+                };
+                Self::render_prompt(&message);
+                self.pending_removal = Some(path.to_owned());
                 None
             }
+            _ => unreachable!(),
         }
     }
 
-    fn render_result(message: &str, success: bool) {
+    /// Completes the typed command, using the command names and the secret
+    /// paths. Completing again cycles through the candidates, once there is
+    /// no longer a common part to complete.
+    fn complete_command(&mut self) {
+        if let Some((lines, index)) = &mut self.completion {
+            *index = (*index + 1) % lines.len();
+            self.command = lines[*index].clone();
+            return;
+        }
+        let index_map = GlobalCache::get_index_map();
+        let paths = index_map.keys().map(String::as_str);
+        let lines = command::complete(&self.command, paths);
+        match lines.len() {
+            0 => {}
+            1 => self.command = lines[0].clone(),
+            _ => {
+                let prefix = command::find_common_prefix(&lines);
+                if prefix.len() > self.command.len() {
+                    self.command = prefix.to_owned();
+                } else {
+                    self.command = lines[0].clone();
+                    self.completion = Some((lines, 0));
+                }
+            }
+        }
+    }
+
+    /// Replaces the typed command with an older or newer one from history.
+    /// Going past the newest one brings back what was typed before.
+    fn recall_history(&mut self, older: bool) {
+        let history = GlobalCache::get_command_history();
+        let index = match (self.history_index, older) {
+            (None, true) if !history.is_empty() => Some(history.len() - 1),
+            (None, _) => return,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < history.len() =>
+                Some(index + 1),
+            (Some(_), false) => None,
+        };
+        if self.history_index.is_none() {
+            self.draft = mem::take(&mut self.command);
+        }
+        self.command = match index {
+            Some(index) => history[index].clone(),
+            None => mem::take(&mut self.draft),
+        };
+        self.history_index = index;
+    }
+
+    fn render_help() {
+        let (term_rows, term_cols) = TermConfig::get_size();
+        let mut contents = SecretString::new();
+        command::COMMANDS.iter().for_each(|spec| {
+            contents.push_str(&format!(":{:<16}{}\n", spec.usage, spec.help));
+        });
+        contents.push('\n');
+        Self::KEY_HELP.iter().for_each(|(key, help)| {
+            contents.push_str(&format!("{:<17}{}\n", key, help));
+        });
+        SecretViewWidgetBuilder {
+            start: (0, term_cols / 2),
+            size: (term_rows - 1, term_cols - term_cols / 2),
+            title: String::from("help"),
+            contents,
+        }.build().render();
+    }
+
+    fn render_line(message: &str, color: Color) {
         let mut out = TermConfig::get_out();
-        out.use_color_fg(if success { Color::Green } else { Color::Red });
-        out.hide_cursor();
+        let (term_rows, term_cols) = TermConfig::get_size();
+        out.move_cursor_to(term_rows - 1, 0);
+        out.write_str(" ".repeat(term_cols as usize));
         out.move_cursor_to_horiz(0);
+        out.use_color_fg(color);
         out.write_str(" ");
         out.write_str(message);
+    }
+
+    fn render_result(message: &str, success: bool) {
+        let color = if success { Color::Green } else { Color::Red };
+        Self::render_line(message, color);
+        let mut out = TermConfig::get_out();
+        out.hide_cursor();
+        out.flush().unwrap();
+    }
+
+    fn render_prompt(message: &str) {
+        Self::render_line(message, Color::Yellow);
+        let mut out = TermConfig::get_out();
+        out.show_cursor();
         out.flush().unwrap();
     }
 
-    fn render_before_all() {
+    /// Renders the typed command, showing only its end if it does not fit.
+    fn render_command(&self) {
         let mut out = TermConfig::get_out();
         let (term_rows, term_cols) = TermConfig::get_size();
+        let view_len = term_cols.saturating_sub(2) as usize;
+        let skip = self.command.chars().count().saturating_sub(view_len);
         out.move_cursor_to(term_rows - 1, 0);
         out.write_str(" ".repeat(term_cols as usize));
         out.move_cursor_to_horiz(0);
         out.use_color_fg(Color::Yellow);
         out.write_str(":");
+        out.write_str(self.command.chars().skip(skip).collect::<String>());
         out.show_cursor();
         out.flush().unwrap();
     }
@@ -269,42 +1143,355 @@ impl CommandPageParams {
         out.flush().unwrap();
     }
 
-    pub fn render(&self) -> Option<Page> {
-        let mut command = String::with_capacity(20);
-        let mut editing = true;
-        Self::render_before_all();
-        TermConfig::get_events().find_map(|event| match event {
-            Event::Key(Key::Esc) => {
-                Self::render_after_all();
-                Some(Page::Back)
-            },
-            Event::Key(Key::Char('\n')) if editing => {
-                editing = false;
-                Self::evaluate_command(&command)
-            }
-            Event::Key(Key::Char(char)) if editing => {
-                if command.len() < 20 {
-                    let mut out = TermConfig::get_out();
-                    out.write_all(&[char as u8]).unwrap();
-                    out.flush().unwrap();
-                    command.push(char);
+    pub fn render(&mut self) -> Option<Page> {
+        let mut editing = !self.submitted;
+        self.render_command();
+        if self.submitted {
+            if let Some(page) = self.evaluate_command() {
+                return Some(page);
+            }
+        }
+        TermConfig::get_events().find_map(|event| {
+            let key = match event {
+                TermEvent::Input(Event::Key(key)) => key,
+                TermEvent::Input(_) => return None,
+                // the page below is laid out again at the new size, which
+                // leaves no room to keep the command line open
+                TermEvent::Resize => {
+                    Self::render_after_all();
+                    return Some(Page::Back);
                 }
-                None
+                TermEvent::Idle => return Some(Page::Lock(None)),
+            };
+            if let Some(path) = self.pending_removal.take() {
+                return match key {
+                    Key::Char('y') => Self::apply_change(|pass| {
+                        secret::remove_entry(&path, pass)
+                    }),
+                    _ => {
+                        Self::render_after_all();
+                        Some(Page::Back)
+                    }
+                };
             }
-            Event::Key(Key::Backspace) if editing => {
-                if command.pop().is_some() {
-                    let mut out = TermConfig::get_out();
-                    out.apply_backspace(1);
-                    out.flush().unwrap();
+            if key != Key::Char('\t') {
+                self.completion = None;
+            }
+            match key {
+                Key::Esc => {
+                    Self::render_after_all();
+                    return Some(Page::Back);
                 }
-                None
+                _ if !editing => return None,
+                Key::Char('\n') => {
+                    editing = false;
+                    return self.evaluate_command();
+                }
+                Key::Char('\t') => self.complete_command(),
+                Key::Up => self.recall_history(true),
+                Key::Down => self.recall_history(false),
+                Key::Char(char) => self.command.push(char),
+                Key::Backspace => {
+                    self.command.pop();
+                }
+                _ => return None,
+            }
+            self.render_command();
+            None
+        })
+    }
+}
+
+#[derive(PartialEq)]
+struct FindPageParams {
+    query: String,
+    chosen_path: Option<String>,
+}
+
+impl FindPageParams {
+    /// Matches the query against the paths, with the best matches first.
+    fn find_matches<'a, I>(query: &str, paths: I) -> Vec<(String, Vec<usize>)>
+    where I: Iterator<Item = &'a str> {
+        let mut matches = paths
+            .filter_map(|path| algo::fuzzy_match(query, path)
+                .map(|(score, positions)| (score, path, positions)))
+            .collect::<Vec<_>>();
+        matches.sort_by(|(a_score, a_path, _), (b_score, b_path, _)| {
+            b_score
+                .cmp(a_score)
+                .then(a_path.len().cmp(&b_path.len()))
+                .then(a_path.cmp(b_path))
+        });
+        matches
+            .into_iter()
+            .map(|(_, path, positions)| (path.to_owned(), positions))
+            .collect()
+    }
+
+    fn format_title(match_count: usize, total_count: usize) -> String {
+        format!("find {}/{}", match_count, total_count)
+    }
+
+    fn render_query(query: &str) {
+        let mut out = TermConfig::get_out();
+        let (term_rows, term_cols) = TermConfig::get_size();
+        let view_len = term_cols.saturating_sub(2) as usize;
+        let skip = query.chars().count().saturating_sub(view_len);
+        out.move_cursor_to(term_rows - 1, 0);
+        out.write_str(" ".repeat(term_cols as usize));
+        out.move_cursor_to_horiz(0);
+        out.use_color_fg(Color::Yellow);
+        out.write_str("/");
+        out.use_color_fg(Color::Reset);
+        out.write_str(query.chars().skip(skip).collect::<String>());
+        out.show_cursor();
+        out.flush().unwrap();
+    }
+
+    pub fn render(&mut self) -> Option<Page> {
+        let (term_rows, term_cols) = TermConfig::get_size();
+        let index_map = GlobalCache::get_index_map();
+        let total_count = index_map.len();
+        let mut query = mem::take(&mut self.query);
+        let paths = index_map.keys().map(String::as_str);
+        let matches = Self::find_matches(&query, paths);
+        let mut match_list = MatchListWidgetBuilder {
+            start: (0, 0),
+            size: (term_rows - 1, term_cols),
+            title: Self::format_title(matches.len(), total_count),
+            list: matches,
+        }.build();
+        match_list.render();
+        Self::render_query(&query);
+        TermConfig::get_events().find_map(|event| {
+            let event = match event {
+                TermEvent::Input(event) => map_wheel_to_keys(event),
+                TermEvent::Resize => {
+                    self.query = mem::take(&mut query);
+                    return Some(Page::Refresh);
+                }
+                TermEvent::Idle => return Some(Page::Lock(None)),
+            };
+            let matches = match event {
+                Event::Key(Key::Esc) => return Some(Page::Back),
+                Event::Key(Key::Char('\n')) => {
+                    let path = match_list.get_selected()?;
+                    self.chosen_path = Some(path.to_owned());
+                    return Some(Page::Back);
+                }
+                Event::Key(Key::Up) => {
+                    match_list.select_prev();
+                    None
+                }
+                Event::Key(Key::Down) => {
+                    match_list.select_next();
+                    None
+                }
+                Event::Key(Key::Char(char)) => {
+                    // a longer query only matches a subset of the matches
+                    query.push(char);
+                    let paths = match_list.get_entries();
+                    Some(Self::find_matches(&query, paths))
+                }
+                Event::Key(Key::Backspace) if query.pop().is_some() => {
+                    let paths = index_map.keys().map(String::as_str);
+                    Some(Self::find_matches(&query, paths))
+                }
+                _ => return None,
+            };
+            if let Some(matches) = matches {
+                let title = Self::format_title(matches.len(), total_count);
+                match_list.set_list(title, matches);
+            }
+            Self::render_query(&query);
+            None
+        })
+    }
+}
+
+/// Question asked in the status line of the edit page.
+#[derive(Clone, Copy, PartialEq)]
+enum EditPrompt {
+    Save,
+    Discard,
+}
+
+/// Edits kept while the edit page is laid out again.
+struct EditState {
+    lines: Vec<SecretString>,
+    cursor_pos: (usize, usize),
+    viewport_pos: (usize, usize),
+    is_modified: bool,
+}
+
+impl From<&TextEditWidget> for EditState {
+    fn from(text_edit: &TextEditWidget) -> Self {
+        Self {
+            lines: text_edit.get_lines().to_vec(),
+            cursor_pos: text_edit.get_cursor_pos(),
+            viewport_pos: text_edit.get_viewport_pos(),
+            is_modified: text_edit.is_modified(),
+        }
+    }
+}
+
+struct EditPageParams {
+    path: String,
+    state: Option<EditState>,
+}
+
+impl EditPageParams {
+    const HINT: &'static str = "ctrl-s: save, esc: close";
+
+    /// Returns the path of the secret, if edits kept while the page is
+    /// laid out again are not saved yet.
+    fn get_unsaved_path(&self) -> Option<String> {
+        match &self.state {
+            Some(state) if state.is_modified => Some(self.path.clone()),
+            _ => None,
+        }
+    }
+
+    fn render_before_all(&self) {
+        let mut out = TermConfig::get_out();
+        out.clear_screen();
+    }
+
+    /// Writes the message into the status line at the bottom.
+    fn render_status(message: &str, color: Color) {
+        let mut out = TermConfig::get_out();
+        let (term_rows, term_cols) = TermConfig::get_size();
+        out.move_cursor_to(term_rows - 1, 0);
+        out.write_str(" ".repeat(term_cols as usize));
+        out.move_cursor_to_horiz(0);
+        out.use_color_fg(color);
+        out.write_str(" ");
+        out.write_str(message);
+        out.use_color_fg(Color::Reset);
+        out.flush().unwrap();
+    }
+
+    /// Decrypts the secret to edit, split into lines.
+    /// - If the secret cannot be read or is not valid utf-8, returns the
+    ///   message to show instead.
+    fn load_lines(&self) -> Result<Vec<SecretString>, String> {
+        let pass = GlobalCache::get_pass();
+        let contents = secret::get_secret_bytes(&self.path, &pass)
+            .map_err(VaultCliError::from)?;
+        let contents = SecretString::from_utf8(contents)
+            .ok_or_else(|| String::from("secret is not valid utf-8"))?;
+        Ok(contents.split('\n').map(SecretString::from).collect())
+    }
+
+    /// Encrypts the edited contents into the secret.
+    fn save(&self, text_edit: &TextEditWidget) -> Result<(), String> {
+        let pass = GlobalCache::get_pass();
+        let contents = text_edit.get_contents();
+        secret::set_secret(&self.path, &contents, &pass)
+            .map_err(VaultCliError::from)
+    }
+
+    pub fn render(&mut self) -> Option<Page> {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => match self.load_lines() {
+                Ok(lines) => EditState {
+                    lines,
+                    cursor_pos: (0, 0),
+                    viewport_pos: (0, 0),
+                    is_modified: false,
+                },
+                Err(message) => {
+                    Self::render_status(&message, Color::Red);
+                    TermConfig::get_events().next();
+                    return Some(Page::Back);
+                }
+            },
+        };
+        let (term_rows, term_cols) = TermConfig::get_size();
+        let mut text_edit = TextEditWidgetBuilder {
+            start: (0, term_cols / 2),
+            size: (term_rows - 1, term_cols - term_cols / 2),
+            title: format!("edit /{}", self.path),
+            lines: state.lines,
+            cursor_pos: state.cursor_pos,
+            viewport_pos: state.viewport_pos,
+            is_modified: state.is_modified,
+        }.build();
+        let mut prompt = None;
+        self.render_before_all();
+        Self::render_status(Self::HINT, Color::Reset);
+        text_edit.render();
+        TermConfig::get_events().find_map(|event| {
+            let event = match event {
+                TermEvent::Input(event) => map_wheel_to_keys(event),
+                TermEvent::Resize => {
+                    self.state = Some(EditState::from(&text_edit));
+                    return Some(Page::Refresh);
+                }
+                TermEvent::Idle => {
+                    let unsaved_path = match text_edit.is_modified() {
+                        true => Some(self.path.clone()),
+                        false => None,
+                    };
+                    return Some(Page::Lock(unsaved_path));
+                }
+            };
+            let event = match (prompt.take(), event) {
+                (Some(EditPrompt::Save), Event::Key(Key::Char('y'))) => {
+                    return match self.save(&text_edit) {
+                        Ok(()) => Some(Page::Back),
+                        Err(message) => {
+                            Self::render_status(&message, Color::Red);
+                            text_edit.focus();
+                            None
+                        }
+                    };
+                }
+                (Some(EditPrompt::Discard), Event::Key(Key::Char('y'))) => {
+                    return Some(Page::Back);
+                }
+                (Some(_), _) => {
+                    Self::render_status(Self::HINT, Color::Reset);
+                    text_edit.focus();
+                    return None;
+                }
+                (None, event) => event,
+            };
+            match event {
+                Event::Key(Key::Ctrl('s')) => {
+                    let message =
+                        format!("save changes to /{}? (y/n)", self.path);
+                    Self::render_status(&message, Color::Yellow);
+                    prompt = Some(EditPrompt::Save);
+                }
+                Event::Key(Key::Esc) if text_edit.is_modified() => {
+                    let message = "discard changes? (y/n)";
+                    Self::render_status(message, Color::Yellow);
+                    prompt = Some(EditPrompt::Discard);
+                }
+                Event::Key(Key::Esc) => return Some(Page::Back),
+                Event::Key(Key::Left) => text_edit.move_left(),
+                Event::Key(Key::Right) => text_edit.move_right(),
+                Event::Key(Key::Up) => text_edit.move_up(),
+                Event::Key(Key::Down) => text_edit.move_down(),
+                Event::Key(Key::Home) => text_edit.move_home(),
+                Event::Key(Key::End) => text_edit.move_end(),
+                Event::Key(Key::PageUp) => text_edit.move_page_up(),
+                Event::Key(Key::PageDown) => text_edit.move_page_down(),
+                Event::Key(Key::Backspace) => text_edit.remove_prev_char(),
+                Event::Key(Key::Delete) => text_edit.remove_next_char(),
+                Event::Key(Key::Char(char)) => text_edit.insert_char(char),
+                _ => {}
             }
-            _ => None
+            None
         })
     }
 }
 
-pub fn start_event_loop_blocking() {
+pub fn start_event_loop_blocking(lock_timeout: Duration, max_attempts: u32) {
+    TermConfig::set_idle_timeout(lock_timeout);
+    MAX_PASS_ATTEMPTS.store(max_attempts.max(1), Ordering::Relaxed);
     let init_page = Page::Password(PasswordPageParams);
     std::panic::catch_unwind(|| init_page.render())
         .unwrap_or_else(|_| TermConfig::restore());
@@ -1,7 +1,9 @@
 use crate::util::algo;
+use crate::util::secure::SecretString;
 use std::cmp;
+use std::io::Write;
 use super::config::TermConfig;
-use super::ansi::TermControl;
+use super::ansi::{Color, TermControl};
 
 pub struct ListWidgetBuilder {
     pub start: (u16, u16),
@@ -96,7 +98,7 @@ impl ListWidget {
     }
 
     fn render_selector(&self, indicator: &str) {
-        if self.list.len() > 0 {
+        if !self.list.is_empty() {
             let mut out = TermConfig::get_out();
             let view_top = self.start.0 + Self::BORDER_Y;
             let view_left = self.start.1 + Self::BORDER_X;
@@ -129,6 +131,40 @@ impl ListWidget {
         self.selected_index
     }
 
+    pub fn get_selected(&self) -> Option<&str> {
+        self.list
+            .get(self.selected_index as usize)
+            .map(|key| key.as_str())
+    }
+
+    /// Returns the index of the entry shown at the given screen position.
+    pub fn find_index_at(&self, row: u16, col: u16) -> Option<u16> {
+        let view_top = self.start.0 + Self::BORDER_Y;
+        let view_left = self.start.1 + Self::BORDER_X;
+        let view_rows = self.size.0 - 2 * Self::BORDER_Y;
+        let view_cols = self.size.1 - 2 * Self::BORDER_X;
+        let in_view = (view_top..view_top + view_rows).contains(&row)
+            && (view_left..view_left + view_cols).contains(&col);
+        let index = self.viewport_index + row.saturating_sub(view_top);
+        match in_view && (index as usize) < self.list.len() {
+            true => Some(index),
+            false => None,
+        }
+    }
+
+    /// Returns the byte offset of the title char shown at the given screen
+    /// position.
+    pub fn find_title_offset_at(&self, row: u16, col: u16) -> Option<usize> {
+        let title_left = self.start.1 + Self::BORDER_X + 2;
+        if row != self.start.0 || col < title_left {
+            return None;
+        }
+        self.title
+            .char_indices()
+            .nth((col - title_left) as usize)
+            .map(|(offset, _)| offset)
+    }
+
     pub fn set_title(&mut self, title: String) {
         let mut out = TermConfig::get_out();
         let common_index = algo::find_max_common_index(&title, &self.title);
@@ -142,10 +178,14 @@ impl ListWidget {
         self.title = title;
     }
 
-    pub fn set_list(&mut self, list: Vec<String>) {
+    /// Replaces the list, with the given selected and viewport index.
+    pub fn set_list(
+        &mut self,
+        list: Vec<String>,
+        selected_index: u16,
+        viewport_index: u16,
+    ) {
         let view_rows = self.size.0 - 4;
-        let selected_index = 0;
-        let viewport_index = 0;
         self.render_selector(" ");
         let prev_len_arr = (0..view_rows)
             .map(|line_index| self.list
@@ -157,6 +197,7 @@ impl ListWidget {
         self.selected_index = selected_index;
         self.viewport_index = viewport_index;
         self.list = list;
+        self.sanitize();
         self.render_contents(&prev_len_arr);
         self.render_scroll();
         self.render_selector(">");
@@ -165,7 +206,6 @@ impl ListWidget {
 
     pub fn set_selected_index(&mut self, index: u16) {
         let view_rows = self.size.0 - 4;
-        let total_rows = self.list.len() as u16;
         self.render_selector(" ");
         self.selected_index = self.clamp_selected_index(index);
         let viewport_index = self.clamp_viewport_index(self.viewport_index);
@@ -207,50 +247,705 @@ impl ListWidget {
     }
 }
 
+pub struct MatchListWidgetBuilder {
+    pub start: (u16, u16),
+    pub size: (u16, u16),
+    pub title: String,
+    pub list: Vec<(String, Vec<usize>)>,
+}
+
+impl MatchListWidgetBuilder {
+    pub fn build(self) -> MatchListWidget {
+        MatchListWidget {
+            start: self.start,
+            size: self.size,
+            title: self.title,
+            list: self.list,
+            selected_index: 0,
+            viewport_index: 0,
+        }
+    }
+}
+
+/// List of matches, with the matched chars of each entry highlighted.
+/// The whole view is rendered again on every change, since the entries
+/// are replaced as a whole while typing.
+pub struct MatchListWidget {
+    start: (u16, u16),
+    size: (u16, u16),
+    title: String,
+    list: Vec<(String, Vec<usize>)>,
+    selected_index: u16,
+    viewport_index: u16,
+}
+
+impl MatchListWidget {
+    const BORDER_X: u16 = 2;
+    const BORDER_Y: u16 = 2;
+
+    fn get_view_rows(&self) -> u16 {
+        cmp::max(self.size.0.saturating_sub(2 * Self::BORDER_Y), 1)
+    }
+
+    fn clamp_viewport_index(&self, index: u16) -> u16 {
+        let view_rows = self.get_view_rows();
+        let view_min = self.selected_index.saturating_sub(view_rows - 1);
+        cmp::max(cmp::min(index, self.selected_index), view_min)
+    }
+
+    fn render_contents(&self) {
+        let mut out = TermConfig::get_out();
+        let view_top = self.start.0 + Self::BORDER_Y;
+        let view_left = self.start.1 + Self::BORDER_X;
+        let view_cols = self.size.1.saturating_sub(3 * Self::BORDER_X);
+        let total_rows = self.list.len() as u16;
+        let (scroll_size, scroll_pos) = algo::calculate_scroll_state(
+            self.viewport_index,
+            self.get_view_rows(),
+            total_rows,
+        );
+        (0..self.get_view_rows()).for_each(|i| {
+            let list_index = self.viewport_index + i;
+            out.move_cursor_to(view_top + i, view_left);
+            let is_selected = list_index == self.selected_index;
+            out.write_str(if is_selected { "> " } else { "  " });
+            let mut line_len = 0;
+            let entry = self.list.get(list_index as usize);
+            if let Some((entry, positions)) = entry {
+                entry
+                    .chars()
+                    .take(view_cols as usize)
+                    .enumerate()
+                    .for_each(|(char_index, char)| {
+                        let is_match =
+                            positions.binary_search(&char_index).is_ok();
+                        if is_match {
+                            out.use_color_fg(Color::Yellow);
+                        }
+                        out.write_str(char.encode_utf8(&mut [0; 4]));
+                        if is_match {
+                            out.use_color_fg(Color::Reset);
+                        }
+                        line_len += 1;
+                    });
+            }
+            out.apply_space(view_cols - line_len);
+            let in_range = i >= scroll_pos && i < scroll_pos + scroll_size;
+            out.write_str(if in_range { "\u{2502}" } else { " " });
+        });
+    }
+
+    pub fn get_selected(&self) -> Option<&str> {
+        self.list
+            .get(self.selected_index as usize)
+            .map(|(entry, _)| entry.as_str())
+    }
+
+    pub fn get_entries(&self) -> impl Iterator<Item = &str> {
+        self.list.iter().map(|(entry, _)| entry.as_str())
+    }
+
+    /// Replaces the title and the list, selecting the first entry.
+    pub fn set_list(
+        &mut self,
+        title: String,
+        list: Vec<(String, Vec<usize>)>,
+    ) {
+        let prev_len = self.title.chars().count();
+        self.title = title;
+        self.list = list;
+        self.selected_index = 0;
+        self.viewport_index = 0;
+        let mut out = TermConfig::get_out();
+        write_title(&mut *out, self.start, self.size, &self.title, prev_len);
+        drop(out);
+        self.render_contents();
+        TermConfig::get_out().flush().unwrap();
+    }
+
+    pub fn set_selected_index(&mut self, index: u16) {
+        let total_rows = self.list.len() as u16;
+        self.selected_index = cmp::min(index, total_rows.saturating_sub(1));
+        self.viewport_index = self.clamp_viewport_index(self.viewport_index);
+        self.render_contents();
+        TermConfig::get_out().flush().unwrap();
+    }
+
+    pub fn select_prev(&mut self) {
+        self.set_selected_index(self.selected_index.saturating_sub(1));
+    }
+
+    pub fn select_next(&mut self) {
+        self.set_selected_index(self.selected_index + 1);
+    }
+
+    /// Renders the whole widget, clearing whatever it is drawn over.
+    pub fn render(&self) {
+        let mut out = TermConfig::get_out();
+        out.move_cursor_to(self.start.0, self.start.1);
+        out.draw_box(self.size.0, self.size.1);
+        (1..self.size.0.saturating_sub(1)).for_each(|i| {
+            out.move_cursor_to(self.start.0 + i, self.start.1 + 1);
+            out.apply_space(self.size.1.saturating_sub(2));
+        });
+        write_title(&mut *out, self.start, self.size, &self.title, 0);
+        drop(out);
+        self.render_contents();
+        TermConfig::get_out().flush().unwrap();
+    }
+}
+
+pub struct SecretViewWidgetBuilder {
+    pub start: (u16, u16),
+    pub size: (u16, u16),
+    pub title: String,
+    pub contents: SecretString,
+}
+
+impl SecretViewWidgetBuilder {
+    pub fn build(self) -> SecretViewWidget {
+        SecretViewWidget {
+            start: self.start,
+            size: self.size,
+            title: self.title,
+            contents: self.contents,
+        }
+    }
+}
+
+/// Read-only view of the contents of a secret, which are wiped once
+/// they are replaced. Lines which do not fit in the view are cut off.
+pub struct SecretViewWidget {
+    start: (u16, u16),
+    size: (u16, u16),
+    title: String,
+    contents: SecretString,
+}
+
+impl SecretViewWidget {
+    const BORDER_X: u16 = 2;
+    const BORDER_Y: u16 = 2;
+
+    fn render_window(&self) {
+        let mut out = TermConfig::get_out();
+        out.move_cursor_to(self.start.0, self.start.1);
+        out.draw_box(self.size.0, self.size.1);
+    }
+
+    fn render_title(&self, prev_len: usize) {
+        let mut out = TermConfig::get_out();
+        write_title(&mut *out, self.start, self.size, &self.title, prev_len);
+    }
+
+    fn render_contents(&self) {
+        let mut out = TermConfig::get_out();
+        let view_top = self.start.0 + Self::BORDER_Y;
+        let view_left = self.start.1 + Self::BORDER_X;
+        let view_rows = self.size.0.saturating_sub(2 * Self::BORDER_Y);
+        let view_cols = self.size.1.saturating_sub(2 * Self::BORDER_X);
+        let mut lines = self.contents.lines();
+        (0..view_rows).for_each(|i| {
+            out.move_cursor_to(view_top + i, view_left);
+            let line = lines.next().unwrap_or_default();
+            let line_len = write_line_cut(&mut *out, line, 0, view_cols);
+            out.apply_space(view_cols - line_len);
+        });
+    }
+
+    /// Replaces the title and contents, rendering only the changed parts.
+    pub fn set_contents(&mut self, title: String, contents: SecretString) {
+        let prev_len = self.title.chars().count();
+        self.title = title;
+        self.contents = contents;
+        self.render_title(prev_len);
+        self.render_contents();
+        TermConfig::get_out().flush().unwrap();
+    }
+
+    pub fn render(&self) {
+        self.render_window();
+        self.render_title(0);
+        self.render_contents();
+        TermConfig::get_out().flush().unwrap();
+    }
+}
+
 pub struct TextEditWidgetBuilder {
     pub start: (u16, u16),
     pub size: (u16, u16),
     pub title: String,
-    pub lines: Vec<String>,
-    pub cursor_pos: (u16, u16),
-    pub viewport_pos: (u16, u16),
+    pub lines: Vec<SecretString>,
+    pub cursor_pos: (usize, usize),
+    pub viewport_pos: (usize, usize),
+    pub is_modified: bool,
 }
 
 impl TextEditWidgetBuilder {
     pub fn build(self) -> TextEditWidget {
+        let mut lines = self.lines;
+        if lines.is_empty() {
+            lines.push(SecretString::new());
+        }
         let mut widget = TextEditWidget {
             start: self.start,
             size: self.size,
             title: self.title,
-            lines: self.lines,
+            lines,
             cursor_pos: self.cursor_pos,
             viewport_pos: self.viewport_pos,
+            is_modified: self.is_modified,
         };
         widget.sanitize();
         widget
     }
 }
 
+/// Multi-line editor, which wraps the lines that do not fit in the view.
+/// The cursor position is a line and a char index in it, while the viewport
+/// position is the line and the wrapped row of it shown at the top.
+/// Both are kept apart from the screen coordinates, so that neither long
+/// lines nor many of them are limited by the size of the terminal.
 pub struct TextEditWidget {
     start: (u16, u16),
     size: (u16, u16),
     title: String,
-    lines: Vec<String>,
-    cursor_pos: (u16, u16),
-    viewport_pos: (u16, u16),
+    lines: Vec<SecretString>,
+    cursor_pos: (usize, usize),
+    viewport_pos: (usize, usize),
+    is_modified: bool,
 }
 
 impl TextEditWidget {
-    fn clamp_cursor_pos(&self, pos: (u16, u16)) -> (u16, u16) {
-        pos
+    const BORDER_X: u16 = 2;
+    const BORDER_Y: u16 = 2;
+
+    fn get_view_size(&self) -> (u16, u16) {
+        let view_rows = self.size.0.saturating_sub(2 * Self::BORDER_Y);
+        let view_cols = self.size.1.saturating_sub(2 * Self::BORDER_X);
+        (cmp::max(view_rows, 1), cmp::max(view_cols, 1))
+    }
+
+    /// Returns the number of chars the wrapped rows fit.
+    fn get_view_cols(&self) -> usize {
+        self.get_view_size().1 as usize
+    }
+
+    fn get_line_len(&self, line_index: usize) -> usize {
+        self.lines[line_index].chars().count()
+    }
+
+    /// Returns the number of rows the line wraps into. A line filling its
+    /// last row gets an extra one, so that the cursor fits after it.
+    fn get_row_count(&self, line_index: usize) -> usize {
+        self.get_line_len(line_index) / self.get_view_cols() + 1
+    }
+
+    /// Returns the wrapped row the cursor is in.
+    fn get_cursor_row(&self) -> (usize, usize) {
+        (self.cursor_pos.0, self.cursor_pos.1 / self.get_view_cols())
+    }
+
+    fn get_prev_row(&self, row: (usize, usize)) -> Option<(usize, usize)> {
+        match row {
+            (0, 0) => None,
+            (line_index, 0) =>
+                Some((line_index - 1, self.get_row_count(line_index - 1) - 1)),
+            (line_index, row_index) => Some((line_index, row_index - 1)),
+        }
+    }
+
+    fn get_next_row(&self, row: (usize, usize)) -> Option<(usize, usize)> {
+        let (line_index, row_index) = row;
+        if row_index + 1 < self.get_row_count(line_index) {
+            Some((line_index, row_index + 1))
+        } else if line_index + 1 < self.lines.len() {
+            Some((line_index + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    fn clamp_cursor_pos(&self, pos: (usize, usize)) -> (usize, usize) {
+        let line_index = cmp::min(pos.0, self.lines.len() - 1);
+        (line_index, cmp::min(pos.1, self.get_line_len(line_index)))
+    }
+
+    fn clamp_viewport_pos(&self, pos: (usize, usize)) -> (usize, usize) {
+        let cursor_row = self.get_cursor_row();
+        let mut view_min = cursor_row;
+        for _ in 1..self.get_view_size().0 {
+            match self.get_prev_row(view_min) {
+                Some(row) => view_min = row,
+                None => break,
+            }
+        }
+        cmp::max(cmp::min(pos, cursor_row), view_min)
+    }
+
+    fn render_contents(&self) {
+        let mut out = TermConfig::get_out();
+        let view_top = self.start.0 + Self::BORDER_Y;
+        let view_left = self.start.1 + Self::BORDER_X;
+        let (view_rows, view_cols) = self.get_view_size();
+        let mut row = Some(self.viewport_pos);
+        (0..view_rows).for_each(|i| {
+            out.move_cursor_to(view_top + i, view_left);
+            let line_len = row.map_or(0, |(line_index, row_index)| {
+                let line = &self.lines[line_index];
+                let skip = row_index * view_cols as usize;
+                write_line_cut(&mut *out, line, skip, view_cols)
+            });
+            out.apply_space(view_cols - line_len);
+            row = row.and_then(|row| self.get_next_row(row));
+        });
+    }
+
+    fn render_cursor(&self) {
+        let mut out = TermConfig::get_out();
+        let view_top = self.start.0 + Self::BORDER_Y;
+        let view_left = self.start.1 + Self::BORDER_X;
+        let cursor_row = self.get_cursor_row();
+        let mut row = self.viewport_pos;
+        let mut view_row = 0;
+        while row != cursor_row {
+            row = self.get_next_row(row).unwrap();
+            view_row += 1;
+        }
+        // the cursor is kept within the view, so both fit in its size
+        let view_col = (self.cursor_pos.1 % self.get_view_cols()) as u16;
+        out.move_cursor_to(view_top + view_row, view_left + view_col);
+        out.show_cursor();
+        out.flush().unwrap();
+    }
+
+    /// Moves the cursor to the given position, scrolling the view to keep
+    /// it visible. The contents are only rendered again if scrolled.
+    fn set_cursor_pos(&mut self, pos: (usize, usize)) {
+        self.cursor_pos = self.clamp_cursor_pos(pos);
+        let viewport_pos = self.clamp_viewport_pos(self.viewport_pos);
+        if viewport_pos != self.viewport_pos {
+            self.viewport_pos = viewport_pos;
+            self.render_contents();
+        }
+        self.render_cursor();
+    }
+
+    /// Moves the cursor to the given position after the contents changed.
+    fn set_edited_cursor_pos(&mut self, pos: (usize, usize)) {
+        self.is_modified = true;
+        self.cursor_pos = self.clamp_cursor_pos(pos);
+        self.viewport_pos = self.clamp_viewport_pos(self.viewport_pos);
+        self.render_contents();
+        self.render_cursor();
+    }
+
+    /// Returns the byte index of the char at the cursor.
+    fn get_cursor_byte_index(&self) -> usize {
+        let line = &self.lines[self.cursor_pos.0];
+        line.char_indices()
+            .nth(self.cursor_pos.1)
+            .map_or(line.len(), |(index, _)| index)
+    }
+
+    pub fn move_left(&mut self) {
+        let (line_index, char_index) = self.cursor_pos;
+        match (line_index, char_index) {
+            (0, 0) => {}
+            (_, 0) => self.set_cursor_pos((
+                line_index - 1,
+                self.get_line_len(line_index - 1),
+            )),
+            _ => self.set_cursor_pos((line_index, char_index - 1)),
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        let (line_index, char_index) = self.cursor_pos;
+        if char_index < self.get_line_len(line_index) {
+            self.set_cursor_pos((line_index, char_index + 1));
+        } else if line_index + 1 < self.lines.len() {
+            self.set_cursor_pos((line_index + 1, 0));
+        }
+    }
+
+    /// Moves the cursor up by one wrapped row, keeping its column.
+    pub fn move_up(&mut self) {
+        let view_cols = self.get_view_cols();
+        let (line_index, char_index) = self.cursor_pos;
+        if char_index >= view_cols {
+            self.set_cursor_pos((line_index, char_index - view_cols));
+        } else if line_index > 0 {
+            let last_row = self.get_row_count(line_index - 1) - 1;
+            let prev_index = last_row * view_cols + char_index;
+            self.set_cursor_pos((line_index - 1, prev_index));
+        }
+    }
+
+    /// Moves the cursor down by one wrapped row, keeping its column.
+    pub fn move_down(&mut self) {
+        let view_cols = self.get_view_cols();
+        let (line_index, char_index) = self.cursor_pos;
+        let row_index = char_index / view_cols;
+        if row_index + 1 < self.get_row_count(line_index) {
+            self.set_cursor_pos((line_index, char_index + view_cols));
+        } else if line_index + 1 < self.lines.len() {
+            self.set_cursor_pos((line_index + 1, char_index % view_cols));
+        }
     }
 
-    fn clamp_viewport_pos(&self, pos: (u16, u16)) -> (u16, u16) {
-        pos
+    pub fn move_home(&mut self) {
+        self.set_cursor_pos((self.cursor_pos.0, 0));
+    }
+
+    pub fn move_end(&mut self) {
+        let line_index = self.cursor_pos.0;
+        self.set_cursor_pos((line_index, self.get_line_len(line_index)));
+    }
+
+    pub fn move_page_up(&mut self) {
+        (1..self.get_view_size().0).for_each(|_| self.move_up());
+    }
+
+    pub fn move_page_down(&mut self) {
+        (1..self.get_view_size().0).for_each(|_| self.move_down());
+    }
+
+    /// Inserts the char at the cursor, splitting the line on a newline.
+    pub fn insert_char(&mut self, char: char) {
+        let (line_index, char_index) = self.cursor_pos;
+        let byte_index = self.get_cursor_byte_index();
+        let line = &mut self.lines[line_index];
+        if char == '\n' {
+            let rest = line.split_off(byte_index);
+            self.lines.insert(line_index + 1, rest);
+            self.set_edited_cursor_pos((line_index + 1, 0));
+        } else {
+            line.insert(byte_index, char);
+            self.set_edited_cursor_pos((line_index, char_index + 1));
+        }
+    }
+
+    /// Removes the char before the cursor, joining the line with the
+    /// previous one at its start.
+    pub fn remove_prev_char(&mut self) {
+        let (line_index, char_index) = self.cursor_pos;
+        if char_index > 0 {
+            self.move_left();
+            self.remove_next_char();
+        } else if line_index > 0 {
+            let prev_len = self.get_line_len(line_index - 1);
+            let line = self.lines.remove(line_index);
+            self.lines[line_index - 1].push_str(&line);
+            self.set_edited_cursor_pos((line_index - 1, prev_len));
+        }
+    }
+
+    /// Removes the char at the cursor, joining the line with the next one
+    /// at its end.
+    pub fn remove_next_char(&mut self) {
+        let (line_index, char_index) = self.cursor_pos;
+        if char_index < self.get_line_len(line_index) {
+            let byte_index = self.get_cursor_byte_index();
+            self.lines[line_index].remove(byte_index);
+            self.set_edited_cursor_pos(self.cursor_pos);
+        } else if line_index + 1 < self.lines.len() {
+            let next_line = self.lines.remove(line_index + 1);
+            self.lines[line_index].push_str(&next_line);
+            self.set_edited_cursor_pos(self.cursor_pos);
+        }
+    }
+
+    /// Returns the lines joined back into the contents.
+    pub fn get_contents(&self) -> SecretString {
+        let len = self.lines.iter().map(|line| line.len() + 1).sum();
+        let mut contents = SecretString::with_capacity(len);
+        self.lines.iter().enumerate().for_each(|(line_index, line)| {
+            if line_index > 0 {
+                contents.push('\n');
+            }
+            contents.push_str(line);
+        });
+        contents
+    }
+
+    pub fn get_lines(&self) -> &[SecretString] {
+        &self.lines
+    }
+
+    pub fn get_cursor_pos(&self) -> (usize, usize) {
+        self.cursor_pos
+    }
+
+    pub fn get_viewport_pos(&self) -> (usize, usize) {
+        self.viewport_pos
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.is_modified
     }
 
     pub fn sanitize(&mut self) {
         self.cursor_pos = self.clamp_cursor_pos(self.cursor_pos);
         self.viewport_pos = self.clamp_viewport_pos(self.viewport_pos);
     }
+
+    /// Shows the cursor again, after anything else was rendered.
+    pub fn focus(&self) {
+        self.render_cursor();
+    }
+
+    pub fn render(&self) {
+        let mut out = TermConfig::get_out();
+        out.move_cursor_to(self.start.0, self.start.1);
+        out.draw_box(self.size.0, self.size.1);
+        write_title(&mut *out, self.start, self.size, &self.title, 0);
+        drop(out);
+        self.render_contents();
+        self.render_cursor();
+    }
+}
+
+/// Writes the title over the top border of a window, replacing the title
+/// of the given length written before.
+fn write_title<W: Write>(
+    out: &mut W,
+    start: (u16, u16),
+    size: (u16, u16),
+    title: &str,
+    prev_len: usize,
+) {
+    let max_len = size.1.saturating_sub(8) as usize;
+    let title_len = cmp::min(title.chars().count(), max_len);
+    out.move_cursor_to(start.0, start.1 + 3);
+    out.write_str(" ");
+    title.chars().take(max_len).for_each(|char| {
+        out.write_str(char.encode_utf8(&mut [0; 4]));
+    });
+    out.write_str(" ");
+    out.write_str("\u{2500}".repeat(prev_len.saturating_sub(title_len)));
+}
+
+/// Writes the line after skipping the given number of chars, cut off at the
+/// given number of columns. Control chars are written as spaces.
+/// Returns the number of columns written.
+fn write_line_cut<W: Write>(
+    out: &mut W,
+    line: &str,
+    skip: usize,
+    cols: u16,
+) -> u16 {
+    let mut line_len = 0;
+    line.chars()
+        .skip(skip)
+        .take(cols as usize)
+        .for_each(|char| {
+            let char = if char.is_control() { ' ' } else { char };
+            out.write_str(char.encode_utf8(&mut [0; 4]));
+            line_len += 1;
+        });
+    line_len
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TextEditWidget, TextEditWidgetBuilder};
+    use crate::util::secure::SecretString;
+    use once_cell::sync::Lazy;
+    use std::panic;
+    use std::sync::Mutex;
+
+    static TERM_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
+        let lock = TERM_LOCK.lock().unwrap();
+        let result = panic::catch_unwind(test);
+        drop(lock);
+        assert!(result.is_ok())
+    }
+
+    /// Builds an editor, whose view fits 4 rows of 5 chars in its borders.
+    fn build_text_edit(lines: Vec<String>, cursor_pos: (usize, usize))
+        -> TextEditWidget {
+        TextEditWidgetBuilder {
+            start: (1, 1),
+            size: (8, 9),
+            title: String::new(),
+            lines: lines.into_iter().map(SecretString::from).collect(),
+            cursor_pos,
+            viewport_pos: (0, 0),
+            is_modified: false,
+        }.build()
+    }
+
+    fn to_lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn should_step_through_wrapped_rows() {
+        let lines = to_lines(&["abcdefghijkl", "", "abcde"]);
+        let text_edit = build_text_edit(lines, (0, 0));
+        let rows = [(0, 0), (0, 1), (0, 2), (1, 0), (2, 0), (2, 1)];
+        rows.windows(2).for_each(|pair| {
+            assert_eq!(text_edit.get_next_row(pair[0]), Some(pair[1]));
+            assert_eq!(text_edit.get_prev_row(pair[1]), Some(pair[0]));
+        });
+        assert_eq!(text_edit.get_next_row((2, 1)), None);
+        assert_eq!(text_edit.get_prev_row((0, 0)), None);
+    }
+
+    #[test]
+    fn should_clamp_viewport_to_cursor() {
+        let lines = to_lines(&["abcdefghijkl", "a", "b", "c", "d", "e"]);
+        let text_edit = build_text_edit(lines.clone(), (5, 0));
+        assert_eq!(text_edit.clamp_viewport_pos((0, 0)), (2, 0));
+        assert_eq!(text_edit.clamp_viewport_pos((3, 0)), (3, 0));
+        let text_edit = build_text_edit(lines.clone(), (2, 0));
+        assert_eq!(text_edit.clamp_viewport_pos((0, 0)), (0, 1));
+        assert_eq!(text_edit.clamp_viewport_pos((0, 2)), (0, 2));
+        assert_eq!(text_edit.clamp_viewport_pos((4, 0)), (2, 0));
+        let text_edit = build_text_edit(lines, (0, 12));
+        assert_eq!(text_edit.clamp_viewport_pos((3, 0)), (0, 2));
+    }
+
+    #[test]
+    fn should_move_up_and_down_keeping_column() {
+        let lines = to_lines(&["abcdefghijkl", "xy"]);
+        let mut text_edit = build_text_edit(lines, (0, 7));
+        run_test(move || {
+            let moves = [(0, 12), (1, 2), (1, 2)];
+            moves.into_iter().for_each(|pos| {
+                text_edit.move_down();
+                assert_eq!(text_edit.get_cursor_pos(), pos);
+            });
+            let moves = [(0, 12), (0, 7), (0, 2), (0, 2)];
+            moves.into_iter().for_each(|pos| {
+                text_edit.move_up();
+                assert_eq!(text_edit.get_cursor_pos(), pos);
+            });
+        })
+    }
+
+    #[test]
+    fn should_edit_long_and_many_lines() {
+        let lines = vec!["a".to_owned(); 65536];
+        let mut text_edit = build_text_edit(lines, (65535, 1));
+        run_test(move || {
+            assert_eq!(text_edit.get_cursor_pos(), (65535, 1));
+            assert_eq!(text_edit.get_viewport_pos(), (65532, 0));
+            text_edit.insert_char('\n');
+            assert_eq!(text_edit.get_lines().len(), 65537);
+            assert_eq!(text_edit.get_cursor_pos(), (65536, 0));
+        });
+        let lines = vec!["a".repeat(70000)];
+        let mut text_edit = build_text_edit(lines, (0, 70000));
+        run_test(move || {
+            assert_eq!(text_edit.get_viewport_pos(), (0, 13997));
+            text_edit.move_up();
+            assert_eq!(text_edit.get_cursor_pos(), (0, 69995));
+            text_edit.insert_char('b');
+            assert_eq!(&text_edit.get_lines()[0][69995..], "baaaaa");
+        })
+    }
 }
@@ -20,6 +20,94 @@ pub fn find_max_common_index(a: &str, b: &str) -> usize {
         .unwrap_or(max_common_index)
 }
 
+/// Formats the size in bytes with the largest binary unit up to GiB it
+/// reaches, with one decimal place for units above bytes.
+pub fn format_byte_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let (value, unit) = units
+        .iter()
+        .skip(1)
+        .fold((size as f64, units[0]), |(value, unit), next_unit| {
+            match value >= 1024.0 {
+                true => (value / 1024.0, next_unit),
+                false => (value, unit),
+            }
+        });
+    match unit {
+        "B" => format!("{} {}", size, unit),
+        _ => format!("{:.1} {}", value, unit),
+    }
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Checks whether the chars are the same, ignoring case.
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Matches the pattern chars in order against the text, ignoring case.
+/// Of the matches ending earliest, the one starting latest is chosen.
+/// It scores higher for consecutive chars and chars starting a segment,
+/// and lower for the chars skipped before and in between.
+/// Returns the score, along with the indices of the chars matched.
+/// - If the text does not contain the pattern chars, returns `None`.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    if pattern.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let mut pattern_index = 0;
+    let end = text.iter().position(|&char| {
+        if eq_ignore_case(char, pattern[pattern_index]) {
+            pattern_index += 1;
+        }
+        pattern_index == pattern.len()
+    })?;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut pattern_rev = pattern.iter().rev().peekable();
+    for index in (0..=end).rev() {
+        match pattern_rev.peek() {
+            Some(&&char) if eq_ignore_case(text[index], char) => {
+                positions.push(index);
+                pattern_rev.next();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    positions.reverse();
+    let leading_gap = cmp::min(positions[0], 8) as i32;
+    let score = positions
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| {
+            let gap = match i {
+                0 => 0,
+                _ => (index - positions[i - 1] - 1) as i32,
+            };
+            let is_start = index == 0
+                || matches!(text[index - 1], '/' | '-' | '_' | '.' | ' ');
+            let is_consecutive = i > 0 && gap == 0;
+            16 + 10 * is_start as i32 + 8 * is_consecutive as i32 - gap
+        })
+        .sum::<i32>();
+    Some((score - leading_gap, positions))
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -65,4 +153,49 @@ mod test {
             assert_eq!(found, expected, "({}, {})", a, b);
         }
     }
+
+    #[test]
+    fn should_format_byte_size() {
+        for (size, expected) in [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (1024, "1.0 KiB"),
+            (1536, "1.5 KiB"),
+            (5 * 1024 * 1024, "5.0 MiB"),
+        ] {
+            assert_eq!(super::format_byte_size(size), expected);
+        }
+    }
+
+    #[test]
+    fn should_fuzzy_match_chars_in_order() {
+        let (_, positions) = super::fuzzy_match("dfl", "dir1/fil1").unwrap();
+        assert_eq!(positions, [0, 5, 7]);
+        let (_, positions) = super::fuzzy_match("AB", "xaxab").unwrap();
+        assert_eq!(positions, [3, 4]);
+        assert_eq!(super::fuzzy_match("", "abc"), Some((0, Vec::new())));
+        assert_eq!(super::fuzzy_match("ba", "abc"), None);
+    }
+
+    #[test]
+    fn should_rank_tight_fuzzy_matches_higher() {
+        for (pattern, better, worse) in [
+            ("mail", "web/mail", "my/archive/list"),
+            ("gh", "git/hub", "laugh"),
+            ("key", "ssh/key", "ssh/old/my_key"),
+        ] {
+            let better_match = super::fuzzy_match(pattern, better).unwrap();
+            let worse_match = super::fuzzy_match(pattern, worse).unwrap();
+            let message = format!("{} in {}", pattern, better);
+            assert!(better_match.0 > worse_match.0, "{}", message);
+        }
+    }
+
+    #[test]
+    fn should_encode_and_decode_hex() {
+        assert_eq!(super::encode_hex(&[0, 15, 255]), "000fff");
+        assert_eq!(super::decode_hex("000fff"), Some([0, 15, 255].to_vec()));
+        assert_eq!(super::decode_hex("0g"), None);
+        assert_eq!(super::decode_hex("000"), None);
+    }
 }
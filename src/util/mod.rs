@@ -1,6 +1,7 @@
 pub mod algo;
 pub mod ext;
 pub mod pattern;
+pub mod secure;
 pub mod serde;
 pub mod sync;
 pub mod zip;
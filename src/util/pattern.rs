@@ -128,7 +128,7 @@ impl<'a> Pattern<'a> {
             false => full_path.parent().unwrap().to_owned(),
         };
         let filter_fn = |path: &Path| {
-            let path_rel = path.strip_prefix(&working_dir).unwrap();
+            let path_rel = path.strip_prefix(working_dir).unwrap();
             let path_rel_str = path_rel.to_path_str();
             let is_match = path_rel_str.starts_with(prefix)
                 || (path.is_dir() && prefix.starts_with(path_rel_str));
@@ -243,11 +243,11 @@ mod test {
     use std::sync::Mutex;
     use super::{Pattern, PatternFilter};
 
-    const GLOB_DIR: &'static str = "glob-test-dir";
+    const GLOB_DIR: &str = "glob-test-dir";
     static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-    fn run_test<T>(test: T) -> ()
-    where T: FnOnce() -> () + panic::UnwindSafe {
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
         let lock = DIR_LOCK.lock().unwrap();
         let root_dir = Path::new(GLOB_DIR);
         let sub_dir = root_dir.join("sub");
@@ -255,7 +255,7 @@ mod test {
         fs::write(root_dir.join("f1"), "content f1").unwrap();
         fs::write(root_dir.join("f2"), "content f2").unwrap();
         fs::write(sub_dir.join("f3"), "content f3").unwrap();
-        let result = panic::catch_unwind(|| test());
+        let result = panic::catch_unwind(test);
         fs::remove_dir_all(root_dir).unwrap_or_default();
         drop(lock);
         assert!(result.is_ok())
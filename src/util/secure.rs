@@ -0,0 +1,361 @@
+#[cfg(unix)]
+use once_cell::sync::Lazy;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::sync::Mutex;
+use zeroize::Zeroize;
+
+/// Size of the memory pages, which is the granularity of locking.
+#[cfg(unix)]
+static PAGE_SIZE: Lazy<usize> =
+    Lazy::new(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize);
+
+/// Number of live buffers on each locked page, keyed by the page address.
+/// Small buffers may share a page, so a page is only unlocked once none of
+/// the buffers on it is alive.
+#[cfg(unix)]
+static LOCKED_PAGES: Lazy<Mutex<HashMap<usize, usize>>> =
+    Lazy::new(Default::default);
+
+/// Returns the addresses of all the pages the memory of the buffer spans.
+#[cfg(unix)]
+fn get_pages(buf: &Vec<u8>) -> impl Iterator<Item = usize> {
+    let start = buf.as_ptr() as usize;
+    let first_page = start - start % *PAGE_SIZE;
+    (first_page..start + buf.capacity()).step_by(*PAGE_SIZE)
+}
+
+/// Locks the memory of the buffer, so that it is never swapped out.
+/// Locking fails past the limit of locked memory, in which case the buffer
+/// is still wiped on drop, only not kept out of swap.
+#[cfg(unix)]
+fn lock(buf: &Vec<u8>) {
+    if buf.capacity() > 0 {
+        let mut locked_pages = LOCKED_PAGES.lock().unwrap();
+        for page in get_pages(buf) {
+            let count = locked_pages.entry(page).or_insert(0);
+            if *count == 0 {
+                let ptr = page as *const libc::c_void;
+                unsafe { libc::mlock(ptr, *PAGE_SIZE) };
+            }
+            *count += 1;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_buf: &Vec<u8>) {}
+
+/// Wipes the whole memory of the buffer and unlocks the pages which no
+/// other buffer is on.
+#[cfg(unix)]
+fn wipe(mut buf: Vec<u8>) {
+    buf.zeroize();
+    if buf.capacity() > 0 {
+        let mut locked_pages = LOCKED_PAGES.lock().unwrap();
+        for page in get_pages(&buf) {
+            let count = locked_pages.get_mut(&page).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                locked_pages.remove(&page);
+                let ptr = page as *const libc::c_void;
+                unsafe { libc::munlock(ptr, *PAGE_SIZE) };
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wipe(mut buf: Vec<u8>) {
+    buf.zeroize();
+}
+
+/// Bytes of a secret, such as a password or decrypted contents.
+/// The memory is locked where possible, and wiped on drop as well as
+/// whenever the buffer grows, so that no copy is left behind.
+/// It does not implement `Debug`, so that it is never printed by accident.
+pub struct SecretBytes {
+    inner: Vec<u8>,
+}
+
+impl SecretBytes {
+    #[inline]
+    pub fn new() -> Self {
+        Self { inner: Vec::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let inner = Vec::with_capacity(capacity);
+        lock(&inner);
+        Self { inner }
+    }
+
+    /// Creates a buffer of the given length filled with zeroes.
+    pub fn zeroed(len: usize) -> Self {
+        let mut bytes = Self::with_capacity(len);
+        bytes.inner.resize(len, 0);
+        bytes
+    }
+
+    /// Makes room for the additional bytes, moving the contents into a new
+    /// locked buffer and wiping the old one if it is too small.
+    fn reserve(&mut self, additional: usize) {
+        let len = self.inner.len() + additional;
+        if len > self.inner.capacity() {
+            let capacity = len.max(self.inner.capacity() * 2);
+            let mut inner = Vec::with_capacity(capacity);
+            lock(&inner);
+            inner.extend_from_slice(&self.inner);
+            wipe(mem::replace(&mut self.inner, inner));
+        }
+    }
+
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.reserve(data.len());
+        self.inner.extend_from_slice(data);
+    }
+
+    /// Inserts the bytes at the given index, shifting the rest after them.
+    pub fn insert_from_slice(&mut self, index: usize, data: &[u8]) {
+        self.reserve(data.len());
+        self.inner.splice(index..index, data.iter().copied());
+    }
+
+    /// Removes the bytes in the given range, shifting the rest before them.
+    /// The bytes left past the new length are wiped.
+    pub fn remove_range(&mut self, start: usize, end: usize) {
+        let len = self.inner.len();
+        self.inner.copy_within(end..len, start);
+        self.truncate(len - (end - start));
+    }
+
+    /// Splits the buffer at the given index, returning the bytes after it.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        let tail = Self::from(&self.inner[index..]);
+        self.truncate(index);
+        tail
+    }
+
+    /// Shortens the buffer, wiping the bytes past the given length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.inner.len() {
+            self.inner[len..].zeroize();
+            self.inner.truncate(len);
+        }
+    }
+}
+
+impl Clone for SecretBytes {
+    fn clone(&self) -> Self {
+        Self::from(self.inner.as_slice())
+    }
+}
+
+impl Default for SecretBytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(inner: Vec<u8>) -> Self {
+        lock(&inner);
+        Self { inner }
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(data: &[u8]) -> Self {
+        let mut bytes = Self::with_capacity(data.len());
+        bytes.inner.extend_from_slice(data);
+        bytes
+    }
+}
+
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.inner
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.inner
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.inner
+    }
+}
+
+impl Write for SecretBytes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        wipe(mem::take(&mut self.inner));
+    }
+}
+
+/// String holding a secret, such as a password or decrypted contents.
+/// The memory is handled same as `SecretBytes`, and it implements neither
+/// `Debug` nor `Display`, so that it is never printed by accident.
+#[derive(Clone, Default)]
+pub struct SecretString {
+    bytes: SecretBytes,
+}
+
+impl SecretString {
+    #[inline]
+    pub fn new() -> Self {
+        Self { bytes: SecretBytes::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { bytes: SecretBytes::with_capacity(capacity) }
+    }
+
+    /// Converts the bytes into a string.
+    /// - If the bytes are not valid utf-8, returns `None`.
+    pub fn from_utf8(bytes: SecretBytes) -> Option<Self> {
+        match std::str::from_utf8(&bytes) {
+            Ok(_) => Some(Self { bytes }),
+            Err(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only valid utf-8 is ever written into the bytes
+        unsafe { std::str::from_utf8_unchecked(&self.bytes) }
+    }
+
+    pub fn push_str(&mut self, string: &str) {
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    pub fn push(&mut self, char: char) {
+        self.push_str(char.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Inserts the char at the given byte index.
+    pub fn insert(&mut self, index: usize, char: char) {
+        assert!(self.as_str().is_char_boundary(index));
+        let mut bytes = [0; 4];
+        let bytes = char.encode_utf8(&mut bytes).as_bytes();
+        self.bytes.insert_from_slice(index, bytes);
+    }
+
+    /// Removes the char at the given byte index.
+    pub fn remove(&mut self, index: usize) -> char {
+        let char = self.as_str()[index..].chars().next().unwrap();
+        self.bytes.remove_range(index, index + char.len_utf8());
+        char
+    }
+
+    /// Splits the string at the given byte index, returning the rest.
+    pub fn split_off(&mut self, index: usize) -> Self {
+        assert!(self.as_str().is_char_boundary(index));
+        Self { bytes: self.bytes.split_off(index) }
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let char = self.as_str().chars().next_back()?;
+        self.bytes.truncate(self.bytes.len() - char.len_utf8());
+        Some(char)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(string: String) -> Self {
+        Self { bytes: SecretBytes::from(string.into_bytes()) }
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(string: &str) -> Self {
+        Self { bytes: SecretBytes::from(string.as_bytes()) }
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SecretBytes, SecretString};
+    use std::io::Write;
+
+    #[test]
+    fn should_keep_contents_while_growing() {
+        let mut bytes = SecretBytes::with_capacity(2);
+        bytes.write_all(b"secret").unwrap();
+        bytes.extend_from_slice(b" value");
+        assert_eq!(&*bytes, b"secret value");
+        bytes.truncate(6);
+        assert_eq!(&*bytes, b"secret");
+    }
+
+    #[test]
+    fn should_push_and_pop_chars() {
+        let mut string = SecretString::new();
+        "pässwörd".chars().for_each(|char| string.push(char));
+        assert_eq!(string.as_str(), "pässwörd");
+        assert_eq!(string.pop(), Some('d'));
+        assert_eq!(string.pop(), Some('r'));
+        assert_eq!(string.pop(), Some('ö'));
+        assert_eq!(string.as_str(), "pässw");
+        assert!(SecretString::new().pop().is_none());
+    }
+
+    #[test]
+    fn should_insert_remove_and_split_chars() {
+        let mut string = SecretString::from("pässwörd");
+        string.insert(0, 'ö');
+        string.insert(3, '-');
+        assert_eq!(string.as_str(), "öp-ässwörd");
+        assert_eq!(string.remove(4), 'ä');
+        let tail = string.split_off(7);
+        assert_eq!(string.as_str(), "öp-ssw");
+        assert_eq!(tail.as_str(), "örd");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_keep_shared_pages_locked() {
+        let first = SecretBytes::from(b"first".as_slice());
+        let second = SecretBytes::from(b"second".as_slice());
+        let pages = super::get_pages(&second.inner).collect::<Vec<_>>();
+        drop(first);
+        let locked_pages = super::LOCKED_PAGES.lock().unwrap();
+        assert!(pages.iter().all(|page| locked_pages.contains_key(page)));
+        drop(locked_pages);
+        drop(second);
+    }
+
+    #[test]
+    fn should_only_convert_utf8_bytes() {
+        let bytes = SecretBytes::from(b"secret".as_slice());
+        assert_eq!(SecretString::from_utf8(bytes).unwrap().as_str(), "secret");
+        let bytes = SecretBytes::from([0xff, 0xfe].as_slice());
+        assert!(SecretString::from_utf8(bytes).is_none());
+    }
+}
@@ -23,9 +23,9 @@ impl Serialize for &HashMap<String, u32> {
         let approx_size = self.len() * 10;
         let mut result = String::with_capacity(approx_size);
         result.push('{');
-        self.into_iter().for_each(|(key, value)| {
+        self.iter().for_each(|(key, value)| {
             result.push('"');
-            result.push_str(&key);
+            result.push_str(key);
             result.push('"');
             result.push(':');
             result.push_str(&value.to_string());
@@ -39,6 +39,21 @@ impl Serialize for &HashMap<String, u32> {
     }
 }
 
+impl Serialize for &Vec<String> {
+    fn serialize(self) -> String {
+        let items = self
+            .iter()
+            .map(|item| {
+                let item_escaped = item
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"");
+                format!("\"{}\"", item_escaped)
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", items.join(","))
+    }
+}
+
 pub trait Deserialize {
     fn deserialize(val: &str) -> Option<Self>
     where Self: Sized;
@@ -93,6 +108,12 @@ mod test {
         assert_eq!(value.serialize(), "{}")
     }
 
+    #[test]
+    fn should_serialize_string_vec_escaped() {
+        let value = ["a\"b".to_owned(), "c".to_owned()].to_vec();
+        assert_eq!(value.serialize(), "[\"a\\\"b\",\"c\"]")
+    }
+
     #[test]
     fn should_deserialize_empty_hashmap() {
         let value = HashMap::new();
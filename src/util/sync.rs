@@ -15,7 +15,7 @@ impl<T> SingleLock<T> {
         }
     }
 
-    pub fn lock(&self) -> SingleLockGuard<T> {
+    pub fn lock(&self) -> SingleLockGuard<'_, T> {
         let order = Ordering::Relaxed;
         if self.lock.compare_exchange(false, true, order, order).is_err() {
             panic!("did not expect anyone to hold on to this lock")
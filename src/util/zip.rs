@@ -1,22 +1,117 @@
+use crate::crypto::{self, CryptoResult};
 use super::ext::PathExt;
+use super::secure::{SecretBytes, SecretString};
 use std::fs;
-use std::io::Write;
-use std::path::Path;
-use zip::ZipWriter;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 use zip::write::FileOptions;
 
+/// Magic bytes at the start of encrypted archives.
+const ENC_MAGIC: &[u8; 4] = b"VZIP";
+
+/// Checks whether the archive at the given path is encrypted.
+/// - If the file cannot be read, returns `false`.
+pub fn is_encrypted<P: AsRef<Path>>(archive_path: P) -> bool {
+    let mut magic = [0; 4];
+    match fs::File::open(archive_path) {
+        Ok(mut file) => match file.read_exact(&mut magic) {
+            Ok(_) => &magic == ENC_MAGIC,
+            Err(_) => false,
+        }
+        Err(_) => false,
+    }
+}
+
+/// Destination of the archive, which is written to the file directly unless
+/// it has to be encrypted as a whole first.
+enum ZipSink {
+    File(fs::File),
+    Buffer(Cursor<Vec<u8>>),
+}
+
+impl Write for ZipSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Buffer(buffer) => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Buffer(buffer) => buffer.flush(),
+        }
+    }
+}
+
+impl Seek for ZipSink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Buffer(buffer) => buffer.seek(pos),
+        }
+    }
+}
+
+/// Source of the archive, which is read from the file directly unless it
+/// has to be decrypted as a whole first.
+enum ZipSource {
+    File(BufReader<fs::File>),
+    Buffer(Cursor<SecretBytes>),
+}
+
+impl Read for ZipSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::Buffer(buffer) => buffer.read(buf),
+        }
+    }
+}
+
+impl Seek for ZipSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::Buffer(buffer) => buffer.seek(pos),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ZipperOptions {
+    /// Whether the archived files are compressed using deflate.
+    pub compress: bool,
+    /// Password used to encrypt the whole archive, if any.
+    pub pass: Option<SecretString>,
+}
+
 pub struct Zipper {
+    path: PathBuf,
+    pass: Option<SecretString>,
     options: FileOptions,
-    inner: ZipWriter<fs::File>,
+    inner: ZipWriter<ZipSink>,
     matches: Vec<String>,
 }
 
 impl Zipper {
-    pub fn new<P: AsRef<Path>>(archive_path: P) -> Self {
-        let archive = fs::File::create(archive_path).unwrap();
+    pub fn new<P>(archive_path: P, options: ZipperOptions) -> Self
+    where P: AsRef<Path> {
+        let method = match options.compress {
+            true => CompressionMethod::Deflated,
+            false => CompressionMethod::Stored,
+        };
+        let sink = match options.pass {
+            Some(_) => ZipSink::Buffer(Cursor::new(Vec::new())),
+            None => ZipSink::File(fs::File::create(&archive_path).unwrap()),
+        };
         Self {
-            options: FileOptions::default(),
-            inner: ZipWriter::new(archive),
+            path: archive_path.as_ref().to_owned(),
+            pass: options.pass,
+            options: FileOptions::default().compression_method(method),
+            inner: ZipWriter::new(sink),
             matches: Vec::new(),
         }
     }
@@ -33,6 +128,13 @@ impl Zipper {
         }
     }
 
+    pub fn zip_contents<P: AsRef<Path>>(&mut self, path: P, contents: &[u8]) {
+        let path_str = path.to_path_str();
+        self.inner.start_file(path_str, self.options).unwrap();
+        self.inner.write_all(contents).unwrap();
+        self.matches.push(path_str.to_owned());
+    }
+
     pub fn zip_dir<P: AsRef<Path>>(&mut self, path: P) -> bool {
         if let Ok(dir_entries) = fs::read_dir(&path) {
             let dir_path_str = path.to_path_str();
@@ -51,29 +153,110 @@ impl Zipper {
         }
     }
 
-    pub fn finish(mut self) -> Vec<String> {
-        self.inner.finish().unwrap();
-        self.matches
+    /// Writes the archive, encrypting it with a key derived from the password
+    /// first if there is one. Returns the paths of the archived files.
+    /// - If the password is empty, returns `IncorrectPassword`.
+    pub fn finish(mut self) -> CryptoResult<Vec<String>> {
+        let sink = self.inner.finish().unwrap();
+        let (contents, pass) = match (sink, self.pass) {
+            (ZipSink::Buffer(buffer), Some(pass)) =>
+                (buffer.into_inner(), pass),
+            _ => return Ok(self.matches),
+        };
+        let contents_enc = crypto::encrypt_derived(&contents, &pass)?;
+        let contents_enc = [ENC_MAGIC.as_slice(), &contents_enc].concat();
+        fs::write(self.path, contents_enc).unwrap();
+        Ok(self.matches)
+    }
+}
+
+pub struct Unzipper {
+    inner: ZipArchive<ZipSource>,
+}
+
+impl Unzipper {
+    /// Opens the archive at the given path for reading.
+    /// - If the file cannot be read or is not a valid archive, returns `None`.
+    pub fn new<P: AsRef<Path>>(archive_path: P) -> Option<Self> {
+        let file = BufReader::new(fs::File::open(archive_path).ok()?);
+        let inner = ZipArchive::new(ZipSource::File(file)).ok()?;
+        Some(Self { inner })
+    }
+
+    /// Opens the encrypted archive at the given path for reading.
+    /// Archives which are not encrypted are opened as is.
+    /// - If the password does not match, returns `None`.
+    pub fn with_password<P>(
+        archive_path: P,
+        pass: &SecretString,
+    ) -> Option<Self>
+    where P: AsRef<Path> {
+        if !is_encrypted(&archive_path) {
+            return Self::new(archive_path);
+        }
+        let contents_enc = fs::read(archive_path).ok()?;
+        let contents_enc = &contents_enc[ENC_MAGIC.len()..];
+        let contents = crypto::decrypt_derived(contents_enc, pass).ok()?;
+        let source = ZipSource::Buffer(Cursor::new(contents));
+        let inner = ZipArchive::new(source).ok()?;
+        Some(Self { inner })
+    }
+
+    /// Reads the contents of the archived file at the given path.
+    /// - If the file does not exist in the archive, returns `None`.
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) -> Option<Vec<u8>> {
+        let mut entry = self.inner.by_name(path.to_path_str()).ok()?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).ok()?;
+        Some(contents)
+    }
+
+    /// Extracts all the files within the archived directory at the given path
+    /// into the dest directory, stripping the archived directory prefix.
+    /// Returns the extracted file paths relative to the dest directory.
+    /// - Entries pointing outside the archived directory are skipped.
+    pub fn unzip_dir<P, Q>(&mut self, path: P, dest: Q) -> Vec<String>
+    where P: AsRef<Path>, Q: AsRef<Path> {
+        let mut matches = Vec::new();
+        for i in 0..self.inner.len() {
+            let mut entry = self.inner.by_index(i).unwrap();
+            let rel_path = match entry.enclosed_name() {
+                Some(name) => match name.strip_prefix(&path) {
+                    Ok(rel) if !entry.is_dir() => rel.to_path_buf(),
+                    _ => continue,
+                }
+                None => continue,
+            };
+            let dest_path = dest.as_ref().join(&rel_path);
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            let mut dest_file = fs::File::create(&dest_path).unwrap();
+            io::copy(&mut entry, &mut dest_file).unwrap();
+            matches.push(rel_path.to_path_str().to_owned());
+        }
+        matches
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::super::ext::PathExt;
+    use super::super::secure::SecretString;
     use once_cell::sync::Lazy;
     use std::fs;
     use std::panic;
     use std::path::Path;
     use std::sync::Mutex;
 
-    const ZIP_DIR: &'static str = "zip-test-dir";
+    const ZIP_DIR: &str = "zip-test-dir";
     static DIR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
-    fn run_test<T>(test: T) -> ()
-    where T: FnOnce() -> () + panic::UnwindSafe {
+    fn run_test<T>(test: T)
+    where T: FnOnce() + panic::UnwindSafe {
         let lock = DIR_LOCK.lock().unwrap();
         fs::create_dir_all(ZIP_DIR).unwrap();
-        let result = panic::catch_unwind(|| test());
+        let result = panic::catch_unwind(test);
         fs::remove_dir_all(ZIP_DIR).unwrap();
         drop(lock);
         assert!(result.is_ok())
@@ -86,12 +269,108 @@ mod test {
         let final_file = "final.zip";
         run_test(|| {
             fs::write(&file_path, "contents").unwrap();
-            let mut zipper = super::Zipper::new(final_file);
-            assert_eq!(zipper.zip_dir(zip_dir), true);
-            let matches = zipper.finish();
+            let options = Default::default();
+            let mut zipper = super::Zipper::new(final_file, options);
+            assert!(zipper.zip_dir(zip_dir));
+            let matches = zipper.finish().unwrap();
             assert_eq!(matches, [file_path.to_path_str()]);
             assert!(fs::read(final_file).is_ok());
             fs::remove_file(final_file).unwrap();
         })
     }
+
+    #[test]
+    fn should_unzip_dir() {
+        let zip_dir = Path::new(ZIP_DIR);
+        let src_dir = zip_dir.join("src");
+        let dest_dir = zip_dir.join("dest");
+        let final_file = zip_dir.join("final.zip");
+        run_test(|| {
+            fs::create_dir_all(&src_dir).unwrap();
+            fs::write(src_dir.join("file1"), "contents").unwrap();
+            let options = Default::default();
+            let mut zipper = super::Zipper::new(&final_file, options);
+            assert!(zipper.zip_dir(&src_dir));
+            zipper.finish().unwrap();
+            let mut unzipper = super::Unzipper::new(&final_file).unwrap();
+            let matches = unzipper.unzip_dir(&src_dir, &dest_dir);
+            assert_eq!(matches, ["file1"]);
+            let contents = fs::read_to_string(dest_dir.join("file1")).unwrap();
+            assert_eq!(contents, "contents");
+        })
+    }
+
+    #[test]
+    fn should_read_zipped_contents() {
+        let final_file = Path::new(ZIP_DIR).join("final.zip");
+        run_test(|| {
+            let options = Default::default();
+            let mut zipper = super::Zipper::new(&final_file, options);
+            zipper.zip_contents("dir/file1", b"contents");
+            zipper.finish().unwrap();
+            let mut unzipper = super::Unzipper::new(&final_file).unwrap();
+            let contents = unzipper.read_file("dir/file1");
+            assert_eq!(contents, Some(b"contents".to_vec()));
+            assert_eq!(unzipper.read_file("dir/file2"), None);
+        })
+    }
+
+    #[test]
+    fn should_zip_and_unzip_encrypted_compressed() {
+        let pass = &SecretString::from("1234");
+        let final_file = Path::new(ZIP_DIR).join("final.zip");
+        let options = super::ZipperOptions {
+            compress: true,
+            pass: Some(SecretString::from("1234")),
+        };
+        run_test(|| {
+            let mut zipper = super::Zipper::new(&final_file, options);
+            zipper.zip_contents("file1", &[b'a'; 100]);
+            zipper.finish().unwrap();
+            assert!(super::is_encrypted(&final_file));
+            assert!(super::Unzipper::new(&final_file).is_none());
+            let wrong_pass = &SecretString::from("1");
+            let unzipper =
+                super::Unzipper::with_password(&final_file, wrong_pass);
+            assert!(unzipper.is_none());
+            let mut unzipper =
+                super::Unzipper::with_password(&final_file, pass).unwrap();
+            let contents = unzipper.read_file("file1");
+            assert_eq!(contents, Some([b'a'; 100].to_vec()));
+        })
+    }
+
+    #[test]
+    fn should_zip_and_unzip_with_long_password() {
+        let final_file = Path::new(ZIP_DIR).join("final.zip");
+        let pass = SecretString::from(
+            "a backup passphrase longer than thirty-two characters",
+        );
+        let options = super::ZipperOptions {
+            compress: false,
+            pass: Some(pass.clone()),
+        };
+        run_test(|| {
+            let mut zipper = super::Zipper::new(&final_file, options);
+            zipper.zip_contents("file1", b"contents");
+            zipper.finish().unwrap();
+            let short_pass = &SecretString::from(&pass[..32]);
+            let unzipper =
+                super::Unzipper::with_password(&final_file, short_pass);
+            assert!(unzipper.is_none());
+            let mut unzipper =
+                super::Unzipper::with_password(&final_file, &pass).unwrap();
+            let contents = unzipper.read_file("file1");
+            assert_eq!(contents, Some(b"contents".to_vec()));
+        })
+    }
+
+    #[test]
+    fn should_not_open_invalid_archive() {
+        let file_path = Path::new(ZIP_DIR).join("invalid.zip");
+        run_test(|| {
+            fs::write(&file_path, "contents").unwrap();
+            assert!(super::Unzipper::new(&file_path).is_none());
+        })
+    }
 }